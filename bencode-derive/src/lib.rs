@@ -0,0 +1,192 @@
+//! Derive macros for the `bencode` crate.
+//!
+//! Use them through `bencode` with the `derive` feature enabled, the generated code refers to
+//! `::bencode::...` paths.
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    Data, DeriveInput, Fields, GenericParam, Lifetime, LifetimeParam, LitStr, Path,
+    parse_macro_input, spanned::Spanned,
+};
+
+/// Derives `bencode::BencodeDecode` for a struct with named fields, decoding it from a dict.
+///
+/// Field attributes:
+/// - `#[bencode(rename = "piece length")]`: use a different dict key than the field name
+/// - `#[bencode(default)]`: use `Default::default()` if the key is missing
+/// - `#[bencode(default = "path::to::fn")]`: call `fn()` if the key is missing
+/// - `#[bencode(raw)]`: capture the raw bencoded bytes of the value (field has to be `&[u8]`)
+///
+/// Fields of type `Option<T>` are optional, every other field without a default is mandatory
+/// and reported as `ErrorKind::MissingField` if absent. Unknown keys are skipped.
+/// Errors in a field's value get the field's key added to their key path.
+///
+/// The struct may have at most one lifetime, which the decoded data borrows from.
+#[proc_macro_derive(BencodeDecode, attributes(bencode))]
+pub fn derive_bencode_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_decode(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+enum DefaultKind {
+    None,
+    Trait,
+    Fn(Path),
+}
+
+struct FieldAttrs {
+    key: String,
+    default: DefaultKind,
+    raw: bool,
+}
+
+fn parse_field_attrs(field: &syn::Field) -> syn::Result<FieldAttrs> {
+    let ident = field
+        .ident
+        .as_ref()
+        .expect("only named fields are supported");
+    let mut attrs = FieldAttrs {
+        key: ident.to_string(),
+        default: DefaultKind::None,
+        raw: false,
+    };
+
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("bencode")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                attrs.key = meta.value()?.parse::<LitStr>()?.value();
+            } else if meta.path.is_ident("default") {
+                attrs.default = if meta.input.peek(syn::Token![=]) {
+                    DefaultKind::Fn(meta.value()?.parse::<LitStr>()?.parse()?)
+                } else {
+                    DefaultKind::Trait
+                };
+            } else if meta.path.is_ident("raw") {
+                attrs.raw = true;
+            } else {
+                return Err(meta.error("expected `rename`, `default` or `raw`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(attrs)
+}
+
+fn expand_decode(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = input.ident.clone();
+    let generics = input.generics.clone();
+    let (_, ty_generics, where_clause) = generics.split_for_impl();
+
+    // The decoded struct borrows from the input, so its lifetime becomes the one of
+    // `BencodeDecode<'de>`. Structs without a lifetime get a fresh `'__de`.
+    let lifetimes: Vec<_> = input.generics.lifetimes().collect();
+    let de = match lifetimes.as_slice() {
+        [] => {
+            let de = Lifetime::new("'__de", Span::call_site());
+            input
+                .generics
+                .params
+                .insert(0, GenericParam::Lifetime(LifetimeParam::new(de.clone())));
+            de
+        }
+        [lt] => lt.lifetime.clone(),
+        [_, second, ..] => {
+            return Err(syn::Error::new(
+                second.span(),
+                "BencodeDecode supports at most one lifetime",
+            ));
+        }
+    };
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    input.span(),
+                    "BencodeDecode can only be derived for structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.span(),
+                "BencodeDecode can only be derived for structs",
+            ));
+        }
+    };
+
+    let mut slots = Vec::new();
+    let mut arms = Vec::new();
+    let mut inits = Vec::new();
+
+    for (i, field) in fields.iter().enumerate() {
+        let ident = field.ident.as_ref().expect("named field");
+        let ty = &field.ty;
+        let attrs = parse_field_attrs(field)?;
+        let slot = syn::Ident::new(&format!("__field{}", i), Span::call_site());
+        let key = &attrs.key;
+        let key_bytes = syn::LitByteStr::new(key.as_bytes(), Span::call_site());
+
+        slots.push(quote! {
+            let mut #slot: ::core::option::Option<#ty> = ::core::option::Option::None;
+        });
+
+        let decode = if attrs.raw {
+            quote! { p.parse_raw_value() }
+        } else {
+            quote! { <#ty as ::bencode::BencodeDecode<#de>>::decode(p) }
+        };
+        arms.push(quote! {
+            #key_bytes => {
+                #slot = ::core::option::Option::Some(#decode.map_err(|e| e.in_field(#key))?);
+            }
+        });
+
+        let missing = match &attrs.default {
+            DefaultKind::None => quote! {
+                <#ty as ::bencode::BencodeDecode<#de>>::missing().ok_or(
+                    ::bencode::Error::new(::bencode::ErrorKind::MissingField(#key), __dict_start),
+                )?
+            },
+            DefaultKind::Trait => quote! { ::core::default::Default::default() },
+            DefaultKind::Fn(path) => quote! { #path() },
+        };
+        inits.push(quote! {
+            #ident: match #slot {
+                ::core::option::Option::Some(v) => v,
+                ::core::option::Option::None => #missing,
+            }
+        });
+    }
+
+    let (impl_generics, _, _) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::bencode::BencodeDecode<#de> for #name #ty_generics #where_clause {
+            fn decode(p: &mut ::bencode::BencodeParser<#de>) -> ::bencode::Result<Self> {
+                #(#slots)*
+
+                // missing fields are reported at the start of the dict
+                let __dict_start = p.offset();
+                p.expect_dict_start()?;
+
+                while !p.match_dict_end() {
+                    let key = p.parse_bytes()?;
+                    match key {
+                        #(#arms)*
+                        _ => p.skip_any()?,
+                    }
+                }
+
+                ::core::result::Result::Ok(Self {
+                    #(#inits,)*
+                })
+            }
+        }
+    })
+}
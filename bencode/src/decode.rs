@@ -0,0 +1,291 @@
+//! Typed decoding on top of [`BencodeParser`].
+//!
+//! Structs usually get their implementation through `#[derive(BencodeDecode)]`
+//! (enable the `derive` feature), which generates the `while !p.match_dict_end()` loop
+//! that used to be written by hand for every dict.
+use crate::{BencodeParser, Dict, Error, ErrorKind, List, Result, Value};
+
+/// A type that can be decoded from the next element of a `BencodeParser`,
+/// borrowing from the input with the lifetime `'a`.
+pub trait BencodeDecode<'a>: Sized {
+    /// Consume the next element of `p` and decode it.
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self>;
+
+    /// The value to use if a dict field of this type is missing.
+    /// `None` makes the field mandatory, which is the default.
+    #[inline]
+    fn missing() -> Option<Self> {
+        None
+    }
+
+    /// Decode `input`, which has to contain exactly one bencoded value.
+    fn decode_from(input: &'a [u8]) -> Result<Self> {
+        let mut p = BencodeParser::new(input);
+        let value = Self::decode(&mut p)?;
+        let end = p.offset();
+        if !p.remaining().is_empty() {
+            return Err(Error::new(ErrorKind::InvalidSyntax, end));
+        }
+        Ok(value)
+    }
+}
+
+impl<'a, T: BencodeDecode<'a>> BencodeDecode<'a> for Option<T> {
+    #[inline]
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        T::decode(p).map(Some)
+    }
+
+    #[inline]
+    fn missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+impl<'a> BencodeDecode<'a> for i64 {
+    #[inline]
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        p.parse_int()
+    }
+}
+
+/// Integers are range checked instead of silently truncated.
+macro_rules! impl_decode_int {
+    ($($t:ty),*) => {$(
+        impl<'a> BencodeDecode<'a> for $t {
+            #[inline]
+            fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+                let start = p.offset();
+                <$t>::try_from(p.parse_int()?)
+                    .map_err(|_| Error::new(ErrorKind::OutOfRange, start))
+            }
+        }
+    )*};
+}
+
+impl_decode_int!(u8, u16, u32, u64, usize, i8, i16, i32);
+
+impl<'a> BencodeDecode<'a> for &'a [u8] {
+    #[inline]
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        p.parse_bytes()
+    }
+}
+
+impl<'a> BencodeDecode<'a> for &'a str {
+    #[inline]
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        p.parse_str()
+    }
+}
+
+/// A byte string made up of fixed-size chunks, e.g. the SHA-1 hashes in `pieces`.
+/// Fails with `InvalidSyntax` if the length isn't a multiple of `N`.
+impl<'a, const N: usize> BencodeDecode<'a> for &'a [[u8; N]] {
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        let start = p.offset();
+        let (chunks, rest) = p.parse_bytes()?.as_chunks::<N>();
+        if !rest.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidSyntax, start));
+        }
+        Ok(chunks)
+    }
+}
+
+/// A byte string of exactly `N` bytes, e.g. a single hash.
+impl<'a, const N: usize> BencodeDecode<'a> for &'a [u8; N] {
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        let start = p.offset();
+        p.parse_bytes()?
+            .try_into()
+            .map_err(|_| Error::new(ErrorKind::InvalidSyntax, start))
+    }
+}
+
+impl<'a> BencodeDecode<'a> for Value<'a> {
+    #[inline]
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        Value::from_parser(p)
+    }
+}
+
+impl<'a> BencodeDecode<'a> for List<'a> {
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        if p.peek() != Some(b'l') {
+            return Err(Error::new(ErrorKind::ExpectedList, p.offset()));
+        }
+        Ok(Value::from_parser(p)?.as_list().expect("checked above"))
+    }
+}
+
+impl<'a> BencodeDecode<'a> for Dict<'a> {
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        if p.peek() != Some(b'd') {
+            return Err(Error::new(ErrorKind::ExpectedDict, p.offset()));
+        }
+        Ok(Value::from_parser(p)?.as_dict().expect("checked above"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bencode_derive::BencodeDecode;
+
+    #[derive(BencodeDecode, PartialEq, Debug)]
+    struct FileEntry<'a> {
+        length: u64,
+        #[bencode(rename = "path")]
+        segments: List<'a>,
+        md5sum: Option<&'a str>,
+    }
+
+    #[derive(BencodeDecode, PartialEq, Debug)]
+    struct Owned {
+        interval: u32,
+        #[bencode(default)]
+        complete: u32,
+        #[bencode(default = "default_incomplete")]
+        incomplete: u32,
+    }
+
+    fn default_incomplete() -> u32 {
+        7
+    }
+
+    #[derive(BencodeDecode, Debug)]
+    struct Torrent<'a> {
+        announce: Option<&'a str>,
+        #[bencode(raw)]
+        info: &'a [u8],
+        #[bencode(rename = "piece length")]
+        piece_length: Option<u32>,
+        pieces: Option<&'a [[u8; 4]]>,
+    }
+
+    #[test]
+    fn test_derive_rename_and_optional() {
+        let entry = FileEntry::decode_from(b"d6:lengthi12e4:pathl1:a1:bee").unwrap();
+        assert_eq!(entry.length, 12);
+        assert_eq!(entry.segments.len(), 2);
+        assert_eq!(entry.md5sum, None);
+
+        let entry = FileEntry::decode_from(b"d6:lengthi12e6:md5sum3:abc4:pathlee").unwrap();
+        assert_eq!(entry.md5sum, Some("abc"));
+    }
+
+    #[test]
+    fn test_derive_defaults() {
+        let owned = Owned::decode_from(b"d8:intervali1800ee").unwrap();
+        assert_eq!(
+            owned,
+            Owned {
+                interval: 1800,
+                complete: 0,
+                incomplete: 7
+            }
+        );
+
+        let owned = Owned::decode_from(b"d8:completei3e10:incompletei4e8:intervali1ee").unwrap();
+        assert_eq!(owned.complete, 3);
+        assert_eq!(owned.incomplete, 4);
+    }
+
+    #[test]
+    fn test_derive_raw_and_skips_unknown() {
+        let input = b"d8:announce3:url7:comment3:foo4:infod1:xi1ee6:pieces8:aaaabbbbe";
+        let t = Torrent::decode_from(input).unwrap();
+        assert_eq!(t.announce, Some("url"));
+        assert_eq!(t.info, b"d1:xi1ee");
+        assert_eq!(t.piece_length, None);
+        assert_eq!(t.pieces, Some(&[*b"aaaa", *b"bbbb"][..]));
+    }
+
+    #[test]
+    fn test_derive_missing_field() {
+        assert!(matches!(
+            FileEntry::decode_from(b"d4:pathlee").map_err(|e| e.kind()),
+            Err(ErrorKind::MissingField("length"))
+        ));
+        assert!(matches!(
+            Owned::decode_from(b"de").map_err(|e| e.kind()),
+            Err(ErrorKind::MissingField("interval"))
+        ));
+    }
+
+    #[test]
+    fn test_derive_mistyped_field() {
+        // wrong type
+        let e = FileEntry::decode_from(b"d6:length3:abc4:pathlee").unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::ExpectedInteger));
+        assert_eq!(e.offset(), 9);
+        assert!(e.path().eq(["length"]));
+
+        // doesn't fit into the field's type
+        let e = Owned::decode_from(b"d8:intervali-1ee").unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::OutOfRange));
+        assert!(e.path().eq(["interval"]));
+        let e = Owned::decode_from(b"d8:intervali4294967296ee").unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::OutOfRange));
+
+        // pieces not a multiple of the chunk size
+        let e = Torrent::decode_from(b"d4:infode6:pieces3:abce").unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::InvalidSyntax));
+        assert_eq!(e.offset(), 17);
+        assert!(e.path().eq(["pieces"]));
+    }
+
+    #[derive(BencodeDecode, Debug)]
+    struct Outer<'a> {
+        info: FileEntry<'a>,
+    }
+
+    #[test]
+    fn test_derive_nested_key_path() {
+        let outer = Outer::decode_from(b"d4:infod6:lengthi1e4:pathl1:aeee").unwrap();
+        assert_eq!(outer.info.length, 1);
+
+        let e = Outer::decode_from(b"d4:infod6:lengthi1e4:pathi3eee").unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::ExpectedList));
+        assert_eq!(e.offset(), 25);
+        assert!(e.path().eq(["info", "path"]));
+        assert_eq!(format!("{:?}", e), "ExpectedList at byte 25 (in info.path)");
+
+        // missing fields point at the start of the dict they are missing from
+        let e = Outer::decode_from(b"d4:infod4:pathleee").unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::MissingField("length")));
+        assert_eq!(e.offset(), 7);
+        assert!(e.path().eq(["info"]));
+    }
+
+    #[test]
+    fn test_derive_not_a_dict() {
+        assert!(matches!(
+            Owned::decode_from(b"i42e").map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedDict)
+        ));
+    }
+
+    #[test]
+    fn test_decode_ints_range_checked() {
+        assert_eq!(u8::decode_from(b"i255e").unwrap(), 255);
+        assert!(matches!(
+            u8::decode_from(b"i256e").map_err(|e| e.kind()),
+            Err(ErrorKind::OutOfRange)
+        ));
+        assert!(matches!(
+            u64::decode_from(b"i-1e").map_err(|e| e.kind()),
+            Err(ErrorKind::OutOfRange)
+        ));
+        assert_eq!(i64::decode_from(b"i-1e").unwrap(), -1);
+    }
+
+    #[test]
+    fn test_decode_fixed_size_bytes() {
+        assert_eq!(<&[u8; 3]>::decode_from(b"3:abc").unwrap(), b"abc");
+        assert!(matches!(
+            <&[u8; 3]>::decode_from(b"2:ab").map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
+    }
+}
@@ -1,15 +1,81 @@
-use super::{Error, Result};
+use super::{Error, ErrorKind, Result};
 use core::str;
 
+/// Nesting depth allowed by default, deep enough for every torrent or tracker response we've seen.
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
 // Holds the current position in the byte slice.
 pub struct BencodeParser<'a> {
     input: &'a [u8],
+    /// length of the whole input, to calculate the offset for errors
+    total_len: usize,
+    /// number of lists/dicts we are currently in
+    depth: usize,
+    max_depth: usize,
+    max_str_len: usize,
+    /// reject everything that isn't the one canonical encoding, see [`BencodeParser::strict`]
+    strict: bool,
+    /// In strict mode dicts are validated as a whole when they are entered. This is the length
+    /// of the remaining input at the end of the last validated dict, so nested dicts aren't checked again.
+    validated_until: usize,
 }
 
 impl<'a> BencodeParser<'a> {
     #[inline]
     pub const fn new(input: &'a [u8]) -> Self {
-        Self { input }
+        Self {
+            input,
+            total_len: input.len(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_str_len: usize::MAX,
+            strict: false,
+            validated_until: usize::MAX,
+        }
+    }
+
+    /// Maximum number of nested lists/dicts before failing with `DepthLimitExceeded`.
+    /// `skip_any` recurses once per level, so this also bounds the stack usage.
+    #[inline]
+    pub const fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Maximum length of a byte string before failing with `StringTooLong`. Unlimited by default.
+    #[inline]
+    pub const fn with_max_str_len(mut self, max_str_len: usize) -> Self {
+        self.max_str_len = max_str_len;
+        self
+    }
+
+    /// Only accept canonical bencode, i.e. the one encoding every other client would produce:
+    /// - no leading zeros and no `-0` in integers (`NonCanonicalInteger`)
+    /// - no leading zeros or signs in string lengths (`NonCanonicalLength`)
+    /// - dict keys are byte strings in strictly ascending order (`UnsortedKeys`, `DuplicateKey`)
+    ///
+    /// Use this for anything that gets hashed (the info dict) or comes from untrusted peers.
+    #[inline]
+    pub const fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    #[inline]
+    pub const fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Number of bytes consumed so far.
+    #[inline]
+    pub const fn offset(&self) -> usize {
+        self.total_len - self.input.len()
+    }
+
+    /// An error at the current position.
+    #[inline]
+    const fn error(&self, kind: ErrorKind) -> Error {
+        Error::new(kind, self.offset())
     }
 
     /// Peek at the next byte without consuming it
@@ -20,8 +86,9 @@ impl<'a> BencodeParser<'a> {
 
     /// Consume the 'i'.. 'e' integer format
     pub fn parse_int(&mut self) -> Result<i64> {
+        let start = self.offset();
         if self.peek() != Some(b'i') {
-            return Err(Error::ExpectedInteger);
+            return Err(self.error(ErrorKind::ExpectedInteger));
         }
         self.input = &self.input[1..]; // skip 'i'
 
@@ -30,18 +97,26 @@ impl<'a> BencodeParser<'a> {
             .input
             .iter()
             .position(|&b| b == b'e')
-            .ok_or(Error::InvalidSyntax)?;
+            .ok_or(Error::new(ErrorKind::InvalidSyntax, start))?;
 
         let (int_bytes, rest) = self.input.split_at(end);
 
         // Bencode spec: integers cannot have leading plus sign
         if int_bytes.first() == Some(&b'+') {
-            return Err(Error::InvalidSyntax);
+            return Err(Error::new(ErrorKind::InvalidSyntax, start));
         }
 
         // Parse the number
-        let s = str::from_utf8(int_bytes).map_err(|_| Error::InvalidSyntax)?;
-        let val = s.parse::<i64>().map_err(|_| Error::InvalidSyntax)?;
+        let invalid = || Error::new(ErrorKind::InvalidSyntax, start);
+        let s = str::from_utf8(int_bytes).map_err(|_| invalid())?;
+        let val = s.parse::<i64>().map_err(|_| invalid())?;
+
+        // the number is valid, so only "-0..." and leading zeros are left to check
+        if self.strict
+            && (int_bytes.starts_with(b"-0") || (int_bytes.len() > 1 && int_bytes[0] == b'0'))
+        {
+            return Err(Error::new(ErrorKind::NonCanonicalInteger, start));
+        }
 
         self.input = &rest[1..]; // skip 'e'
         Ok(val)
@@ -50,31 +125,46 @@ impl<'a> BencodeParser<'a> {
     /// Consume a length-prefixed byte string: "4:spam" -> "spam" and utf-8 decode it
     #[inline]
     pub fn parse_str(&mut self) -> Result<&'a str> {
+        let start = self.offset();
         let s_bytes = self.parse_bytes()?;
-        let s = str::from_utf8(s_bytes).map_err(Error::InvalidUtf8)?;
+        let s =
+            str::from_utf8(s_bytes).map_err(|e| Error::new(ErrorKind::InvalidUtf8(e), start))?;
 
         Ok(s)
     }
 
     /// Consume a length-prefixed byte string: "4:spam" -> "spam"
     pub fn parse_bytes(&mut self) -> Result<&'a [u8]> {
+        let error = |kind| Error::new(kind, self.offset());
+
         // Find the colon
         let colon_idx = self
             .input
             .iter()
             .position(|&b| b == b':')
-            .ok_or(Error::ExpectedString)?;
+            .ok_or(error(ErrorKind::ExpectedString))?;
 
         let (len_bytes, rest) = self.input.split_at(colon_idx);
 
         // Parse length
-        let len_str = str::from_utf8(len_bytes).map_err(|_| Error::InvalidSyntax)?;
-        let len = len_str.parse::<usize>().map_err(|_| Error::InvalidSyntax)?;
+        let len_str = str::from_utf8(len_bytes).map_err(|_| error(ErrorKind::InvalidSyntax))?;
+        let len = len_str
+            .parse::<usize>()
+            .map_err(|_| error(ErrorKind::InvalidSyntax))?;
+
+        // `usize::from_str` happily accepts "+3" and "03"
+        if self.strict && (len_bytes[0] == b'+' || (len_bytes.len() > 1 && len_bytes[0] == b'0')) {
+            return Err(error(ErrorKind::NonCanonicalLength));
+        }
+
+        if len > self.max_str_len {
+            return Err(error(ErrorKind::StringTooLong));
+        }
 
         let rest = &rest[1..]; // skip ':'
 
         if rest.len() < len {
-            return Err(Error::UnexpectedEof);
+            return Err(error(ErrorKind::UnexpectedEof));
         }
 
         // Slice the string (Zero Copy!)
@@ -99,54 +189,120 @@ impl<'a> BencodeParser<'a> {
 
     /// Crucial: Skips the next element (int, string, list, or dict)
     /// Needed when the input has fields your struct doesn't need.
+    ///
+    /// Recurses once per nested list/dict, which is bounded by the maximum depth.
     pub fn skip_any(&mut self) -> Result<()> {
-        let next = self.peek().ok_or(Error::UnexpectedEof)?;
+        let next = self.peek().ok_or(self.error(ErrorKind::UnexpectedEof))?;
         match next {
             b'i' => self.parse_int().map(|_| ()),
             b'0'..=b'9' => self.parse_bytes().map(|_| ()),
             b'l' => {
+                self.enter()?;
                 self.input = &self.input[1..]; // skip 'l'
                 // skip until 'e'
                 while self.peek() != Some(b'e') {
                     self.skip_any()?;
                 }
                 self.input = &self.input[1..]; // skip 'e'
+                self.depth -= 1;
                 Ok(())
             }
             b'd' => {
+                self.enter()?;
                 self.input = &self.input[1..]; // skip 'd'
+                let mut last_key = None;
                 // skip until 'e'
                 while self.peek() != Some(b'e') {
-                    self.skip_any()?; // key
+                    if self.strict {
+                        last_key = Some(self.parse_sorted_key(last_key)?);
+                    } else {
+                        self.skip_any()?; // key
+                    }
                     self.skip_any()?; // value
                 }
                 self.input = &self.input[1..]; // skip 'e'
+                self.depth -= 1;
                 Ok(())
             }
-            _ => Err(Error::InvalidSyntax),
+            _ => Err(self.error(ErrorKind::InvalidSyntax)),
+        }
+    }
+
+    /// Go one level deeper, fails if that would exceed the maximum depth.
+    fn enter(&mut self) -> Result<()> {
+        if self.depth >= self.max_depth {
+            return Err(self.error(ErrorKind::DepthLimitExceeded));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Consume a dict key, which has to come after `last_key`.
+    fn parse_sorted_key(&mut self, last_key: Option<&'a [u8]>) -> Result<&'a [u8]> {
+        if self.peek().is_none() {
+            return Err(self.error(ErrorKind::UnexpectedEof));
+        }
+        let start = self.offset();
+        let key = self.parse_bytes()?;
+        match last_key {
+            Some(last) if last == key => Err(Error::new(ErrorKind::DuplicateKey, start)),
+            Some(last) if last > key => Err(Error::new(ErrorKind::UnsortedKeys, start)),
+            _ => Ok(key),
         }
     }
 
     /// Helper to start a dict
+    ///
+    /// In strict mode the whole dict is validated up front, because the keys are handed out
+    /// one by one and the caller might return early before seeing all of them.
     pub fn expect_dict_start(&mut self) -> Result<()> {
-        if self.peek() == Some(b'd') {
-            self.input = &self.input[1..];
-            Ok(())
-        } else {
-            Err(Error::ExpectedDict)
+        if self.peek() != Some(b'd') {
+            return Err(self.error(ErrorKind::ExpectedDict));
         }
+
+        if self.strict && self.input.len() <= self.validated_until {
+            let mut lookahead = BencodeParser {
+                input: self.input,
+                validated_until: usize::MAX,
+                ..*self
+            };
+            lookahead.skip_any()?;
+            self.validated_until = lookahead.input.len();
+        }
+
+        self.enter()?;
+        self.input = &self.input[1..];
+        Ok(())
     }
 
     /// Returns true if the next element is the end of a dict and consumes it.
     pub fn match_dict_end(&mut self) -> bool {
         if self.peek() == Some(b'e') {
             self.input = &self.input[1..];
+            self.depth = self.depth.saturating_sub(1);
             true
         } else {
             false
         }
     }
 
+    /// Helper to start a list
+    pub fn expect_list_start(&mut self) -> Result<()> {
+        if self.peek() == Some(b'l') {
+            self.enter()?;
+            self.input = &self.input[1..];
+            Ok(())
+        } else {
+            Err(self.error(ErrorKind::ExpectedList))
+        }
+    }
+
+    /// Returns true if the next element is the end of a list and consumes it.
+    #[inline]
+    pub fn match_list_end(&mut self) -> bool {
+        self.match_dict_end()
+    }
+
     /// Drops the parser and returns the remaining bytes.
     #[inline]
     pub const fn remaining(self) -> &'a [u8] {
@@ -185,19 +341,28 @@ mod tests {
     #[test]
     fn test_parse_int_missing_start() {
         let mut parser = BencodeParser::new(b"42e");
-        assert!(matches!(parser.parse_int(), Err(Error::ExpectedInteger)));
+        assert!(matches!(
+            parser.parse_int().map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedInteger)
+        ));
     }
 
     #[test]
     fn test_parse_int_missing_end() {
         let mut parser = BencodeParser::new(b"i42");
-        assert!(matches!(parser.parse_int(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.parse_int().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_parse_int_invalid_number() {
         let mut parser = BencodeParser::new(b"iabce");
-        assert!(matches!(parser.parse_int(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.parse_int().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
@@ -221,19 +386,28 @@ mod tests {
     #[test]
     fn test_parse_str_missing_colon() {
         let mut parser = BencodeParser::new(b"4spam");
-        assert!(matches!(parser.parse_str(), Err(Error::ExpectedString)));
+        assert!(matches!(
+            parser.parse_str().map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedString)
+        ));
     }
 
     #[test]
     fn test_parse_str_length_too_long() {
         let mut parser = BencodeParser::new(b"10:spam");
-        assert!(matches!(parser.parse_str(), Err(Error::UnexpectedEof)));
+        assert!(matches!(
+            parser.parse_str().map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedEof)
+        ));
     }
 
     #[test]
     fn test_parse_str_invalid_length() {
         let mut parser = BencodeParser::new(b"abc:spam");
-        assert!(matches!(parser.parse_str(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.parse_str().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
@@ -315,8 +489,8 @@ mod tests {
     fn test_expect_dict_start_fail() {
         let mut parser = BencodeParser::new(b"i42e");
         assert!(matches!(
-            parser.expect_dict_start(),
-            Err(Error::ExpectedDict)
+            parser.expect_dict_start().map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedDict)
         ));
     }
 
@@ -371,6 +545,26 @@ mod tests {
         assert!(parser.match_dict_end());
     }
 
+    #[test]
+    fn test_list_parsing_workflow() {
+        let mut parser = BencodeParser::new(b"l4:spami42eei7e");
+        parser.expect_list_start().unwrap();
+        assert!(!parser.match_list_end());
+        assert_eq!(parser.parse_str().unwrap(), "spam");
+        assert_eq!(parser.parse_int().unwrap(), 42);
+        assert!(parser.match_list_end());
+        assert_eq!(parser.parse_int().unwrap(), 7);
+    }
+
+    #[test]
+    fn test_expect_list_start_fail() {
+        let mut parser = BencodeParser::new(b"d3:key5:valuee");
+        assert!(matches!(
+            parser.expect_list_start().map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedList)
+        ));
+    }
+
     #[test]
     fn test_empty_list_skip() {
         let mut parser = BencodeParser::new(b"lei42e");
@@ -382,75 +576,111 @@ mod tests {
     #[test]
     fn test_skip_any_invalid_start() {
         let mut parser = BencodeParser::new(b"x");
-        assert!(matches!(parser.skip_any(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_skip_any_empty_input() {
         let mut parser = BencodeParser::new(b"");
-        assert!(matches!(parser.skip_any(), Err(Error::UnexpectedEof)));
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedEof)
+        ));
     }
 
     #[test]
     fn test_skip_any_list_missing_end() {
         let mut parser = BencodeParser::new(b"li42e");
-        assert!(matches!(parser.skip_any(), Err(Error::UnexpectedEof)));
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedEof)
+        ));
     }
 
     #[test]
     fn test_skip_any_dict_missing_end() {
         let mut parser = BencodeParser::new(b"d3:key5:value");
-        assert!(matches!(parser.skip_any(), Err(Error::UnexpectedEof)));
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedEof)
+        ));
     }
 
     #[test]
     fn test_skip_any_dict_odd_elements() {
         let mut parser = BencodeParser::new(b"d3:keye");
-        assert!(matches!(parser.skip_any(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_parse_str_invalid_utf8() {
         // Invalid UTF-8 sequence
         let mut parser = BencodeParser::new(b"4:\xff\xfe\xfd\xfc");
-        assert!(matches!(parser.parse_str(), Err(Error::InvalidUtf8(_))));
+        assert!(matches!(
+            parser.parse_str().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidUtf8(_))
+        ));
     }
 
     #[test]
     fn test_parse_int_overflow() {
         // Number larger than i64::MAX
         let mut parser = BencodeParser::new(b"i99999999999999999999e");
-        assert!(matches!(parser.parse_int(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.parse_int().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_parse_int_empty() {
         let mut parser = BencodeParser::new(b"ie");
-        assert!(matches!(parser.parse_int(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.parse_int().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_parse_int_invalid_chars() {
         let mut parser = BencodeParser::new(b"i42xe");
-        assert!(matches!(parser.parse_int(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.parse_int().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_parse_int_multiple_signs() {
         let mut parser = BencodeParser::new(b"i--42e");
-        assert!(matches!(parser.parse_int(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.parse_int().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_parse_int_plus_sign() {
         let mut parser = BencodeParser::new(b"i+42e");
-        assert!(matches!(parser.parse_int(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.parse_int().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_parse_str_negative_length() {
         let mut parser = BencodeParser::new(b"-5:hello");
-        assert!(matches!(parser.parse_str(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.parse_str().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
@@ -462,27 +692,36 @@ mod tests {
     #[test]
     fn test_skip_any_list_with_invalid_element() {
         let mut parser = BencodeParser::new(b"lxe");
-        assert!(matches!(parser.skip_any(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_skip_any_dict_with_invalid_key() {
         let mut parser = BencodeParser::new(b"dxi42ee");
-        assert!(matches!(parser.skip_any(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_skip_any_dict_with_invalid_value() {
         let mut parser = BencodeParser::new(b"d3:keyxe");
-        assert!(matches!(parser.skip_any(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_expect_dict_start_on_list() {
         let mut parser = BencodeParser::new(b"li42ee");
         assert!(matches!(
-            parser.expect_dict_start(),
-            Err(Error::ExpectedDict)
+            parser.expect_dict_start().map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedDict)
         ));
     }
 
@@ -490,8 +729,8 @@ mod tests {
     fn test_expect_dict_start_on_string() {
         let mut parser = BencodeParser::new(b"4:spam");
         assert!(matches!(
-            parser.expect_dict_start(),
-            Err(Error::ExpectedDict)
+            parser.expect_dict_start().map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedDict)
         ));
     }
 
@@ -499,33 +738,45 @@ mod tests {
     fn test_expect_dict_start_empty_input() {
         let mut parser = BencodeParser::new(b"");
         assert!(matches!(
-            parser.expect_dict_start(),
-            Err(Error::ExpectedDict)
+            parser.expect_dict_start().map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedDict)
         ));
     }
 
     #[test]
     fn test_parse_int_eof_in_number() {
         let mut parser = BencodeParser::new(b"i42");
-        assert!(matches!(parser.parse_int(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.parse_int().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
     }
 
     #[test]
     fn test_parse_str_eof_in_data() {
         let mut parser = BencodeParser::new(b"10:short");
-        assert!(matches!(parser.parse_str(), Err(Error::UnexpectedEof)));
+        assert!(matches!(
+            parser.parse_str().map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedEof)
+        ));
     }
 
     #[test]
     fn test_nested_structure_errors() {
         let mut parser = BencodeParser::new(b"lli42e");
-        assert!(matches!(parser.skip_any(), Err(Error::UnexpectedEof)));
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedEof)
+        ));
     }
 
     #[test]
     fn test_dict_nested_error() {
         let mut parser = BencodeParser::new(b"d3:keyd3:foo");
-        assert!(matches!(parser.skip_any(), Err(Error::UnexpectedEof)));
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedEof)
+        ));
     }
 
     #[test]
@@ -534,10 +785,176 @@ mod tests {
         assert_eq!(parser.parse_str().unwrap(), "abc");
     }
 
+    #[test]
+    fn test_strict_accepts_canonical() {
+        let mut parser = BencodeParser::new(b"d1:ai0e1:bi-42e1:cli10e0:e2:cd3:abce").strict();
+        assert!(parser.is_strict());
+        parser.skip_any().unwrap();
+        assert!(parser.remaining().is_empty());
+
+        let mut parser = BencodeParser::new(b"10:abcdefghij").strict();
+        assert_eq!(parser.parse_str().unwrap(), "abcdefghij");
+    }
+
+    #[test]
+    fn test_strict_rejects_non_canonical_ints() {
+        for input in [&b"i-0e"[..], b"i03e", b"i-03e", b"i00e"] {
+            let mut parser = BencodeParser::new(input).strict();
+            assert!(matches!(
+                parser.parse_int().map_err(|e| e.kind()),
+                Err(ErrorKind::NonCanonicalInteger)
+            ));
+            // the lenient parser still takes them
+            assert!(BencodeParser::new(input).parse_int().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_strict_rejects_non_canonical_lengths() {
+        for input in [&b"03:abc"[..], b"+3:abc", b"00:"] {
+            let mut parser = BencodeParser::new(input).strict();
+            assert!(matches!(
+                parser.parse_bytes().map_err(|e| e.kind()),
+                Err(ErrorKind::NonCanonicalLength)
+            ));
+            assert!(BencodeParser::new(input).parse_bytes().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_strict_rejects_unsorted_keys() {
+        let mut parser = BencodeParser::new(b"d1:bi1e1:ai2ee").strict();
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::UnsortedKeys)
+        ));
+
+        let mut parser = BencodeParser::new(b"d1:ai1e1:ai2ee").strict();
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::DuplicateKey)
+        ));
+
+        // keys are compared as bytes: "piece length" < "pieces"
+        let mut parser = BencodeParser::new(b"d12:piece lengthi1e6:pieces0:e").strict();
+        assert!(parser.skip_any().is_ok());
+    }
+
+    #[test]
+    fn test_strict_dict_validated_on_start() {
+        // the problem is in a nested dict after the first key, it's caught before any key is read
+        let mut parser = BencodeParser::new(b"d1:ai1e1:bd1:yi1e1:xi2eee").strict();
+        assert!(matches!(
+            parser.expect_dict_start().map_err(|e| e.kind()),
+            Err(ErrorKind::UnsortedKeys)
+        ));
+
+        let mut parser = BencodeParser::new(b"d1:ai1e1:bd1:xi03eee").strict();
+        assert!(matches!(
+            parser.expect_dict_start().map_err(|e| e.kind()),
+            Err(ErrorKind::NonCanonicalInteger)
+        ));
+
+        // walking a valid dict by hand works as usual
+        let mut parser = BencodeParser::new(b"d1:ad1:xi1ee1:bi2ee").strict();
+        parser.expect_dict_start().unwrap();
+        assert_eq!(parser.parse_str().unwrap(), "a");
+        parser.expect_dict_start().unwrap();
+        assert_eq!(parser.parse_str().unwrap(), "x");
+        assert_eq!(parser.parse_int().unwrap(), 1);
+        assert!(parser.match_dict_end());
+        assert_eq!(parser.parse_str().unwrap(), "b");
+        assert_eq!(parser.parse_int().unwrap(), 2);
+        assert!(parser.match_dict_end());
+    }
+
+    #[test]
+    fn test_strict_dict_key_must_be_string() {
+        let mut parser = BencodeParser::new(b"di1ei2ee").strict();
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedString)
+        ));
+    }
+
+    #[test]
+    fn test_depth_limit_skip() {
+        let mut deep = [b'l'; 1000];
+        deep[999] = b'e';
+        let mut parser = BencodeParser::new(&deep);
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::DepthLimitExceeded)
+        ));
+
+        let mut parser = BencodeParser::new(b"llleee").with_max_depth(2);
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::DepthLimitExceeded)
+        ));
+
+        let mut parser = BencodeParser::new(b"lldeeei1e").with_max_depth(3);
+        parser.skip_any().unwrap();
+        assert_eq!(parser.parse_int().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_depth_limit_helpers() {
+        // the depth is tracked across the dict/list helpers, too
+        let mut parser = BencodeParser::new(b"d1:ald1:bleeee").with_max_depth(3);
+        parser.expect_dict_start().unwrap();
+        parser.parse_str().unwrap();
+        parser.expect_list_start().unwrap();
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::DepthLimitExceeded)
+        ));
+
+        let mut parser = BencodeParser::new(b"lelel").with_max_depth(1);
+        parser.expect_list_start().unwrap();
+        assert!(parser.match_list_end());
+        parser.expect_list_start().unwrap();
+        assert!(parser.match_list_end());
+        parser.expect_list_start().unwrap();
+    }
+
+    #[test]
+    fn test_depth_limit_strict_lookahead() {
+        let mut parser = BencodeParser::new(b"d1:ad1:bdeee")
+            .strict()
+            .with_max_depth(2);
+        assert!(matches!(
+            parser.expect_dict_start().map_err(|e| e.kind()),
+            Err(ErrorKind::DepthLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn test_max_str_len() {
+        let mut parser = BencodeParser::new(b"4:spam").with_max_str_len(3);
+        assert!(matches!(
+            parser.parse_bytes().map_err(|e| e.kind()),
+            Err(ErrorKind::StringTooLong)
+        ));
+
+        // checked before looking at the data, so huge lengths fail early
+        let mut parser = BencodeParser::new(b"99999999:").with_max_str_len(1024);
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::StringTooLong)
+        ));
+
+        let mut parser = BencodeParser::new(b"3:egg").with_max_str_len(3);
+        assert_eq!(parser.parse_str().unwrap(), "egg");
+    }
+
     #[test]
     fn test_multiple_consecutive_errors() {
         let mut parser = BencodeParser::new(b"xyz");
-        assert!(matches!(parser.skip_any(), Err(Error::InvalidSyntax)));
+        assert!(matches!(
+            parser.skip_any().map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
         // Parser state after error - should still be at 'x'
         assert_eq!(parser.peek(), Some(b'x'));
     }
@@ -2,27 +2,81 @@
 
 use core::str::Utf8Error;
 
-pub use crate::deserialize::BencodeParser;
+// lets the code generated by `bencode-derive` refer to `::bencode` inside this crate, too
+extern crate self as bencode;
 
+#[cfg(feature = "derive")]
+pub use bencode_derive::BencodeDecode;
+
+pub use crate::decode::BencodeDecode;
+pub use crate::deserialize::{BencodeParser, DEFAULT_MAX_DEPTH};
+pub use crate::serialize::{BencodeEncoder, DictEncoder, ListEncoder};
+pub use crate::value::{Dict, DictIter, List, ListIter, Value};
+
+mod decode;
 mod deserialize;
+mod serialize;
+mod value;
 
-#[derive(Clone, Copy)]
+/// What went wrong, see [`Error`] for where.
+#[derive(Clone, Copy, PartialEq)]
 #[cfg_attr(feature = "log", derive(Debug))]
-pub enum Error {
+pub enum ErrorKind {
     UnexpectedEof,
     InvalidSyntax,
     InvalidUtf8(Utf8Error),
     ExpectedInteger,
     ExpectedString,
     ExpectedDict,
+    ExpectedList,
     UnknownField,
+    /// An integer doesn't fit into the type it is decoded into.
+    OutOfRange,
+    /// Strict mode: an integer with leading zeros or `-0`.
+    NonCanonicalInteger,
+    /// Strict mode: a string length with leading zeros or a sign.
+    NonCanonicalLength,
+    /// Strict mode: a dict key was smaller than the one before it.
+    UnsortedKeys,
+    /// Strict mode: a dict key appeared twice.
+    DuplicateKey,
+    /// Lists/dicts are nested deeper than the parser's maximum depth.
+    DepthLimitExceeded,
+    /// A byte string is longer than the parser's maximum string length.
+    StringTooLong,
+    /// A mandatory dict key is missing.
+    MissingField(&'static str),
 }
 
 #[cfg(feature = "defmt")]
-impl defmt::Format for Error {
+impl ErrorKind {
+    const fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::UnexpectedEof => "UnexpectedEof",
+            ErrorKind::InvalidSyntax => "InvalidSyntax",
+            ErrorKind::InvalidUtf8(_) => "InvalidUtf8",
+            ErrorKind::ExpectedInteger => "ExpectedInteger",
+            ErrorKind::ExpectedString => "ExpectedString",
+            ErrorKind::ExpectedDict => "ExpectedDict",
+            ErrorKind::ExpectedList => "ExpectedList",
+            ErrorKind::UnknownField => "UnknownField",
+            ErrorKind::OutOfRange => "OutOfRange",
+            ErrorKind::NonCanonicalInteger => "NonCanonicalInteger",
+            ErrorKind::NonCanonicalLength => "NonCanonicalLength",
+            ErrorKind::UnsortedKeys => "UnsortedKeys",
+            ErrorKind::DuplicateKey => "DuplicateKey",
+            ErrorKind::DepthLimitExceeded => "DepthLimitExceeded",
+            ErrorKind::StringTooLong => "StringTooLong",
+            ErrorKind::MissingField(_) => "MissingField",
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ErrorKind {
     fn format(&self, f: defmt::Formatter) {
         match self {
-            Error::InvalidUtf8(e) => {
+            ErrorKind::InvalidUtf8(e) => {
                 defmt::write!(
                     f,
                     "Invalid UTF-8: valid_up_to: {}, error_len: {:?}",
@@ -30,9 +84,120 @@ impl defmt::Format for Error {
                     e.error_len()
                 )
             }
-            kind => defmt::write!(f, "{:?}", kind),
+            ErrorKind::MissingField(field) => defmt::write!(f, "MissingField({=str})", field),
+            kind => defmt::write!(f, "{=str}", kind.name()),
+        }
+    }
+}
+
+/// Number of dict keys an [`Error`] remembers, the innermost ones are kept.
+pub const MAX_KEY_PATH: usize = 4;
+
+/// A parsing error with the byte offset into the input where it happened
+/// and the path of dict keys leading there (e.g. `info.pieces`).
+///
+/// The key path is filled in by `#[derive(BencodeDecode)]`, hand-written dict loops
+/// can add to it with [`Error::in_field`].
+#[derive(Clone, Copy)]
+pub struct Error {
+    kind: ErrorKind,
+    offset: usize,
+    /// innermost key first
+    path: [&'static str; MAX_KEY_PATH],
+    path_len: u8,
+}
+
+impl Error {
+    #[inline]
+    pub const fn new(kind: ErrorKind, offset: usize) -> Self {
+        Self {
+            kind,
+            offset,
+            path: [""; MAX_KEY_PATH],
+            path_len: 0,
+        }
+    }
+
+    #[inline]
+    pub const fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Byte offset into the input where the failing element starts.
+    #[inline]
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The dict keys leading to the error, outermost first.
+    pub fn path(&self) -> impl DoubleEndedIterator<Item = &'static str> + '_ {
+        self.path[..self.path_len as usize].iter().rev().copied()
+    }
+
+    /// Records that the error occurred while decoding the value of `field`.
+    ///
+    /// Called from the innermost dict outwards, once the path is full the outer keys are dropped.
+    pub const fn in_field(mut self, field: &'static str) -> Self {
+        if (self.path_len as usize) < MAX_KEY_PATH {
+            self.path[self.path_len as usize] = field;
+            self.path_len += 1;
+        }
+        self
+    }
+
+    /// Shifts the offset of an error that occurred while parsing a slice starting at `base`
+    /// of a larger input, e.g. the `info` dict of a torrent.
+    #[inline]
+    pub const fn offset_by(mut self, base: usize) -> Self {
+        self.offset += base;
+        self
+    }
+}
+
+/// e.g. "ExpectedInteger at byte 42 (in info.length)"
+#[cfg(feature = "log")]
+impl core::fmt::Debug for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?} at byte {}", self.kind, self.offset)?;
+        for (i, key) in self.path().enumerate() {
+            f.write_str(if i == 0 { " (in " } else { "." })?;
+            f.write_str(key)?;
+        }
+        if self.path_len > 0 {
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{} at byte {}", self.kind, self.offset);
+        for (i, key) in self.path().enumerate() {
+            if i == 0 {
+                defmt::write!(f, " (in {=str}", key);
+            } else {
+                defmt::write!(f, ".{=str}", key);
+            }
+        }
+        if self.path_len > 0 {
+            defmt::write!(f, ")");
         }
     }
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
+
+/// Errors that can occur while encoding, generic over the writer's error type.
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "log", derive(Debug))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EncodeError<E> {
+    /// The underlying writer failed (e.g. the buffer is full).
+    Write(E),
+    /// A dict key was smaller than the one before it.
+    UnsortedKeys,
+    /// A dict key was written twice.
+    DuplicateKey,
+}
@@ -0,0 +1,332 @@
+use super::EncodeError;
+use core::fmt::Write as _;
+use embedded_io::Write;
+
+use num_buf::NumBuf;
+
+type EncodeResult<T, W> =
+    core::result::Result<T, EncodeError<<W as embedded_io::ErrorType>::Error>>;
+
+/// Writes bencoded values into any `embedded_io::Write` sink.
+///
+/// `&mut [u8]` implements `Write`, so encoding into a caller-supplied buffer is just:
+///
+/// ```
+/// use bencode::BencodeEncoder;
+///
+/// let mut buf = [0u8; 64];
+/// let mut enc = BencodeEncoder::new(&mut buf[..]);
+///
+/// let mut dict = enc.dict().unwrap();
+/// dict.entry("m").unwrap().dict().unwrap().finish().unwrap();
+/// dict.entry("v").unwrap().str("minitorrent").unwrap();
+/// dict.finish().unwrap();
+///
+/// let len = enc.written();
+/// assert_eq!(&buf[..len], b"d1:mde1:v11:minitorrente");
+/// ```
+///
+/// Lists and dicts are written through [`ListEncoder`] and [`DictEncoder`], which borrow the
+/// encoder until `finish()` writes the closing `e`. Dict keys have to be given in sorted order,
+/// otherwise the output wouldn't be canonical (and info hashes wouldn't match other clients).
+pub struct BencodeEncoder<W: Write> {
+    writer: W,
+    written: usize,
+}
+
+impl<W: Write> BencodeEncoder<W> {
+    #[inline]
+    pub const fn new(writer: W) -> Self {
+        Self { writer, written: 0 }
+    }
+
+    /// Number of bytes written so far.
+    #[inline]
+    pub const fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Drops the encoder and returns the underlying writer.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Write an integer: 42 -> "i42e"
+    pub fn int(&mut self, value: i64) -> EncodeResult<(), W> {
+        let mut digits = NumBuf::new();
+        write!(digits, "i{}e", value).expect("an i64 always fits");
+        self.write_raw(digits.as_bytes())
+    }
+
+    /// Write a length-prefixed byte string: "spam" -> "4:spam"
+    pub fn bytes(&mut self, value: &[u8]) -> EncodeResult<(), W> {
+        let mut digits = NumBuf::new();
+        write!(digits, "{}:", value.len()).expect("a usize always fits");
+        self.write_raw(digits.as_bytes())?;
+        self.write_raw(value)
+    }
+
+    /// Write a utf-8 string as a byte string.
+    #[inline]
+    pub fn str(&mut self, value: &str) -> EncodeResult<(), W> {
+        self.bytes(value.as_bytes())
+    }
+
+    /// Write bytes that are already bencoded, e.g. the raw `info` dict of a torrent.
+    /// No validation is done here, the caller has to make sure `value` is a single valid element.
+    pub fn raw(&mut self, value: &[u8]) -> EncodeResult<(), W> {
+        self.write_raw(value)
+    }
+
+    /// Start a list, the returned `ListEncoder` has to be `finish`ed.
+    pub fn list(&mut self) -> EncodeResult<ListEncoder<'_, W>, W> {
+        self.write_raw(b"l")?;
+        Ok(ListEncoder { enc: self })
+    }
+
+    /// Start a dict, the returned `DictEncoder` has to be `finish`ed.
+    pub fn dict<'k>(&mut self) -> EncodeResult<DictEncoder<'_, 'k, W>, W> {
+        self.write_raw(b"d")?;
+        Ok(DictEncoder {
+            enc: self,
+            last_key: None,
+        })
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> EncodeResult<(), W> {
+        self.writer.write_all(bytes).map_err(EncodeError::Write)?;
+        self.written += bytes.len();
+        Ok(())
+    }
+}
+
+/// An open list. Every element is written through the underlying encoder.
+#[must_use = "the list has to be closed with `finish()`"]
+pub struct ListEncoder<'e, W: Write> {
+    enc: &'e mut BencodeEncoder<W>,
+}
+
+impl<W: Write> ListEncoder<'_, W> {
+    /// Get the encoder to write the next element of the list.
+    #[inline]
+    pub fn item(&mut self) -> &mut BencodeEncoder<W> {
+        self.enc
+    }
+
+    /// Writes the closing `e` of the list.
+    pub fn finish(self) -> EncodeResult<(), W> {
+        self.enc.write_raw(b"e")
+    }
+}
+
+/// An open dict. Keys have to be added in strictly ascending (byte-wise) order.
+#[must_use = "the dict has to be closed with `finish()`"]
+pub struct DictEncoder<'e, 'k, W: Write> {
+    enc: &'e mut BencodeEncoder<W>,
+    last_key: Option<&'k [u8]>,
+}
+
+impl<'k, W: Write> DictEncoder<'_, 'k, W> {
+    /// Write the key and return the encoder for its value.
+    /// Exactly one value has to be written before the next call.
+    ///
+    /// Fails with `UnsortedKeys` or `DuplicateKey` if `key` doesn't come after the previous one.
+    pub fn entry<K>(&mut self, key: &'k K) -> EncodeResult<&mut BencodeEncoder<W>, W>
+    where
+        K: AsRef<[u8]> + ?Sized,
+    {
+        let key = key.as_ref();
+        match self.last_key {
+            Some(last) if last == key => return Err(EncodeError::DuplicateKey),
+            Some(last) if last > key => return Err(EncodeError::UnsortedKeys),
+            _ => {}
+        }
+        self.enc.bytes(key)?;
+        self.last_key = Some(key);
+        Ok(self.enc)
+    }
+
+    /// Writes the closing `e` of the dict.
+    pub fn finish(self) -> EncodeResult<(), W> {
+        self.enc.write_raw(b"e")
+    }
+}
+
+/// A tiny stack buffer for formatting integers without `alloc`.
+mod num_buf {
+    /// "i-9223372036854775808e" is the longest thing we ever format (22 bytes).
+    const CAP: usize = 24;
+
+    pub(super) struct NumBuf {
+        buf: [u8; CAP],
+        len: usize,
+    }
+
+    impl NumBuf {
+        pub(super) const fn new() -> Self {
+            Self {
+                buf: [0; CAP],
+                len: 0,
+            }
+        }
+
+        pub(super) fn as_bytes(&self) -> &[u8] {
+            &self.buf[..self.len]
+        }
+    }
+
+    impl core::fmt::Write for NumBuf {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            let end = self.len + s.len();
+            if end > CAP {
+                return Err(core::fmt::Error);
+            }
+            self.buf[self.len..end].copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BencodeParser;
+
+    fn encode(f: impl FnOnce(&mut BencodeEncoder<&mut [u8]>)) -> Vec<u8> {
+        let mut buf = [0u8; 256];
+        let mut enc = BencodeEncoder::new(&mut buf[..]);
+        f(&mut enc);
+        let len = enc.written();
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn test_encode_int() {
+        assert_eq!(encode(|e| e.int(42).unwrap()), b"i42e");
+        assert_eq!(encode(|e| e.int(0).unwrap()), b"i0e");
+        assert_eq!(encode(|e| e.int(-42).unwrap()), b"i-42e");
+        assert_eq!(
+            encode(|e| e.int(i64::MIN).unwrap()),
+            b"i-9223372036854775808e"
+        );
+    }
+
+    #[test]
+    fn test_encode_bytes() {
+        assert_eq!(encode(|e| e.str("spam").unwrap()), b"4:spam");
+        assert_eq!(encode(|e| e.bytes(b"").unwrap()), b"0:");
+        assert_eq!(encode(|e| e.bytes(&[0xff, 0x00]).unwrap()), b"2:\xff\x00");
+    }
+
+    #[test]
+    fn test_encode_list() {
+        let out = encode(|e| {
+            let mut l = e.list().unwrap();
+            l.item().int(1).unwrap();
+            l.item().str("a").unwrap();
+            let inner = l.item().list().unwrap();
+            inner.finish().unwrap();
+            l.finish().unwrap();
+        });
+        assert_eq!(out, b"li1e1:alee");
+    }
+
+    #[test]
+    fn test_encode_nested_dict() {
+        let out = encode(|e| {
+            let mut d = e.dict().unwrap();
+            d.entry("announce").unwrap().str("http://test.com").unwrap();
+            let mut info = d.entry("info").unwrap().dict().unwrap();
+            info.entry("length").unwrap().int(100).unwrap();
+            info.entry("name").unwrap().str("log").unwrap();
+            info.finish().unwrap();
+            d.finish().unwrap();
+        });
+        assert_eq!(
+            out,
+            b"d8:announce15:http://test.com4:infod6:lengthi100e4:name3:logee"
+        );
+    }
+
+    #[test]
+    fn test_encode_raw() {
+        let out = encode(|e| {
+            let mut d = e.dict().unwrap();
+            d.entry("info").unwrap().raw(b"d1:ai1ee").unwrap();
+            d.finish().unwrap();
+        });
+        assert_eq!(out, b"d4:infod1:ai1eee");
+    }
+
+    #[test]
+    fn test_encode_byte_keys() {
+        let hash = [0x01u8; 4];
+        let out = encode(|e| {
+            let mut d = e.dict().unwrap();
+            d.entry(&hash).unwrap().int(1).unwrap();
+            d.finish().unwrap();
+        });
+        assert_eq!(out, b"d4:\x01\x01\x01\x01i1ee");
+    }
+
+    #[test]
+    fn test_encode_unsorted_keys() {
+        let mut buf = [0u8; 64];
+        let mut enc = BencodeEncoder::new(&mut buf[..]);
+        let mut d = enc.dict().unwrap();
+        d.entry("b").unwrap().int(1).unwrap();
+        assert!(matches!(d.entry("a"), Err(EncodeError::UnsortedKeys)));
+        assert!(matches!(d.entry("b"), Err(EncodeError::DuplicateKey)));
+        // a key that sorts after the previous one is still fine
+        d.entry("c").unwrap().int(2).unwrap();
+        d.finish().unwrap();
+        let len = enc.written();
+        assert_eq!(&buf[..len], b"d1:bi1e1:ci2ee");
+    }
+
+    #[test]
+    fn test_encode_keys_sorted_as_bytes() {
+        // "piece length" < "pieces" because ' ' (0x20) < 's'
+        let out = encode(|e| {
+            let mut d = e.dict().unwrap();
+            d.entry("piece length").unwrap().int(16).unwrap();
+            d.entry("pieces").unwrap().bytes(b"").unwrap();
+            d.finish().unwrap();
+        });
+        assert_eq!(out, b"d12:piece lengthi16e6:pieces0:e");
+    }
+
+    #[test]
+    fn test_encode_buffer_full() {
+        let mut buf = [0u8; 4];
+        let mut enc = BencodeEncoder::new(&mut buf[..]);
+        assert!(matches!(
+            enc.str("spam"),
+            Err(EncodeError::Write(embedded_io::SliceWriteError::Full))
+        ));
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let out = encode(|e| {
+            let mut d = e.dict().unwrap();
+            d.entry("interval").unwrap().int(1800).unwrap();
+            d.entry("peers")
+                .unwrap()
+                .bytes(&[127, 0, 0, 1, 0x1A, 0xE1])
+                .unwrap();
+            d.finish().unwrap();
+        });
+
+        let mut p = BencodeParser::new(&out);
+        p.expect_dict_start().unwrap();
+        assert_eq!(p.parse_str().unwrap(), "interval");
+        assert_eq!(p.parse_int().unwrap(), 1800);
+        assert_eq!(p.parse_str().unwrap(), "peers");
+        assert_eq!(p.parse_bytes().unwrap(), &[127, 0, 0, 1, 0x1A, 0xE1]);
+        assert!(p.match_dict_end());
+        assert!(p.remaining().is_empty());
+    }
+}
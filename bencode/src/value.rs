@@ -0,0 +1,362 @@
+//! A borrowed, non-allocating view over a bencoded value.
+//!
+//! Lists and dicts aren't decoded eagerly, they just remember their raw bytes and are
+//! parsed again while iterating. That keeps `Value` `Copy` and free of any allocation,
+//! at the cost of a linear scan for every lookup (which is fine for the small dicts we see).
+use crate::{BencodeParser, Error, ErrorKind, Result};
+
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "log", derive(Debug))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Value<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    List(List<'a>),
+    Dict(Dict<'a>),
+}
+
+/// A bencoded list, iterate over it with [`List::iter`].
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "log", derive(Debug))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct List<'a> {
+    /// the whole list including the leading 'l' and trailing 'e'
+    raw: &'a [u8],
+}
+
+/// A bencoded dict, iterate over it with [`Dict::iter`] or look up keys with [`Dict::get`].
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "log", derive(Debug))]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Dict<'a> {
+    /// the whole dict including the leading 'd' and trailing 'e'
+    raw: &'a [u8],
+}
+
+impl<'a> Value<'a> {
+    /// Parse `input`, which has to contain exactly one bencoded value.
+    ///
+    /// ```
+    /// use bencode::Value;
+    ///
+    /// let v = Value::parse(b"d4:infod5:filesld6:lengthi3eeeee").unwrap();
+    /// let files = v.get("info").and_then(|i| i.get("files")).unwrap();
+    /// let first = files.as_list().unwrap().iter().next().unwrap();
+    /// assert_eq!(first.get("length").and_then(|l| l.as_int()), Some(3));
+    /// ```
+    pub fn parse(input: &'a [u8]) -> Result<Self> {
+        let mut p = BencodeParser::new(input);
+        let value = Self::from_parser(&mut p)?;
+        let end = p.offset();
+        if !p.remaining().is_empty() {
+            return Err(Error::new(ErrorKind::InvalidSyntax, end));
+        }
+        Ok(value)
+    }
+
+    /// Consume the next element of `p` as a `Value`.
+    /// Nested lists and dicts are fully validated here, so iterating over them later can't fail.
+    pub fn from_parser(p: &mut BencodeParser<'a>) -> Result<Self> {
+        let start = p.offset();
+        match p
+            .peek()
+            .ok_or(Error::new(ErrorKind::UnexpectedEof, start))?
+        {
+            b'i' => p.parse_int().map(Value::Int),
+            b'0'..=b'9' => p.parse_bytes().map(Value::Bytes),
+            b'l' => p.parse_raw_value().map(|raw| Value::List(List { raw })),
+            b'd' => p.parse_raw_value().map(|raw| Value::Dict(Dict { raw })),
+            _ => Err(Error::new(ErrorKind::InvalidSyntax, start)),
+        }
+    }
+
+    /// Look up `key` if this is a dict.
+    #[inline]
+    pub fn get<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> Option<Value<'a>> {
+        self.as_dict()?.get(key)
+    }
+
+    #[inline]
+    pub const fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub const fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Returns the byte string if it is valid utf-8.
+    #[inline]
+    pub fn as_str(&self) -> Option<&'a str> {
+        core::str::from_utf8(self.as_bytes()?).ok()
+    }
+
+    #[inline]
+    pub const fn as_list(&self) -> Option<List<'a>> {
+        match self {
+            Value::List(l) => Some(*l),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub const fn as_dict(&self) -> Option<Dict<'a>> {
+        match self {
+            Value::Dict(d) => Some(*d),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> List<'a> {
+    /// Iterate over the items without allocating.
+    #[inline]
+    pub fn iter(&self) -> ListIter<'a> {
+        ListIter {
+            // skip 'l', the depth was already checked by the parser this came from
+            p: BencodeParser::new(&self.raw[1..]).with_max_depth(usize::MAX),
+        }
+    }
+
+    /// Number of items, this walks the whole list.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.raw.len() == 2 // "le"
+    }
+
+    /// The raw bencoded list, including the leading 'l' and trailing 'e'.
+    #[inline]
+    pub const fn as_raw(&self) -> &'a [u8] {
+        self.raw
+    }
+}
+
+impl<'a> Dict<'a> {
+    /// Iterate over the key-value pairs in the order they appear in the input.
+    #[inline]
+    pub fn iter(&self) -> DictIter<'a> {
+        DictIter {
+            // skip 'd', the depth was already checked by the parser this came from
+            p: BencodeParser::new(&self.raw[1..]).with_max_depth(usize::MAX),
+        }
+    }
+
+    /// Returns the value of the first entry with the given key.
+    pub fn get<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> Option<Value<'a>> {
+        let key = key.as_ref();
+        self.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.raw.len() == 2 // "de"
+    }
+
+    /// The raw bencoded dict, including the leading 'd' and trailing 'e'.
+    /// Useful for hashing, e.g. the `info` dict of a torrent.
+    #[inline]
+    pub const fn as_raw(&self) -> &'a [u8] {
+        self.raw
+    }
+}
+
+impl<'a> IntoIterator for List<'a> {
+    type Item = Value<'a>;
+    type IntoIter = ListIter<'a>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for Dict<'a> {
+    type Item = (&'a [u8], Value<'a>);
+    type IntoIter = DictIter<'a>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct ListIter<'a> {
+    p: BencodeParser<'a>,
+}
+
+impl<'a> Iterator for ListIter<'a> {
+    type Item = Value<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.p.match_list_end() {
+            return None;
+        }
+        // the list was validated when the `Value` was created, so this can't fail
+        Value::from_parser(&mut self.p).ok()
+    }
+}
+
+pub struct DictIter<'a> {
+    p: BencodeParser<'a>,
+}
+
+impl<'a> Iterator for DictIter<'a> {
+    type Item = (&'a [u8], Value<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.p.match_dict_end() {
+            return None;
+        }
+        // the dict was validated when the `Value` was created,
+        // only a key that isn't a byte string (e.g. "di1ei2ee") can end the iteration early
+        let key = self.p.parse_bytes().ok()?;
+        let value = Value::from_parser(&mut self.p).ok()?;
+        Some((key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_scalars() {
+        assert_eq!(Value::parse(b"i42e").unwrap(), Value::Int(42));
+        assert_eq!(Value::parse(b"4:spam").unwrap(), Value::Bytes(b"spam"));
+        assert_eq!(Value::parse(b"4:spam").unwrap().as_str(), Some("spam"));
+        assert_eq!(Value::parse(b"i42e").unwrap().as_str(), None);
+    }
+
+    #[test]
+    fn test_value_list_iter() {
+        let v = Value::parse(b"li1e3:fooli2eee").unwrap();
+        let list = v.as_list().unwrap();
+        assert_eq!(list.len(), 3);
+
+        let mut it = list.iter();
+        assert_eq!(it.next(), Some(Value::Int(1)));
+        assert_eq!(it.next(), Some(Value::Bytes(b"foo")));
+        let inner = it.next().unwrap().as_list().unwrap();
+        assert_eq!(inner.as_raw(), b"li2ee");
+        assert_eq!(it.next(), None);
+    }
+
+    #[test]
+    fn test_value_empty_containers() {
+        let list = Value::parse(b"le").unwrap().as_list().unwrap();
+        assert!(list.is_empty());
+        assert_eq!(list.iter().next(), None);
+
+        let dict = Value::parse(b"de").unwrap().as_dict().unwrap();
+        assert!(dict.is_empty());
+        assert_eq!(dict.get("x"), None);
+    }
+
+    #[test]
+    fn test_value_dict_lookup() {
+        let v = Value::parse(b"d8:intervali1800e5:peers6:abcdefe").unwrap();
+        assert_eq!(v.get("interval").and_then(|i| i.as_int()), Some(1800));
+        assert_eq!(
+            v.get("peers").and_then(|p| p.as_bytes()),
+            Some(&b"abcdef"[..])
+        );
+        assert_eq!(v.get("missing"), None);
+        // not a dict
+        assert_eq!(Value::Int(3).get("interval"), None);
+    }
+
+    #[test]
+    fn test_value_path_lookup() {
+        let input = b"d8:announce3:url4:infod5:filesld6:lengthi3e4:pathl1:a1:beed6:lengthi4e4:pathl1:ceee4:name3:diree";
+        let v = Value::parse(input).unwrap();
+
+        let files = v
+            .get("info")
+            .and_then(|i| i.get("files"))
+            .and_then(|f| f.as_list())
+            .unwrap();
+
+        let lengths: Vec<_> = files
+            .iter()
+            .filter_map(|f| f.get("length")?.as_int())
+            .collect();
+        assert_eq!(lengths, [3, 4]);
+
+        let first_path: Vec<_> = files
+            .iter()
+            .next()
+            .and_then(|f| f.get("path")?.as_list())
+            .unwrap()
+            .iter()
+            .filter_map(|s| s.as_str())
+            .collect();
+        assert_eq!(first_path, ["a", "b"]);
+    }
+
+    #[test]
+    fn test_value_dict_iter() {
+        let dict = Value::parse(b"d1:ai1e1:b1:ce").unwrap().as_dict().unwrap();
+        let mut it = dict.iter();
+        assert_eq!(it.next(), Some((&b"a"[..], Value::Int(1))));
+        assert_eq!(it.next(), Some((&b"b"[..], Value::Bytes(b"c"))));
+        assert_eq!(it.next(), None);
+        assert_eq!(dict.len(), 2);
+    }
+
+    #[test]
+    fn test_value_raw_dict() {
+        let input = b"d4:infod6:lengthi1eee";
+        let info = Value::parse(input).unwrap().get("info").unwrap();
+        assert_eq!(info.as_dict().unwrap().as_raw(), b"d6:lengthi1ee");
+    }
+
+    #[test]
+    fn test_value_from_parser_leaves_rest() {
+        let mut p = BencodeParser::new(b"li1eei2e");
+        let v = Value::from_parser(&mut p).unwrap();
+        assert_eq!(v.as_list().unwrap().len(), 1);
+        assert_eq!(p.parse_int().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_value_errors() {
+        assert!(matches!(
+            Value::parse(b"").map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedEof)
+        ));
+        assert!(matches!(
+            Value::parse(b"x").map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
+        assert!(matches!(
+            Value::parse(b"li1e").map_err(|e| e.kind()),
+            Err(ErrorKind::UnexpectedEof)
+        ));
+        assert!(matches!(
+            Value::parse(b"d3:keye").map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
+        // trailing data
+        assert!(matches!(
+            Value::parse(b"i1ei2e").map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidSyntax)
+        ));
+    }
+}
@@ -0,0 +1,188 @@
+use alloc::string::String;
+use embassy_time::{Duration, Instant};
+
+use crate::core::{
+    InfoHash, PeerId,
+    tracker::{AnnounceEvent, TrackerRequest, TrackerResponse},
+};
+
+/// How long to wait after no tracker answered before announcing again.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+/// The shortest interval between regular announces, whatever the tracker asks for.
+pub(crate) const DEFAULT_MIN_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// When to announce to the trackers and what to tell them.
+///
+/// The first announce is `started`, then one follows every `interval` the tracker asked for,
+/// with the bytes transferred so far. `completed` is due right away once nothing is left. If no
+/// tracker answers, the announce is tried again after a minute.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "log", derive(Debug))]
+pub struct AnnounceSchedule {
+    uploaded: u64,
+    downloaded: u64,
+    left: u64,
+    /// sent with the next announce, stays pending until a tracker answered
+    event: Option<AnnounceEvent>,
+    next_announce: Instant,
+    /// what the last tracker told us to send back
+    tracker_id: Option<String>,
+    /// a tracker asking for `interval` 0 would have us announce after every piece
+    min_interval: Duration,
+}
+
+impl AnnounceSchedule {
+    pub(crate) const fn new(left: u64) -> Self {
+        Self {
+            uploaded: 0,
+            downloaded: 0,
+            left,
+            event: Some(AnnounceEvent::Started),
+            next_announce: Instant::MIN,
+            tracker_id: None,
+            min_interval: DEFAULT_MIN_ANNOUNCE_INTERVAL,
+        }
+    }
+
+    /// The shortest interval between regular announces.
+    #[inline]
+    pub(crate) const fn with_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    #[inline]
+    pub const fn uploaded(&self) -> u64 {
+        self.uploaded
+    }
+
+    #[inline]
+    pub const fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    #[inline]
+    pub const fn left(&self) -> u64 {
+        self.left
+    }
+
+    /// The event of the next announce.
+    #[inline]
+    pub const fn event(&self) -> Option<AnnounceEvent> {
+        self.event
+    }
+
+    #[inline]
+    pub fn tracker_id(&self) -> Option<&str> {
+        self.tracker_id.as_deref()
+    }
+
+    /// When the tracker expects to hear from us again.
+    #[inline]
+    pub const fn next_announce(&self) -> Instant {
+        self.next_announce
+    }
+
+    /// Whether it's time to announce, an event makes it due right away.
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.next_announce
+    }
+
+    /// The next announce, with the event (if any) and the bytes transferred so far.
+    pub(crate) fn request<'a>(
+        &'a self,
+        info_hash: &'a InfoHash,
+        peer_id: &'a PeerId,
+        port: u16,
+    ) -> TrackerRequest<'a> {
+        TrackerRequest::new(info_hash, peer_id, port, self.left)
+            .with_transferred(self.uploaded, self.downloaded)
+            .with_event(self.event)
+            .with_tracker_id(self.tracker_id())
+    }
+
+    /// Counts the piece data received, the download is completed once nothing is left.
+    pub(crate) fn add_downloaded(&mut self, bytes: u64) {
+        self.downloaded += bytes;
+        if self.left > 0 {
+            self.left = self.left.saturating_sub(bytes);
+            if self.left == 0 {
+                self.event = Some(AnnounceEvent::Completed);
+                self.next_announce = Instant::MIN;
+            }
+        }
+    }
+
+    /// A tracker answered at `now`, the event was delivered.
+    pub(crate) fn announced(&mut self, response: &TrackerResponse, now: Instant) {
+        self.event = None;
+        let interval = Duration::from_secs(response.next_announce_in().into());
+        self.next_announce = now + interval.max(self.min_interval);
+        if response.tracker_id.is_some() {
+            self.tracker_id.clone_from(&response.tracker_id);
+        }
+    }
+
+    /// No tracker answered at `now`, the event stays pending.
+    pub(crate) fn failed(&mut self, now: Instant) {
+        self.next_announce = now + RETRY_DELAY;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(interval: u32, tracker_id: Option<&str>) -> TrackerResponse {
+        TrackerResponse {
+            interval,
+            min_interval: None,
+            peers: alloc::vec::Vec::new(),
+            warning_message: None,
+            tracker_id: tracker_id.map(String::from),
+            complete: None,
+            incomplete: None,
+        }
+    }
+
+    #[test]
+    fn test_announce_schedule() {
+        let start = Instant::from_secs(100);
+        let mut schedule = AnnounceSchedule::new(20);
+        assert!(schedule.is_due(start));
+        assert_eq!(schedule.event(), Some(AnnounceEvent::Started));
+
+        // the tracker's interval, unless it's too short
+        schedule.announced(&response(0, None), start);
+        assert!(!schedule.is_due(start + DEFAULT_MIN_ANNOUNCE_INTERVAL - Duration::from_secs(1)));
+        assert!(schedule.is_due(start + DEFAULT_MIN_ANNOUNCE_INTERVAL));
+
+        schedule.announced(&response(60, Some("abc")), start);
+        assert_eq!(schedule.event(), None);
+        assert!(!schedule.is_due(start + Duration::from_secs(59)));
+        assert!(schedule.is_due(start + Duration::from_secs(60)));
+
+        // a tracker without an id doesn't make us forget the last one
+        schedule.announced(&response(60, None), start);
+        assert_eq!(schedule.tracker_id(), Some("abc"));
+
+        schedule.add_downloaded(15);
+        assert_eq!(schedule.event(), None);
+        assert_eq!((schedule.downloaded(), schedule.left()), (15, 5));
+        // more than what's left, e.g. a block that was received twice
+        schedule.add_downloaded(10);
+        assert_eq!((schedule.downloaded(), schedule.left()), (25, 0));
+        assert_eq!(schedule.event(), Some(AnnounceEvent::Completed));
+        assert!(schedule.is_due(start));
+
+        // no tracker answered, `completed` is sent with the retry
+        schedule.failed(start);
+        assert!(!schedule.is_due(start + RETRY_DELAY - Duration::from_secs(1)));
+        assert!(schedule.is_due(start + RETRY_DELAY));
+        assert_eq!(schedule.event(), Some(AnnounceEvent::Completed));
+
+        schedule.announced(&response(60, None), start);
+        schedule.add_downloaded(1);
+        assert_eq!(schedule.event(), None);
+    }
+}
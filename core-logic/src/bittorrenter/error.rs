@@ -1,9 +1,18 @@
 //! Error Types
 
+use alloc::string::String;
 use embedded_nal_async::Dns;
 use embedded_sdmmc::BlockDevice;
 
-use crate::{TcpConnector, fs::VolumeMgr, peer::handshake::HandshakeError};
+#[cfg(feature = "tls")]
+use crate::net::tls::TlsError;
+use crate::{
+    TcpConnector, UdpConnector,
+    core::{magnet::MagnetError, metainfo::ValidationError},
+    fs::VolumeMgr,
+    net::http::HttpError,
+    peer::{handshake::HandshakeError, metadata::MetadataError},
+};
 
 /// Errors that can occur during BitTorrent operations.
 ///
@@ -12,17 +21,58 @@ use crate::{TcpConnector, fs::VolumeMgr, peer::handshake::HandshakeError};
 #[defmt_or_log::derive_format_or_debug]
 pub enum BitTorrenterError<NET, V>
 where
-    NET: TcpConnector + Dns,
+    NET: TcpConnector + UdpConnector + Dns,
     V: VolumeMgr,
 {
     /// DNS resolution failed (e.g., tracker hostname not found).
     DnsError(<NET as Dns>::Error),
     /// TCP connection or I/O failed.
     TcpError(<NET as TcpConnector>::Error),
+    /// The HTTP tracker's response is broken, incomplete or not `200 OK`.
+    HttpFailed(HttpError<<NET as TcpConnector>::Error>),
+    /// The TLS handshake with the HTTPS tracker failed, or its response is broken, incomplete or
+    /// not `200 OK`.
+    #[cfg(feature = "tls")]
+    HttpsFailed(HttpError<TlsError>),
+    /// The tracker is an HTTPS tracker, but no `TlsSettings` were given.
+    #[cfg(feature = "tls")]
+    TlsNotConfigured,
+    /// The torrent (or magnet link) has no trackers, e.g. because it's meant for DHT only.
+    NoTrackers,
+    /// The tracker's URL is malformed, or it's a UDP tracker without a port.
+    InvalidUrl(&'static str),
+    /// The tracker is neither an HTTP(S) nor a UDP tracker, or an HTTPS tracker without the `tls`
+    /// feature.
+    UnsupportedScheme,
+    /// The HTTP tracker kept redirecting.
+    TooManyRedirects,
+    /// The announce or scrape parameters don't fit into the query of the tracker's URL
+    /// (512 bytes), e.g. because of a long passkey.
+    QueryTooLong,
+    /// The tracker has an IPv6 address, only IPv4 is supported.
+    Ipv6Unsupported,
+    /// Opening a UDP socket or sending/receiving a datagram failed.
+    UdpError(<NET as UdpConnector>::Error),
     /// File system operation failed.
     FsError(embedded_sdmmc::Error<<<V as VolumeMgr>::BlockDevice as BlockDevice>::Error>),
     /// Failed to parse the tracker's response (e.g., invalid bencoding).
     TrackerResponseParseError(bencode::Error),
+    /// The tracker refused the request (`failure reason`), with a human-readable message.
+    TrackerFailure(#[cfg_attr(feature = "defmt", defmt(Debug2Format))] String),
+    /// The UDP tracker didn't answer, even after all retransmissions.
+    TrackerTimeout,
+    /// The tracker's URL is invalid or the tracker can't be scraped (BEP 48).
+    ScrapeUnsupported,
     /// Failed to perform the BitTorrent handshake with a peer.
     HandshakeFailed(HandshakeError<NET>),
+    /// The torrent is well-formed, but can't be downloaded (e.g., a piece length of zero).
+    InvalidTorrent(ValidationError),
+    /// The `.torrent` can't be built from the magnet link (e.g., the buffer is too small).
+    InvalidMagnet(MagnetError),
+    /// No peer could be reached (or the tracker didn't know any).
+    NoPeers,
+    /// The peer closed the connection before the download was finished.
+    PeerDisconnected,
+    /// Downloading the metadata of a magnet link failed, this is the error of the last peer.
+    MetadataFailed(MetadataError<NET>),
 }
@@ -1,20 +1,33 @@
+pub mod announce;
 pub mod error;
+pub mod peer_pool;
 pub mod states;
+use alloc::boxed::Box;
+use embassy_time::Duration;
 use embedded_nal_async::Dns;
 
-use crate::bittorrenter::states::RequestingTracker;
+use crate::bittorrenter::{
+    announce::DEFAULT_MIN_ANNOUNCE_INTERVAL, peer_pool::DEFAULT_MAX_PEERS,
+    states::RequestingTracker,
+};
 use crate::net::buffer::SocketBuffers;
+#[cfg(feature = "tls")]
+use crate::net::tls::TlsSettings;
+use crate::net::udp_tracker_requester::{DEFAULT_UDP_TIMEOUT, UdpConnectionId};
 use crate::{
-    TcpConnector,
+    Rng, TcpConnector, UdpConnector,
+    core::PeerId,
     fs::{FileSystem, VolumeMgr},
+    peer::generate_peer_id,
+    rng::XorShift,
 };
 
 /// The main BitTorrent client that coordinates networking and file system access.
 ///
 /// # Type Parameters
 ///
-/// * `NET` - Network implementation providing DNS resolution and TCP connections.
-///   Must implement `TcpConnector` (caller-provided buffers) and `Dns`.
+/// * `NET` - Network implementation providing DNS resolution, TCP connections and UDP sockets.
+///   Must implement `TcpConnector`, `UdpConnector` (both with caller-provided buffers) and `Dns`.
 /// * `V` - Volume manager for file system operations (reading/writing torrent data).
 /// * `RX` - Socket receive buffer size in bytes (default: 4096).
 /// * `TX` - Socket transmit buffer size in bytes (default: 1024).
@@ -48,10 +61,10 @@ pub struct BitTorrenter<
     const RX: usize = 4096,
     const TX: usize = 1024,
 > where
-    NET: TcpConnector + Dns,
+    NET: TcpConnector + UdpConnector + Dns,
     V: VolumeMgr,
 {
-    /// Network implementation for DNS and TCP.
+    /// Network implementation for DNS, TCP and UDP.
     pub(crate) net: NET,
     /// File system for torrent data.
     pub fs: FileSystem<V>,
@@ -59,17 +72,40 @@ pub struct BitTorrenter<
     /// Only one TCP connection can be active at a time.
     pub(crate) socket_buffers: SocketBuffers<RX, TX>,
     /// Unique identifier for this client (sent to trackers and peers).
-    pub(crate) peer_id: [u8; 20],
+    pub(crate) peer_id: PeerId,
+    /// Sent to trackers (`key`), so they recognize us when our IP address changes.
+    pub(crate) key: u32,
     /// Port number this client listens on for incoming peer connections.
     pub(crate) port: u16,
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+    pub(crate) rng: Box<dyn Rng>,
+    /// How many peers are kept, also asked for from the trackers (`numwant`).
+    pub(crate) max_peers: usize,
+    /// The connection id of the last UDP tracker, reused while it's valid.
+    pub(crate) udp_connection: Option<UdpConnectionId>,
+    /// How long to wait for a UDP tracker before the first retransmission.
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+    pub(crate) udp_timeout: Duration,
+    /// The shortest interval between regular announces, whatever the trackers ask for.
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+    pub(crate) min_announce_interval: Duration,
+    /// How to talk to HTTPS trackers, they fail without it.
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<TlsSettings>,
     pub(crate) state: STATE,
 }
 
 impl<NET, V, STATE, const RX: usize, const TX: usize> BitTorrenter<NET, V, STATE, RX, TX>
 where
-    NET: TcpConnector + Dns,
+    NET: TcpConnector + UdpConnector + Dns,
     V: VolumeMgr,
 {
+    /// Our id, sent to trackers and peers.
+    #[inline]
+    pub const fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
     /// Get mutable access to the file system.
     #[inline]
     pub const fn fs(&mut self) -> &mut FileSystem<V> {
@@ -79,29 +115,81 @@ where
 
 impl<NET, V, const RX: usize, const TX: usize> BitTorrenter<NET, V, RequestingTracker, RX, TX>
 where
-    NET: TcpConnector + Dns,
+    NET: TcpConnector + UdpConnector + Dns,
     V: VolumeMgr,
 {
     /// Create a new BitTorrent client.
     ///
     /// # Arguments
     ///
-    /// * `net` - Network implementation (must implement `TcpConnector + Dns`)
+    /// * `net` - Network implementation (must implement `TcpConnector + UdpConnector + Dns`)
     /// * `fs` - File system for reading .torrent files and writing downloaded data
     ///
     /// # Note
     ///
     /// Socket buffers are allocated internally based on the const generic
     /// parameters `RX` and `TX`. Default sizes are 4KB receive, 1KB transmit.
-    #[inline]
-    pub const fn new(net: NET, fs: FileSystem<V>) -> Self {
+    ///
+    /// The peer id and the tracker key are random, from a clock-seeded [`XorShift`] unless
+    /// another [`Rng`] is given with [`Self::with_rng`].
+    pub fn new(net: NET, fs: FileSystem<V>) -> Self {
+        let mut rng = XorShift::from_clock();
         Self {
             net,
             fs,
             socket_buffers: SocketBuffers::new(),
-            peer_id: [0u8; 20],
+            peer_id: generate_peer_id(&mut rng),
+            key: rng.next_u32(),
             port: 6881,
+            rng: Box::new(rng),
+            max_peers: DEFAULT_MAX_PEERS,
+            udp_connection: None,
+            udp_timeout: DEFAULT_UDP_TIMEOUT,
+            min_announce_interval: DEFAULT_MIN_ANNOUNCE_INTERVAL,
+            #[cfg(feature = "tls")]
+            tls: None,
             state: RequestingTracker,
         }
     }
+
+    /// How many peers to keep (and ask the trackers for), 10 by default.
+    /// More peers cost a little memory, but the download doesn't stall as easily.
+    #[inline]
+    pub const fn with_max_peers(mut self, max_peers: usize) -> Self {
+        self.max_peers = max_peers;
+        self
+    }
+
+    /// How long to wait for a UDP tracker before sending the request again, 15 seconds by default
+    /// (BEP 15). The timeout doubles with every retransmission.
+    #[inline]
+    pub const fn with_udp_timeout(mut self, timeout: Duration) -> Self {
+        self.udp_timeout = timeout;
+        self
+    }
+
+    /// The shortest interval between regular announces, 1 minute by default. A tracker asking
+    /// for less (e.g. `interval` 0) would make us reconnect to a peer after every piece.
+    #[inline]
+    pub const fn with_min_announce_interval(mut self, interval: Duration) -> Self {
+        self.min_announce_interval = interval;
+        self
+    }
+
+    /// Where the random numbers come from, e.g. the hardware RNG. A new peer id and tracker key
+    /// are generated from it.
+    pub fn with_rng(mut self, mut rng: impl Rng + 'static) -> Self {
+        self.peer_id = generate_peer_id(&mut rng);
+        self.key = rng.next_u32();
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Enables HTTPS trackers.
+    #[cfg(feature = "tls")]
+    #[inline]
+    pub fn with_tls(mut self, tls: TlsSettings) -> Self {
+        self.tls = Some(tls);
+        self
+    }
 }
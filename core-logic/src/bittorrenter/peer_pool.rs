@@ -0,0 +1,93 @@
+use alloc::vec::Vec;
+use core::net::SocketAddr;
+
+/// How many peers are kept by default, see [`BitTorrenter::with_max_peers`](crate::BitTorrenter::with_max_peers).
+pub const DEFAULT_MAX_PEERS: usize = 10;
+
+/// The peers we know about, without duplicates and up to a fixed capacity.
+///
+/// Filled from the responses of the trackers, peers that turn out to be dead can be removed
+/// to make room for the ones of the next announce.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "log", derive(Debug))]
+pub struct PeerPool {
+    peers: Vec<SocketAddr>,
+    capacity: usize,
+}
+
+impl PeerPool {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            peers: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds the peer unless it's known already or the pool is full.
+    /// Returns whether it was added.
+    pub fn add(&mut self, peer: SocketAddr) -> bool {
+        if self.is_full() || self.peers.contains(&peer) {
+            return false;
+        }
+        self.peers.push(peer);
+        true
+    }
+
+    /// Adds the peers while there is room, returns how many were new.
+    pub fn extend(&mut self, peers: impl IntoIterator<Item = SocketAddr>) -> usize {
+        peers.into_iter().filter(|peer| self.add(*peer)).count()
+    }
+
+    /// Forgets the peer, e.g. because it can't be reached.
+    pub fn remove(&mut self, peer: &SocketAddr) {
+        self.peers.retain(|p| p != peer);
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[SocketAddr] {
+        &self.peers
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.peers.len() >= self.capacity
+    }
+
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_pool() {
+        let a: SocketAddr = "10.0.0.1:6881".parse().unwrap();
+        let b: SocketAddr = "10.0.0.2:6881".parse().unwrap();
+        let c: SocketAddr = "[::1]:6881".parse().unwrap();
+
+        let mut pool = PeerPool::new(2);
+        assert_eq!(pool.extend([a, a, b, c]), 2);
+        assert_eq!(pool.as_slice(), [a, b]);
+        assert!(pool.is_full());
+        assert!(!pool.add(c));
+
+        // a dead peer makes room for a new one
+        pool.remove(&a);
+        assert!(pool.add(c));
+        assert_eq!(pool.as_slice(), [b, c]);
+    }
+}
@@ -1,34 +1,39 @@
-use embedded_sdmmc::ShortFileName;
-use heapless::Vec;
-
-use crate::{MetaInfoFile, core::InfoHash};
+use crate::{
+    MetaInfoFile,
+    bittorrenter::{announce::AnnounceSchedule, peer_pool::PeerPool},
+    core::{InfoHash, tracker_tiers::TrackerTiers},
+    fs::FileMap,
+};
 
 pub struct RequestingTracker;
 
 #[cfg_attr(feature = "log", derive(Debug))]
 pub struct Downloading {
-    peers: Vec<core::net::SocketAddrV4, 10>,
+    peers: PeerPool,
     info_hash: InfoHash,
     piece_length: u32,
-    total_length: u32,
-    name: ShortFileName,
+    files: FileMap,
     _piece_hashes: alloc::vec::Vec<InfoHash>,
+    /// the order the trackers answered in, for the next announce
+    trackers: TrackerTiers,
+    announce: AnnounceSchedule,
 }
 
 impl Downloading {
     pub(crate) fn new(
-        peers: Vec<core::net::SocketAddrV4, 10>,
+        peers: PeerPool,
         metainfo: &MetaInfoFile<'_>,
+        trackers: TrackerTiers,
+        announce: AnnounceSchedule,
     ) -> Self {
-        let name = ShortFileName::create_from_str(metainfo.info.name)
-            .unwrap_or_else(|_| ShortFileName::create_from_str("1").expect("is valid")); // TODO: maybe generate uuid or counting up
         Self {
             peers,
             info_hash: metainfo.info_hash,
             piece_length: metainfo.info.piece_length,
-            total_length: metainfo.info.length,
-            name,
+            files: FileMap::new(&metainfo.info),
             _piece_hashes: metainfo.info.pieces.to_vec(),
+            trackers,
+            announce,
         }
     }
 
@@ -38,21 +43,50 @@ impl Downloading {
     }
 
     #[inline]
-    pub const fn get_total_length(&self) -> u32 {
-        self.total_length
+    pub const fn get_trackers(&self) -> &TrackerTiers {
+        &self.trackers
+    }
+
+    /// When we announce next, and the bytes transferred so far.
+    #[inline]
+    pub const fn get_announce(&self) -> &AnnounceSchedule {
+        &self.announce
+    }
+
+    pub(crate) const fn get_announce_mut(&mut self) -> &mut AnnounceSchedule {
+        &mut self.announce
+    }
+
+    pub(crate) const fn get_trackers_mut(&mut self) -> &mut TrackerTiers {
+        &mut self.trackers
     }
 
     #[inline]
-    pub const fn get_name(&self) -> &ShortFileName {
-        &self.name
+    pub const fn get_total_length(&self) -> u64 {
+        self.files.total_length()
+    }
+
+    /// Where the torrent's files go on the filesystem.
+    #[inline]
+    pub const fn get_files(&self) -> &FileMap {
+        &self.files
+    }
+
+    /// The files and the announce schedule at once, to count the pieces while writing them.
+    pub(crate) const fn get_files_and_announce_mut(&mut self) -> (&FileMap, &mut AnnounceSchedule) {
+        (&self.files, &mut self.announce)
     }
 
     pub(crate) const fn get_piece_length(&self) -> u32 {
         self.piece_length
     }
 
-    pub(crate) fn get_peers(&self) -> &[core::net::SocketAddrV4] {
-        &self.peers
+    pub(crate) fn get_peers(&self) -> &[core::net::SocketAddr] {
+        self.peers.as_slice()
+    }
+
+    pub(crate) const fn get_peers_mut(&mut self) -> &mut PeerPool {
+        &mut self.peers
     }
 
     pub(crate) fn _get_pieces_hashes(&self) -> &[InfoHash] {
@@ -0,0 +1,220 @@
+//! Creating new torrents, e.g. to share files collected on the device.
+//!
+//! [`TorrentBuilder`] holds the settings, `TorrentBuilder::build` hashes the files on the
+//! filesystem and writes the bencoded `.torrent`.
+
+use alloc::{string::String, vec::Vec};
+use bencode::BencodeEncoder;
+
+use crate::core::InfoHash;
+
+/// Goes into `created by`.
+const CREATED_BY: &str = "minitorrent";
+
+/// Settings for a new (v1) torrent.
+///
+/// ```ignore
+/// let builder = TorrentBuilder::new("http://tracker.example.com/announce", 16 * 1024)
+///     .with_comment("sensor logs");
+/// let len = builder.build(&mut fs, "logs", &hasher, &mut buf).await?;
+/// fs.save_torrent("logs", &buf[..len]).await?;
+/// ```
+#[derive(Clone, Copy)]
+#[defmt_or_log::derive_format_or_debug]
+pub struct TorrentBuilder<'a> {
+    announce: &'a str,
+    pub(crate) piece_length: u32,
+    comment: Option<&'a str>,
+    creation_date: Option<i64>,
+    private: bool,
+}
+
+/// What the torrent is made of.
+#[cfg_attr(feature = "log", derive(Debug))]
+pub(crate) enum Source {
+    File {
+        length: u64,
+    },
+    /// the files below the directory, in the order they are hashed
+    Dir {
+        files: Vec<SourceFile>,
+    },
+}
+
+/// A file of a directory torrent.
+#[cfg_attr(feature = "log", derive(Debug))]
+pub(crate) struct SourceFile {
+    /// relative to the torrent's directory
+    pub(crate) path: Vec<String>,
+    pub(crate) length: u64,
+}
+
+impl<'a> TorrentBuilder<'a> {
+    /// `piece_length` should be a power of two, 16 KiB to a few MiB are common.
+    #[inline]
+    pub const fn new(announce: &'a str, piece_length: u32) -> Self {
+        Self {
+            announce,
+            piece_length,
+            comment: None,
+            creation_date: None,
+            private: false,
+        }
+    }
+
+    #[inline]
+    pub const fn with_comment(mut self, comment: &'a str) -> Self {
+        self.comment = Some(comment);
+        self
+    }
+
+    /// Seconds since the UNIX epoch, there is no clock to take it from.
+    #[inline]
+    pub const fn with_creation_date(mut self, creation_date: i64) -> Self {
+        self.creation_date = Some(creation_date);
+        self
+    }
+
+    /// Private torrents (BEP 27) are only shared through their trackers.
+    #[inline]
+    pub const fn with_private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    /// Writes the bencoded torrent into `buf` and returns its length,
+    /// `None` if it doesn't fit.
+    pub(crate) fn encode(
+        &self,
+        name: &str,
+        source: &Source,
+        pieces: &[InfoHash],
+        buf: &mut [u8],
+    ) -> Option<usize> {
+        let mut enc = BencodeEncoder::new(buf);
+        let mut torrent = enc.dict().ok()?;
+
+        torrent.entry("announce").ok()?.str(self.announce).ok()?;
+        if let Some(comment) = self.comment {
+            torrent.entry("comment").ok()?.str(comment).ok()?;
+        }
+        torrent.entry("created by").ok()?.str(CREATED_BY).ok()?;
+        if let Some(creation_date) = self.creation_date {
+            torrent
+                .entry("creation date")
+                .ok()?
+                .int(creation_date)
+                .ok()?;
+        }
+
+        let mut info = torrent.entry("info").ok()?.dict().ok()?;
+        match source {
+            Source::File { length } => {
+                info.entry("length").ok()?.int(*length as i64).ok()?;
+            }
+            Source::Dir { files } => {
+                let mut list = info.entry("files").ok()?.list().ok()?;
+                for file in files {
+                    let mut entry = list.item().dict().ok()?;
+                    entry.entry("length").ok()?.int(file.length as i64).ok()?;
+                    let mut path = entry.entry("path").ok()?.list().ok()?;
+                    for segment in &file.path {
+                        path.item().str(segment).ok()?;
+                    }
+                    path.finish().ok()?;
+                    entry.finish().ok()?;
+                }
+                list.finish().ok()?;
+            }
+        }
+        info.entry("name").ok()?.str(name).ok()?;
+        info.entry("piece length")
+            .ok()?
+            .int(self.piece_length as i64)
+            .ok()?;
+        info.entry("pieces")
+            .ok()?
+            .bytes(pieces.as_flattened())
+            .ok()?;
+        if self.private {
+            info.entry("private").ok()?.int(1).ok()?;
+        }
+        info.finish().ok()?;
+        torrent.finish().ok()?;
+
+        Some(enc.written())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MetaInfoFile, core::metainfo::FileLayout};
+
+    #[test]
+    fn test_encode_single_file() {
+        let builder = TorrentBuilder::new("http://tracker.com/announce", 16)
+            .with_comment("logs")
+            .with_creation_date(1700000000);
+        let pieces = [[1; 20], [2; 20]];
+
+        let mut buf = [0u8; 512];
+        let len = builder
+            .encode("log.txt", &Source::File { length: 20 }, &pieces, &mut buf)
+            .unwrap();
+        let torrent = MetaInfoFile::parse(&buf[..len]).unwrap();
+        assert!(torrent.validate().is_ok());
+        assert_eq!(torrent.announce, Some("http://tracker.com/announce"));
+        assert_eq!(torrent.comment, Some("logs"));
+        assert_eq!(torrent.created_by, Some(CREATED_BY));
+        assert_eq!(torrent.creation_date, Some(1700000000));
+        assert_eq!(torrent.info.name, "log.txt");
+        assert_eq!(torrent.info.piece_length, 16);
+        assert_eq!(torrent.info.pieces, pieces);
+        assert!(matches!(
+            torrent.info.layout,
+            FileLayout::SingleFile { length: 20 }
+        ));
+        assert!(!torrent.info.private);
+
+        // doesn't fit
+        assert_eq!(
+            builder.encode(
+                "log.txt",
+                &Source::File { length: 20 },
+                &pieces,
+                &mut buf[..100]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_encode_dir() {
+        let builder = TorrentBuilder::new("http://tracker.com/announce", 16).with_private(true);
+        let files = alloc::vec![
+            SourceFile {
+                path: alloc::vec!["a.csv".into()],
+                length: 10,
+            },
+            SourceFile {
+                path: alloc::vec!["2024".into(), "b.csv".into()],
+                length: 5,
+            },
+        ];
+
+        let mut buf = [0u8; 512];
+        let len = builder
+            .encode("logs", &Source::Dir { files }, &[[0; 20]], &mut buf)
+            .unwrap();
+        let torrent = MetaInfoFile::parse(&buf[..len]).unwrap();
+        assert!(torrent.validate().is_ok());
+        assert!(torrent.info.private);
+        assert_eq!(torrent.info.total_length(), 15);
+        let FileLayout::MultiFile { files } = &torrent.info.layout else {
+            panic!("expected a multi-file torrent");
+        };
+        let paths: Vec<Vec<_>> = files.iter().map(|f| f.path.segments().collect()).collect();
+        assert_eq!(paths, [alloc::vec!["a.csv"], alloc::vec!["2024", "b.csv"]]);
+    }
+}
@@ -0,0 +1,235 @@
+//! Magnet links: `magnet:?xt=urn:btih:<info hash>&dn=<name>&tr=<tracker>`.
+//!
+//! They only carry the info hash, the info dict has to be downloaded from peers (BEP 9).
+
+use alloc::{string::String, vec::Vec};
+use bencode::BencodeEncoder;
+
+use crate::{core::InfoHash, net::percent_decode};
+
+/// A parsed magnet link, only v1 info hashes (`urn:btih:`) are supported.
+#[derive(PartialEq)]
+#[cfg_attr(feature = "log", derive(Debug))]
+pub struct MagnetLink {
+    pub info_hash: InfoHash,
+    /// `dn`: a name to show until the metadata is downloaded
+    pub display_name: Option<String>,
+    /// `tr`: the trackers in the order they were given
+    pub trackers: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub enum MagnetError {
+    /// doesn't start with `magnet:?`
+    NotAMagnetLink,
+    /// there is no `xt=urn:btih:` parameter
+    MissingInfoHash,
+    /// the info hash is neither 40 hex nor 32 base32 characters
+    InvalidInfoHash,
+    /// a parameter isn't valid percent-encoded utf-8
+    InvalidEncoding,
+    /// the buffer for the `.torrent` is too small
+    BufferTooSmall,
+}
+
+impl MagnetLink {
+    /// Parses a magnet link, surrounding whitespace (e.g. a trailing newline) is ignored.
+    pub fn parse(uri: &str) -> Result<Self, MagnetError> {
+        let query = uri
+            .trim()
+            .strip_prefix("magnet:?")
+            .ok_or(MagnetError::NotAMagnetLink)?;
+
+        let mut info_hash = None;
+        let mut display_name = None;
+        let mut trackers = Vec::new();
+
+        for param in query.split('&') {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let decode = |value| percent_decode(value).ok_or(MagnetError::InvalidEncoding);
+            match key {
+                // there may be more than one `xt` (e.g. `urn:btmh:` for v2), the first btih counts
+                "xt" if info_hash.is_none() => {
+                    if let Some(hash) = value.strip_prefix("urn:btih:") {
+                        info_hash = Some(decode_info_hash(hash)?);
+                    }
+                }
+                "dn" => display_name = Some(decode(value)?),
+                // some clients number them: `tr.1=...&tr.2=...`
+                key if key == "tr" || key.starts_with("tr.") => trackers.push(decode(value)?),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            info_hash: info_hash.ok_or(MagnetError::MissingInfoHash)?,
+            display_name,
+            trackers,
+        })
+    }
+
+    /// Writes a `.torrent` with the downloaded `info` dict into `buf`, the trackers of the link
+    /// become `announce` and `announce-list`. Returns the number of bytes written.
+    pub fn to_torrent(&self, info: &[u8], buf: &mut [u8]) -> Result<usize, MagnetError> {
+        let too_small = |_| MagnetError::BufferTooSmall;
+        let mut enc = BencodeEncoder::new(buf);
+        let mut dict = enc.dict().map_err(too_small)?;
+
+        if let Some(first) = self.trackers.first() {
+            dict.entry("announce")
+                .map_err(too_small)?
+                .str(first)
+                .map_err(too_small)?;
+        }
+        if self.trackers.len() > 1 {
+            // the trackers of a magnet link aren't grouped, so each one gets its own tier
+            let mut tiers = dict
+                .entry("announce-list")
+                .map_err(too_small)?
+                .list()
+                .map_err(too_small)?;
+            for tracker in &self.trackers {
+                let mut tier = tiers.item().list().map_err(too_small)?;
+                tier.item().str(tracker).map_err(too_small)?;
+                tier.finish().map_err(too_small)?;
+            }
+            tiers.finish().map_err(too_small)?;
+        }
+        dict.entry("info")
+            .map_err(too_small)?
+            .raw(info)
+            .map_err(too_small)?;
+        dict.finish().map_err(too_small)?;
+
+        Ok(enc.written())
+    }
+}
+
+/// Decodes a hex (40 characters) or base32 (32 characters) info hash.
+fn decode_info_hash(hash: &str) -> Result<InfoHash, MagnetError> {
+    let mut info_hash = [0u8; 20];
+
+    match hash.len() {
+        40 => {
+            let hex = |c: u8| (c as char).to_digit(16).ok_or(MagnetError::InvalidInfoHash);
+            for (byte, pair) in info_hash.iter_mut().zip(hash.as_bytes().chunks_exact(2)) {
+                *byte = (hex(pair[0])? << 4 | hex(pair[1])?) as u8;
+            }
+        }
+        32 => {
+            // 5 bits per character, RFC 4648 alphabet
+            let mut bits = 0u64;
+            let mut len = 0;
+            let mut out = info_hash.iter_mut();
+            for c in hash.bytes() {
+                let value = match c.to_ascii_uppercase() {
+                    c @ b'A'..=b'Z' => c - b'A',
+                    c @ b'2'..=b'7' => c - b'2' + 26,
+                    _ => return Err(MagnetError::InvalidInfoHash),
+                };
+                bits = bits << 5 | value as u64;
+                len += 5;
+                if len >= 8 {
+                    len -= 8;
+                    *out.next().expect("32 * 5 bits are 20 bytes") = (bits >> len) as u8;
+                }
+            }
+        }
+        _ => return Err(MagnetError::InvalidInfoHash),
+    }
+
+    Ok(info_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MetaInfoFile;
+
+    const HASH: [u8; 20] = [
+        0xd6, 0x9f, 0x91, 0xe6, 0xb2, 0xae, 0x4c, 0x54, 0x24, 0x68, 0xd1, 0x07, 0x3a, 0x71, 0xd4,
+        0xea, 0x13, 0x87, 0x9a, 0x7f,
+    ];
+
+    #[test]
+    fn test_parse_magnet_link() {
+        let magnet = MagnetLink::parse(
+            "magnet:?xt=urn:btih:d69f91e6b2ae4c542468d1073a71d4ea13879a7f&dn=sample+file.txt\
+             &tr=http%3A%2F%2Ftracker.com%2Fannounce&tr=udp%3A%2F%2Fother.org%3A6969\n",
+        )
+        .unwrap();
+        assert_eq!(magnet.info_hash, HASH);
+        assert_eq!(magnet.display_name.as_deref(), Some("sample file.txt"));
+        assert_eq!(
+            magnet.trackers,
+            ["http://tracker.com/announce", "udp://other.org:6969"]
+        );
+
+        // base32, no name or trackers
+        let magnet = MagnetLink::parse("magnet:?xt=urn:btih:22PZDZVSVZGFIJDI2EDTU4OU5IJYPGT7");
+        assert_eq!(magnet.unwrap().info_hash, HASH);
+        let magnet = MagnetLink::parse("magnet:?xt=urn:btih:22pzdzvsvzgfijdi2edtu4ou5ijypgt7");
+        let magnet = magnet.unwrap();
+        assert_eq!(magnet.info_hash, HASH);
+        assert_eq!(magnet.display_name, None);
+        assert!(magnet.trackers.is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_magnet_link() {
+        assert_eq!(
+            MagnetLink::parse("http://example.com"),
+            Err(MagnetError::NotAMagnetLink)
+        );
+        assert_eq!(
+            MagnetLink::parse("magnet:?dn=foo&tr=bar"),
+            Err(MagnetError::MissingInfoHash)
+        );
+        assert_eq!(
+            MagnetLink::parse("magnet:?xt=urn:btih:d69f91e6"),
+            Err(MagnetError::InvalidInfoHash)
+        );
+        assert_eq!(
+            MagnetLink::parse("magnet:?xt=urn:btih:22PZDZVSVZGFIJDI2EDTU4OU5IJYPG01"),
+            Err(MagnetError::InvalidInfoHash)
+        );
+        assert_eq!(
+            MagnetLink::parse(
+                "magnet:?xt=urn:btih:d69f91e6b2ae4c542468d1073a71d4ea13879a7f&dn=%ff"
+            ),
+            Err(MagnetError::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    fn test_to_torrent() {
+        let magnet = MagnetLink {
+            info_hash: HASH,
+            display_name: None,
+            trackers: alloc::vec!["http://a.com".into(), "http://b.com".into()],
+        };
+        let info = b"d6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:e";
+
+        let mut buf = [0u8; 256];
+        let len = magnet.to_torrent(info, &mut buf).unwrap();
+        let torrent = MetaInfoFile::parse(&buf[..len]).unwrap();
+        assert_eq!(torrent.announce, Some("http://a.com"));
+        let tiers: Vec<Vec<_>> = torrent
+            .announce_list
+            .unwrap()
+            .tiers()
+            .map(|tier| tier.urls().collect())
+            .collect();
+        assert_eq!(tiers, [["http://a.com"], ["http://b.com"]]);
+        assert_eq!(
+            torrent.info_hash,
+            sha1_smol::Sha1::from(info).digest().bytes()
+        );
+
+        assert_eq!(
+            magnet.to_torrent(info, &mut buf[..32]),
+            Err(MagnetError::BufferTooSmall)
+        );
+    }
+}
@@ -1,114 +1,627 @@
-use crate::{DEFAULT_TRACKER, core::InfoHash};
-use bencode::{BencodeParser, Error, Result};
+use alloc::vec::Vec;
+use sha2::Digest as _;
 
+use crate::core::{InfoHash, InfoHashV2};
+use bencode::{BencodeDecode, BencodeParser, Dict, Error, ErrorKind, List, Result, Value};
+
+/// A parsed `.torrent` file, see BEP 3.
 #[derive(PartialEq)]
 #[defmt_or_log::derive_format_or_debug]
 pub struct MetaInfoFile<'a> {
-    pub announce: &'a str,
+    /// the tracker's URL, torrents using only `announce-list` may not have one
+    pub announce: Option<&'a str>,
+    /// tiers of trackers (BEP 12)
+    pub announce_list: Option<AnnounceList<'a>>,
+    /// seconds since the UNIX epoch
+    pub creation_date: Option<i64>,
+    pub comment: Option<&'a str>,
+    pub created_by: Option<&'a str>,
+    /// the encoding of the strings in `info`, usually `UTF-8`
+    pub encoding: Option<&'a str>,
+    /// web seeds (BEP 19)
+    pub url_list: Option<UrlList<'a>>,
+    /// v2 and hybrid: the hashes of the pieces of every file larger than a piece
+    pub piece_layers: Option<PieceLayers<'a>>,
     pub info: Info<'a>,
-    pub info_hash: [u8; 20],
+    /// identifies the torrent in handshakes and tracker requests: the SHA-1 of the info dict,
+    /// for v2-only torrents the SHA-256 truncated to 20 bytes
+    pub info_hash: InfoHash,
+    /// v2 and hybrid: the SHA-256 of the info dict
+    pub info_hash_v2: Option<InfoHashV2>,
+}
+
+/// Which version(s) of the protocol a torrent supports.
+#[derive(Clone, Copy, PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub enum Version {
+    V1,
+    /// BEP 52
+    V2,
+    /// both v1 and v2 metadata for the same files, usable in both swarms
+    Hybrid,
 }
 
 #[derive(PartialEq)]
 #[defmt_or_log::derive_format_or_debug]
 pub struct Info<'a> {
     pub piece_length: u32,
+    /// the file name for single-file torrents, the directory name for multi-file ones
     pub name: &'a str,
+    /// v1 and hybrid: the SHA-1 hashes of the pieces, empty for v2-only torrents
     pub pieces: &'a [InfoHash],
-    pub length: u32,
+    pub layout: FileLayout<'a>,
+    /// 2 for v2 and hybrid torrents, 1 otherwise
+    pub meta_version: u8,
+    /// v2 and hybrid: the files as a tree of directories
+    pub file_tree: Option<FileTree<'a>>,
+    /// peers may only be found through the tracker, no DHT or PEX (BEP 27)
+    pub private: bool,
+    /// distinguishes otherwise identical torrents of different trackers
+    pub source: Option<&'a str>,
+}
+
+/// How the torrent's data is split up into files.
+/// Pieces are cut from all files concatenated in the given order.
+#[derive(PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub enum FileLayout<'a> {
+    /// a single file called `name`
+    SingleFile { length: u64 },
+    /// a directory called `name` containing the files
+    MultiFile { files: Files<'a> },
+    /// v2-only: a directory called `name` containing the files of the file tree,
+    /// each of them starting at a piece boundary
+    Tree { tree: FileTree<'a> },
+}
+
+/// The `files` list of a multi-file torrent. It was validated while parsing, so iterating can't fail.
+#[derive(PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub struct Files<'a>(List<'a>);
+
+/// An entry of the `files` list.
+#[derive(PartialEq, BencodeDecode)]
+#[defmt_or_log::derive_format_or_debug]
+pub struct FileEntry<'a> {
+    pub length: u64,
+    pub path: FilePath<'a>,
+    /// flags, e.g. `p` for padding files (BEP 47)
+    pub attr: Option<&'a str>,
+}
+
+/// The path of a file relative to the torrent's directory, split into its segments.
+/// Segments are non-empty utf-8 strings, `.`, `..` and separators are rejected while parsing.
+#[derive(PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub struct FilePath<'a>(List<'a>);
+
+/// The `announce-list` of a torrent: tiers of tracker URLs, tried one tier after the other.
+/// It was validated to be a list of lists of strings while parsing.
+#[derive(PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub struct AnnounceList<'a>(List<'a>);
+
+/// A tier of the `announce-list`, its trackers are equivalent.
+#[derive(PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub struct Tier<'a>(List<'a>);
+
+/// The web seeds of a torrent, encoded either as a single URL or a list of them.
+#[derive(PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub struct UrlList<'a>(Value<'a>);
+
+/// The `file tree` of a v2 torrent: nested dicts of path segments, files are the entries
+/// with an empty key. It was validated while parsing, so walking it can't fail.
+#[derive(Clone, Copy, PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub struct FileTree<'a>(Dict<'a>);
+
+/// A file of a [`FileTree`].
+#[derive(PartialEq)]
+#[cfg_attr(feature = "log", derive(Debug))]
+pub struct TreeFile<'a> {
+    /// relative to the torrent's directory
+    pub path: Vec<&'a str>,
+    pub length: u64,
+    /// the root of the file's merkle tree, empty files don't have one
+    pub pieces_root: Option<&'a InfoHashV2>,
+}
+
+/// The `piece layers` of a v2 torrent: the SHA-256 hashes of the pieces of every file larger
+/// than a piece, keyed by the file's `pieces root`.
+#[derive(Clone, Copy, PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub struct PieceLayers<'a>(Dict<'a>);
+
+/// Why a torrent that was parsed successfully can't be downloaded, see [`MetaInfoFile::validate`].
+#[derive(Clone, Copy, PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub enum ValidationError {
+    /// `piece length` is zero
+    ZeroPieceLength,
+    /// the number of hashes in `pieces` doesn't match `ceil(total length / piece length)`
+    PieceCountMismatch { expected: u64, actual: usize },
+    /// `name` is empty, `.`, `..` or contains a path separator
+    InvalidName,
+    /// v2 and hybrid: `piece length` isn't a power of two of at least 16 KiB
+    InvalidPieceLength,
+    /// v2 and hybrid: a file larger than a piece has no piece layer or one of the wrong size
+    MissingPieceLayer,
+    /// hybrid: the v1 files don't match the ones in the file tree
+    HybridMismatch,
+}
+
+/// The top-level dict as it is encoded.
+#[derive(BencodeDecode)]
+struct RawMetaInfo<'a> {
+    announce: Option<&'a str>,
+    #[bencode(rename = "announce-list")]
+    announce_list: Option<AnnounceList<'a>>,
+    comment: Option<&'a str>,
+    #[bencode(rename = "created by")]
+    created_by: Option<&'a str>,
+    #[bencode(rename = "creation date")]
+    creation_date: Option<i64>,
+    encoding: Option<&'a str>,
+    /// kept raw, because it has to be hashed as-is
+    #[bencode(raw)]
+    info: &'a [u8],
+    #[bencode(rename = "piece layers")]
+    piece_layers: Option<PieceLayers<'a>>,
+    #[bencode(rename = "url-list")]
+    url_list: Option<UrlList<'a>>,
+}
+
+/// The info dict as it is encoded, `length` and `files` are mutually exclusive.
+#[derive(BencodeDecode)]
+struct RawInfo<'a> {
+    #[bencode(rename = "piece length")]
+    piece_length: u32,
+    name: &'a str,
+    pieces: Option<&'a [InfoHash]>,
+    length: Option<u64>,
+    files: Option<Files<'a>>,
+    #[bencode(rename = "meta version")]
+    meta_version: Option<u8>,
+    #[bencode(rename = "file tree")]
+    file_tree: Option<FileTree<'a>>,
+    private: Option<u8>,
+    source: Option<&'a str>,
+}
+
+/// A file in the `file tree` as it is encoded.
+#[derive(BencodeDecode)]
+struct RawTreeFile<'a> {
+    length: u64,
+    #[bencode(rename = "pieces root")]
+    pieces_root: Option<&'a InfoHashV2>,
 }
 
 impl<'a> MetaInfoFile<'a> {
+    /// The keys may come in any order, unknown ones are skipped.
     pub fn parse(input: &'a [u8]) -> Result<Self> {
-        let mut p = BencodeParser::new(input);
+        let raw = RawMetaInfo::decode(&mut BencodeParser::new(input))?;
 
-        // Prepare default values (Option is useful here if fields are optional)
-        let mut announce = None;
-        let mut info = None;
-        let mut info_hash = [0u8; 20];
+        // parsed from its own slice, so the offsets have to be shifted
+        let info = Info::parse(raw.info)
+            .map_err(|e| e.in_field("info").offset_by(offset_in(input, raw.info)))?;
 
-        p.expect_dict_start()?;
+        let info_hash_v2: Option<InfoHashV2> = info
+            .file_tree
+            .is_some()
+            .then(|| sha2::Sha256::digest(raw.info).into());
+        let info_hash = match info_hash_v2 {
+            // there is no v1 swarm, the truncated v2 hash takes the place of the SHA-1
+            Some(v2) if info.version() == Version::V2 => {
+                v2[..20].try_into().expect("32 bytes are longer")
+            }
+            _ => sha1_smol::Sha1::from(raw.info).digest().bytes(),
+        };
 
-        // Dictionary Loop
-        while !p.match_dict_end() {
-            let key = p.parse_str()?; // Keys are always strings
+        Ok(MetaInfoFile {
+            announce: raw.announce,
+            announce_list: raw.announce_list,
+            creation_date: raw.creation_date,
+            comment: raw.comment,
+            created_by: raw.created_by,
+            encoding: raw.encoding,
+            url_list: raw.url_list,
+            piece_layers: raw.piece_layers,
+            info,
+            info_hash,
+            info_hash_v2,
+        })
+    }
 
-            match key {
-                "announce" => {
-                    announce = Some(p.parse_str()?);
-                }
-                "info" => {
-                    let info_bytes = p.parse_raw_value()?;
-                    info_hash = sha1_smol::Sha1::from(info_bytes).digest().bytes();
-                    info = Some(Info::parse(info_bytes)?);
-                    // Now let's say I'm lazy to come up with anything else and
-                    // TODO: assuming that the 'announce' key always comes first.
-                    break; // We're not interested in anything else.
-                }
-                _ => {
-                    // Unknown field: skip the value
-                    p.skip_any()?;
+    /// Checks that the torrent makes sense beyond being well-formed bencode,
+    /// so bad torrents are rejected before the download starts instead of failing midway.
+    pub fn validate(&self) -> core::result::Result<(), ValidationError> {
+        let info = &self.info;
+        if info.piece_length == 0 {
+            return Err(ValidationError::ZeroPieceLength);
+        }
+
+        let version = info.version();
+        if version != Version::V2 {
+            let expected = info.total_length().div_ceil(info.piece_length as u64);
+            if expected != info.pieces.len() as u64 {
+                return Err(ValidationError::PieceCountMismatch {
+                    expected,
+                    actual: info.pieces.len(),
+                });
+            }
+        }
+
+        if !is_valid_path_segment(info.name) {
+            return Err(ValidationError::InvalidName);
+        }
+
+        if let Some(tree) = info.file_tree {
+            if !info.piece_length.is_power_of_two() || info.piece_length < 16 * 1024 {
+                return Err(ValidationError::InvalidPieceLength);
+            }
+
+            let tree_files = tree.files();
+            for file in tree_files
+                .iter()
+                .filter(|f| f.length > info.piece_length as u64)
+            {
+                let pieces = file.length.div_ceil(info.piece_length as u64);
+                let layer = self
+                    .piece_layers
+                    .zip(file.pieces_root)
+                    .and_then(|(layers, root)| layers.get(root));
+                if layer.is_none_or(|hashes| hashes.len() as u64 != pieces) {
+                    return Err(ValidationError::MissingPieceLayer);
                 }
             }
+
+            if version == Version::Hybrid && !info.v1_matches_tree(&tree_files) {
+                return Err(ValidationError::HybridMismatch);
+            }
         }
 
-        Ok(MetaInfoFile {
-            announce: announce.unwrap_or(DEFAULT_TRACKER),
-            info: info.ok_or(Error::UnknownField)?,
-            info_hash,
-        })
+        Ok(())
     }
 }
 
 impl<'a> Info<'a> {
+    /// The info dict is parsed strictly: it is hashed as-is, so if it isn't canonical bencode
+    /// our info hash wouldn't match the one of other clients.
     pub fn parse(input: &'a [u8]) -> Result<Self> {
-        let mut p = BencodeParser::new(input);
-
-        // Prepare default values (Option is useful here if fields are optional)
-        let mut piece_length = None;
-        let mut name = None;
-        let mut pieces = None;
-        let mut length = None;
-
-        p.expect_dict_start()?;
-
-        // Dictionary Loop
-        while !p.match_dict_end() {
-            let key = p.parse_str()?; // Keys are always strings
-
-            match key {
-                "pieces" => {
-                    let piece_chunks = p.parse_bytes()?.as_chunks::<20>();
-                    if !piece_chunks.1.is_empty() {
-                        return Err(Error::InvalidSyntax);
-                    }
-                    pieces = Some(piece_chunks.0);
-                }
-                "length" => {
-                    length = Some(p.parse_int()? as u32);
-                }
-                "piece length" => {
-                    piece_length = Some(p.parse_int()? as u32);
-                }
-                "name" => {
-                    name = Some(p.parse_str()?);
-                }
-                _ => {
-                    // Unknown field: skip the value
-                    p.skip_any()?;
-                }
+        let raw = RawInfo::decode(&mut BencodeParser::new(input).strict())?;
+
+        let meta_version = raw.meta_version.unwrap_or(1);
+        let file_tree = match meta_version {
+            // v1 torrents can't have a file tree, ignore it like any other unknown key
+            1 => None,
+            2 => Some(
+                raw.file_tree
+                    .ok_or(Error::new(ErrorKind::MissingField("file tree"), 0))?,
+            ),
+            _ => return Err(Error::new(ErrorKind::OutOfRange, 0).in_field("meta version")),
+        };
+
+        let layout = match (raw.length, raw.files) {
+            (Some(length), None) => FileLayout::SingleFile { length },
+            (None, Some(files)) => {
+                // checked once here, so `total_length()` can't overflow
+                files
+                    .iter()
+                    .try_fold(0u64, |total, f| total.checked_add(f.length))
+                    .ok_or(Error::new(ErrorKind::OutOfRange, 0).in_field("files"))?;
+                FileLayout::MultiFile { files }
             }
-        }
+            (None, None) => match file_tree {
+                Some(tree) => FileLayout::Tree { tree },
+                None => return Err(Error::new(ErrorKind::MissingField("length"), 0)),
+            },
+            (Some(_), Some(_)) => {
+                return Err(Error::new(ErrorKind::InvalidSyntax, 0).in_field("files"));
+            }
+        };
+
+        let pieces = match (&layout, raw.pieces) {
+            (_, Some(pieces)) => pieces,
+            (FileLayout::Tree { .. }, None) => &[],
+            (_, None) => return Err(Error::new(ErrorKind::MissingField("pieces"), 0)),
+        };
 
-        // Validate we got everything
         Ok(Info {
-            piece_length: piece_length.ok_or(Error::UnknownField)?,
-            name: name.ok_or(Error::UnknownField)?,
-            pieces: pieces.ok_or(Error::UnknownField)?,
-            length: length.ok_or(Error::UnknownField)?,
+            piece_length: raw.piece_length,
+            name: raw.name,
+            pieces,
+            layout,
+            meta_version,
+            file_tree,
+            private: raw.private == Some(1),
+            source: raw.source,
+        })
+    }
+
+    /// The size of all files together.
+    pub fn total_length(&self) -> u64 {
+        match &self.layout {
+            FileLayout::SingleFile { length } => *length,
+            FileLayout::MultiFile { files } => files.iter().map(|f| f.length).sum(),
+            FileLayout::Tree { tree } => tree.files().iter().map(|f| f.length).sum(),
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        match (&self.layout, self.file_tree) {
+            (_, None) => Version::V1,
+            (FileLayout::Tree { .. }, Some(_)) => Version::V2,
+            (_, Some(_)) => Version::Hybrid,
+        }
+    }
+
+    /// Whether the v1 files of a hybrid torrent, without the padding files, are the ones of the
+    /// file tree.
+    fn v1_matches_tree(&self, tree_files: &[TreeFile<'_>]) -> bool {
+        match &self.layout {
+            FileLayout::SingleFile { length } => {
+                matches!(tree_files, [file] if file.path == [self.name] && file.length == *length)
+            }
+            FileLayout::MultiFile { files } => {
+                let mut v1_files = files.iter().filter(|f| !f.is_padding());
+                let same = |v1: FileEntry<'_>, v2: &TreeFile<'_>| {
+                    v1.length == v2.length && v1.path.segments().eq(v2.path.iter().copied())
+                };
+                tree_files
+                    .iter()
+                    .all(|v2| v1_files.next().is_some_and(|v1| same(v1, v2)))
+                    && v1_files.next().is_none()
+            }
+            FileLayout::Tree { .. } => true,
+        }
+    }
+}
+
+impl<'a> Files<'a> {
+    pub fn iter(&self) -> impl Iterator<Item = FileEntry<'a>> + use<'a> {
+        self.0.iter().filter_map(|entry| {
+            FileEntry::decode(&mut BencodeParser::new(entry.as_dict()?.as_raw())).ok()
         })
     }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<'a> BencodeDecode<'a> for Files<'a> {
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        let start = p.offset();
+        let list = List::decode(p)?;
+        if list.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidSyntax, start));
+        }
+
+        // decode every entry once, so errors are reported here and not swallowed by `iter()`
+        let mut entries = BencodeParser::new(list.as_raw()).strict();
+        entries.expect_list_start()?;
+        while !entries.match_list_end() {
+            FileEntry::decode(&mut entries).map_err(|e| e.offset_by(start))?;
+        }
+
+        Ok(Files(list))
+    }
+}
+
+impl<'a> AnnounceList<'a> {
+    pub fn tiers(&self) -> impl Iterator<Item = Tier<'a>> + use<'a> {
+        self.0.iter().filter_map(|tier| tier.as_list()).map(Tier)
+    }
+}
+
+impl<'a> BencodeDecode<'a> for AnnounceList<'a> {
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        let start = p.offset();
+        let list = List::decode(p)?;
+
+        let is_tier = |tier: Value<'_>| {
+            tier.as_list()
+                .is_some_and(|urls| urls.iter().all(|url| url.as_str().is_some()))
+        };
+        if !list.iter().all(is_tier) {
+            return Err(Error::new(ErrorKind::InvalidSyntax, start));
+        }
+
+        Ok(AnnounceList(list))
+    }
+}
+
+impl<'a> Tier<'a> {
+    pub fn urls(&self) -> impl Iterator<Item = &'a str> + use<'a> {
+        self.0.iter().filter_map(|url| url.as_str())
+    }
+}
+
+impl<'a> UrlList<'a> {
+    /// Empty URLs, which some clients write instead of leaving the key out, are skipped.
+    pub fn urls(&self) -> impl Iterator<Item = &'a str> + use<'a> {
+        let (single, list) = match self.0 {
+            Value::List(list) => (None, Some(list)),
+            value => (value.as_str(), None),
+        };
+        single
+            .into_iter()
+            .chain(
+                list.into_iter()
+                    .flat_map(|l| l.iter())
+                    .filter_map(|url| url.as_str()),
+            )
+            .filter(|url| !url.is_empty())
+    }
+}
+
+impl<'a> BencodeDecode<'a> for UrlList<'a> {
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        let start = p.offset();
+        let value = Value::decode(p)?;
+
+        let is_valid = match value {
+            Value::Bytes(_) => value.as_str().is_some(),
+            Value::List(list) => list.iter().all(|url| url.as_str().is_some()),
+            _ => false,
+        };
+        if !is_valid {
+            return Err(Error::new(ErrorKind::InvalidSyntax, start));
+        }
+
+        Ok(UrlList(value))
+    }
+}
+
+impl FileEntry<'_> {
+    /// Padding files only align the next file to a piece boundary, they aren't written to disk.
+    #[inline]
+    pub fn is_padding(&self) -> bool {
+        self.attr.is_some_and(|attr| attr.contains('p'))
+    }
+}
+
+impl<'a> FileTree<'a> {
+    /// The files depth-first in key order, which is the order of their pieces.
+    pub fn files(&self) -> Vec<TreeFile<'a>> {
+        let mut files = Vec::new();
+        // can't fail, it was validated while parsing
+        let _ = walk_tree(self.0, self.0.as_raw(), &mut Vec::new(), &mut files);
+        files
+    }
 }
+
+impl<'a> BencodeDecode<'a> for FileTree<'a> {
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        let start = p.offset();
+        let tree = Dict::decode(p)?;
+
+        let mut files = Vec::new();
+        walk_tree(tree, tree.as_raw(), &mut Vec::new(), &mut files)
+            .map_err(|e| e.offset_by(start))?;
+        if files.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidSyntax, start));
+        }
+        // checked once here, so `total_length()` can't overflow
+        files
+            .iter()
+            .try_fold(0u64, |total, f| total.checked_add(f.length))
+            .ok_or(Error::new(ErrorKind::OutOfRange, start))?;
+
+        Ok(FileTree(tree))
+    }
+}
+
+/// Collects the files below `dir` into `files`, `path` holds the keys leading to `dir`.
+/// Error offsets are relative to `base`, the raw tree.
+fn walk_tree<'a>(
+    dir: Dict<'a>,
+    base: &[u8],
+    path: &mut Vec<&'a str>,
+    files: &mut Vec<TreeFile<'a>>,
+) -> Result<()> {
+    let invalid = || Error::new(ErrorKind::InvalidSyntax, offset_in(base, dir.as_raw()));
+    let len = dir.len();
+
+    for (key, value) in dir {
+        let node = value.as_dict().ok_or_else(invalid)?;
+        let node_start = offset_in(base, node.as_raw());
+
+        if key.is_empty() {
+            // a file, the keys leading here are its path
+            if path.is_empty() || len != 1 {
+                return Err(invalid());
+            }
+            let file = RawTreeFile::decode(&mut BencodeParser::new(node.as_raw()))
+                .map_err(|e| e.offset_by(node_start))?;
+            if file.length > 0 && file.pieces_root.is_none() {
+                return Err(Error::new(
+                    ErrorKind::MissingField("pieces root"),
+                    node_start,
+                ));
+            }
+            files.push(TreeFile {
+                path: path.clone(),
+                length: file.length,
+                pieces_root: file.pieces_root,
+            });
+        } else {
+            let segment = core::str::from_utf8(key)
+                .ok()
+                .filter(|s| is_valid_path_segment(s))
+                .ok_or_else(invalid)?;
+            path.push(segment);
+            walk_tree(node, base, path, files)?;
+            path.pop();
+        }
+    }
+
+    Ok(())
+}
+
+impl<'a> PieceLayers<'a> {
+    /// The hashes of the pieces of the file with the given `pieces root`.
+    pub fn get(&self, pieces_root: &InfoHashV2) -> Option<&'a [InfoHashV2]> {
+        let (hashes, _) = self.0.get(pieces_root)?.as_bytes()?.as_chunks();
+        Some(hashes)
+    }
+}
+
+impl<'a> BencodeDecode<'a> for PieceLayers<'a> {
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        let start = p.offset();
+        let layers = Dict::decode(p)?;
+
+        let is_valid = |(root, hashes): (&[u8], Value<'_>)| {
+            root.len() == 32 && hashes.as_bytes().is_some_and(|h| h.len() % 32 == 0)
+        };
+        if !layers.iter().all(is_valid) {
+            return Err(Error::new(ErrorKind::InvalidSyntax, start));
+        }
+
+        Ok(PieceLayers(layers))
+    }
+}
+
+impl<'a> FilePath<'a> {
+    /// The directories and finally the file name.
+    pub fn segments(&self) -> impl Iterator<Item = &'a str> + use<'a> {
+        self.0.iter().filter_map(|segment| segment.as_str())
+    }
+}
+
+impl<'a> BencodeDecode<'a> for FilePath<'a> {
+    fn decode(p: &mut BencodeParser<'a>) -> Result<Self> {
+        let start = p.offset();
+        let list = List::decode(p)?;
+
+        let is_valid = |segment: Value<'_>| segment.as_str().is_some_and(is_valid_path_segment);
+        if list.is_empty() || !list.iter().all(is_valid) {
+            return Err(Error::new(ErrorKind::InvalidSyntax, start));
+        }
+
+        Ok(FilePath(list))
+    }
+}
+
+/// Offset of `part` in `base`, which it was sliced from.
+fn offset_in(base: &[u8], part: &[u8]) -> usize {
+    part.as_ptr().addr() - base.as_ptr().addr()
+}
+
+/// A malicious torrent must not be able to write outside of its directory.
+fn is_valid_path_segment(segment: &str) -> bool {
+    !segment.is_empty() && segment != "." && segment != ".." && !segment.contains(['/', '\\'])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -138,20 +651,24 @@ mod tests {
         input.extend_from_slice(&HASH_B);
 
         input.extend_from_slice(b"e");
-        input.extend_from_slice(b"i3ei9e"); // Extra junk fields after 'info'
+        input.extend_from_slice(b"4:junki9e"); // Extra junk fields after 'info'
         input.extend_from_slice(b"e");
 
         let torrent = MetaInfoFile::parse(&input).expect("Should parse valid input");
 
         assert_eq!(
             torrent.info_hash,
-            sha1_smol::Sha1::from(&input[35..input.len() - 1 - 6]) // Exclude trailing junk
+            sha1_smol::Sha1::from(&input[35..input.len() - 1 - 9]) // Exclude trailing junk
                 .digest()
                 .bytes()
         );
 
-        assert_eq!(torrent.announce, "http://test.com");
-        assert_eq!(torrent.info.length, 1048576);
+        assert_eq!(torrent.announce, Some("http://test.com"));
+        assert_eq!(
+            torrent.info.layout,
+            FileLayout::SingleFile { length: 1048576 }
+        );
+        assert_eq!(torrent.info.total_length(), 1048576);
         assert_eq!(torrent.info.name, "test.image");
         assert_eq!(torrent.info.piece_length, 16384);
 
@@ -169,7 +686,7 @@ mod tests {
         let result = Info::parse(input);
 
         match result {
-            Err(Error::InvalidSyntax) => (), // Pass
+            Err(e) if e.kind() == ErrorKind::InvalidSyntax && e.path().eq(["pieces"]) => (), // Pass
             _ => panic!("Should have failed due to remaining bytes in piece chunks"),
         }
     }
@@ -194,7 +711,7 @@ mod tests {
         let meta = Info::parse(&input).expect("Should successfully skip junk fields");
 
         assert_eq!(meta.name, "log");
-        assert_eq!(meta.length, 100);
+        assert_eq!(meta.total_length(), 100);
     }
 
     #[test]
@@ -204,17 +721,371 @@ mod tests {
 
         let result = Info::parse(input);
 
-        match result {
-            Err(Error::UnknownField) => (), // Pass
+        match result.map_err(|e| e.kind()) {
+            Err(ErrorKind::MissingField("name")) => (), // Pass
             Ok(_) => panic!("Should fail because 'name' is missing"),
             Err(e) => panic!("Wrong error type: {:?}", e),
         }
     }
 
+    #[test]
+    fn test_non_canonical_info() {
+        // "name" after "piece length"
+        let input = b"d6:lengthi1e12:piece lengthi1e4:name1:a6:pieces0:e";
+        assert!(matches!(
+            Info::parse(input).map_err(|e| e.kind()),
+            Err(ErrorKind::UnsortedKeys)
+        ));
+
+        let input = b"d6:lengthi01e4:name1:a12:piece lengthi1e6:pieces0:e";
+        assert!(matches!(
+            Info::parse(input).map_err(|e| e.kind()),
+            Err(ErrorKind::NonCanonicalInteger)
+        ));
+    }
+
+    #[test]
+    fn test_all_fields_any_order() {
+        // everything but `announce` after the info dict, which is private and has a source
+        let mut input = Vec::new();
+        input.extend_from_slice(b"d8:announce3:url");
+        input.extend_from_slice(b"4:infod6:lengthi1e4:name1:a12:piece lengthi1e");
+        input.extend_from_slice(b"6:pieces0:7:privatei1e6:source3:srce");
+        input.extend_from_slice(b"13:announce-listll4:udp14:udp2el4:http");
+        input.extend_from_slice(
+            b"ee7:comment5:hello10:created by7:client113:creation datei1700000000e",
+        );
+        input.extend_from_slice(b"8:encoding5:UTF-88:url-listl6:seed_10:6:seed_2ee");
+
+        let torrent = MetaInfoFile::parse(&input).unwrap();
+        assert_eq!(torrent.announce, Some("url"));
+        assert_eq!(torrent.comment, Some("hello"));
+        assert_eq!(torrent.created_by, Some("client1"));
+        assert_eq!(torrent.creation_date, Some(1700000000));
+        assert_eq!(torrent.encoding, Some("UTF-8"));
+        assert!(torrent.info.private);
+        assert_eq!(torrent.info.source, Some("src"));
+
+        let tiers: Vec<Vec<_>> = torrent
+            .announce_list
+            .unwrap()
+            .tiers()
+            .map(|tier| tier.urls().collect())
+            .collect();
+        assert_eq!(tiers, [vec!["udp1", "udp2"], vec!["http"]]);
+
+        let seeds: Vec<_> = torrent.url_list.unwrap().urls().collect();
+        assert_eq!(seeds, ["seed_1", "seed_2"]);
+    }
+
+    #[test]
+    fn test_optional_fields() {
+        let input = b"d4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:e8:url-list4:seede";
+        let torrent = MetaInfoFile::parse(input).unwrap();
+        assert_eq!(torrent.announce, None);
+        assert_eq!(torrent.announce_list, None);
+        assert_eq!(torrent.creation_date, None);
+        assert!(!torrent.info.private);
+        assert_eq!(torrent.info.source, None);
+        // a single URL instead of a list
+        assert!(torrent.url_list.unwrap().urls().eq(["seed"]));
+
+        // a tier that isn't a list
+        let input =
+            b"d13:announce-listl3:url4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:ee";
+        let e = MetaInfoFile::parse(input).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidSyntax);
+        assert!(e.path().eq(["announce-list"]));
+    }
+
+    #[test]
+    fn test_validate() {
+        let torrent = |info: &[u8]| {
+            let mut input = Vec::new();
+            input.extend_from_slice(b"d4:info");
+            input.extend_from_slice(info);
+            input.extend_from_slice(b"e");
+            MetaInfoFile::parse(&input).unwrap().validate()
+        };
+
+        // 2 pieces of 16 bytes, the last one is shorter
+        let mut info = Vec::new();
+        info.extend_from_slice(b"d6:lengthi20e4:name1:a12:piece lengthi16e6:pieces40:");
+        info.extend_from_slice(&HASH_A);
+        info.extend_from_slice(&HASH_B);
+        info.extend_from_slice(b"e");
+        assert_eq!(torrent(&info), Ok(()));
+
+        assert_eq!(
+            torrent(b"d6:lengthi20e4:name1:a12:piece lengthi0e6:pieces0:e"),
+            Err(ValidationError::ZeroPieceLength)
+        );
+        assert_eq!(
+            torrent(b"d6:lengthi20e4:name1:a12:piece lengthi16e6:pieces0:e"),
+            Err(ValidationError::PieceCountMismatch {
+                expected: 2,
+                actual: 0
+            })
+        );
+        assert_eq!(
+            torrent(b"d6:lengthi0e4:name6:../etc12:piece lengthi16e6:pieces0:e"),
+            Err(ValidationError::InvalidName)
+        );
+    }
+
+    const ROOT_A: [u8; 32] = [b'r'; 32];
+    const ROOT_B: [u8; 32] = [b's'; 32];
+
+    /// A file tree with `b` (10 bytes) and `dir/a` (20000 bytes, more than a piece).
+    fn v2_file_tree() -> Vec<u8> {
+        let mut tree = Vec::new();
+        tree.extend_from_slice(b"9:file treed1:bd0:d6:lengthi10e11:pieces root32:");
+        tree.extend_from_slice(&ROOT_B);
+        tree.extend_from_slice(b"ee3:dird1:ad0:d6:lengthi20000e11:pieces root32:");
+        tree.extend_from_slice(&ROOT_A);
+        tree.extend_from_slice(b"eeee");
+        tree
+    }
+
+    /// A torrent with the given info dict and the piece layer of `dir/a`.
+    fn v2_torrent(info: &[u8]) -> Vec<u8> {
+        let mut input = Vec::new();
+        input.extend_from_slice(b"d4:info");
+        input.extend_from_slice(info);
+        input.extend_from_slice(b"12:piece layersd32:");
+        input.extend_from_slice(&ROOT_A);
+        input.extend_from_slice(b"64:");
+        input.extend_from_slice(&[b'h'; 64]);
+        input.extend_from_slice(b"ee");
+        input
+    }
+
+    #[test]
+    fn test_v2_torrent() {
+        let mut info = Vec::new();
+        info.extend_from_slice(b"d");
+        info.extend_from_slice(&v2_file_tree());
+        info.extend_from_slice(b"12:meta versioni2e4:name4:root12:piece lengthi16384ee");
+        let input = v2_torrent(&info);
+
+        let torrent = MetaInfoFile::parse(&input).unwrap();
+        assert_eq!(torrent.info.version(), Version::V2);
+        assert_eq!(torrent.info.meta_version, 2);
+        assert!(torrent.info.pieces.is_empty());
+        assert_eq!(torrent.info.total_length(), 20010);
+
+        // the handshake uses the truncated SHA-256
+        let hash: InfoHashV2 = sha2::Sha256::digest(&info).into();
+        assert_eq!(torrent.info_hash_v2, Some(hash));
+        assert_eq!(torrent.info_hash, hash[..20]);
+
+        let files = torrent.info.file_tree.unwrap().files();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, ["b"]);
+        assert_eq!(files[0].pieces_root, Some(&ROOT_B));
+        assert_eq!(files[1].path, ["dir", "a"]);
+        assert_eq!(files[1].length, 20000);
+
+        let layer = torrent.piece_layers.unwrap().get(&ROOT_A).unwrap();
+        assert_eq!(layer, [[b'h'; 32]; 2]);
+        assert_eq!(torrent.validate(), Ok(()));
+
+        // without the piece layers `dir/a` can't be verified
+        let mut input = b"d4:info".to_vec();
+        input.extend_from_slice(&info);
+        input.extend_from_slice(b"e");
+        let torrent = MetaInfoFile::parse(&input).unwrap();
+        assert_eq!(torrent.validate(), Err(ValidationError::MissingPieceLayer));
+    }
+
+    #[test]
+    fn test_hybrid_torrent() {
+        // the v1 files are padded to piece boundaries, like in the v2 file tree
+        let info = |length_a: &[u8]| {
+            let mut info = Vec::new();
+            info.extend_from_slice(b"d");
+            info.extend_from_slice(&v2_file_tree());
+            info.extend_from_slice(b"5:filesld6:lengthi10e4:pathl1:bee");
+            info.extend_from_slice(b"d4:attr1:p6:lengthi16374e4:pathl4:.pad5:16374ee");
+            info.extend_from_slice(b"d6:lengthi");
+            info.extend_from_slice(length_a);
+            info.extend_from_slice(b"e4:pathl3:dir1:aeee");
+            info.extend_from_slice(b"12:meta versioni2e4:name4:root12:piece lengthi16384e");
+            info.extend_from_slice(b"6:pieces60:");
+            info.extend_from_slice(&[b'h'; 60]);
+            info.extend_from_slice(b"e");
+            info
+        };
+
+        let hybrid = info(b"20000");
+        let input = v2_torrent(&hybrid);
+        let torrent = MetaInfoFile::parse(&input).unwrap();
+        assert_eq!(torrent.info.version(), Version::Hybrid);
+        // v1 peers only know the SHA-1
+        assert_eq!(
+            torrent.info_hash,
+            sha1_smol::Sha1::from(&hybrid).digest().bytes()
+        );
+        assert!(torrent.info_hash_v2.is_some());
+        assert_eq!(torrent.info.total_length(), 36384);
+        assert_eq!(torrent.validate(), Ok(()));
+
+        let input = v2_torrent(&info(b"19999"));
+        let torrent = MetaInfoFile::parse(&input).unwrap();
+        assert_eq!(torrent.validate(), Err(ValidationError::HybridMismatch));
+    }
+
+    #[test]
+    fn test_file_tree_invalid() {
+        let info = |tree: &[u8]| {
+            let mut input = Vec::new();
+            input.extend_from_slice(b"d9:file tree");
+            input.extend_from_slice(tree);
+            input.extend_from_slice(b"12:meta versioni2e4:name4:root12:piece lengthi16384ee");
+            Info::parse(&input).map(|_| ()).unwrap_err()
+        };
+
+        // a file without a name
+        let e = info(b"d0:d6:lengthi0eee");
+        assert_eq!(e.kind(), ErrorKind::InvalidSyntax);
+        assert!(e.path().eq(["file tree"]));
+        // path traversal
+        assert_eq!(
+            info(b"d2:..d0:d6:lengthi0eeee").kind(),
+            ErrorKind::InvalidSyntax
+        );
+        // a file with content needs a pieces root
+        let e = info(b"d1:ad0:d6:lengthi1eeee");
+        assert_eq!(e.kind(), ErrorKind::MissingField("pieces root"));
+        assert_eq!(e.offset(), 19); // the file's dict
+        // no files at all
+        assert_eq!(info(b"de").kind(), ErrorKind::InvalidSyntax);
+
+        assert_eq!(
+            Info::parse(b"d12:meta versioni2e4:name1:a12:piece lengthi16384ee")
+                .map(|_| ())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::MissingField("file tree")
+        );
+        let e =
+            Info::parse(b"d6:lengthi1e12:meta versioni3e4:name1:a12:piece lengthi1e6:pieces0:e")
+                .map(|_| ())
+                .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::OutOfRange);
+        assert!(e.path().eq(["meta version"]));
+    }
+
+    #[test]
+    fn test_error_position_in_info() {
+        let input = b"d8:announce3:url4:infod6:lengthi1e4:name1:a12:piece lengthi-1e6:pieces0:ee";
+        let e = MetaInfoFile::parse(input).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::OutOfRange);
+        assert_eq!(e.offset(), 58); // the "i-1e"
+        assert!(e.path().eq(["info", "piece length"]));
+
+        let e = MetaInfoFile::parse(b"d8:announce3:urle").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::MissingField("info"));
+    }
+
+    #[test]
+    fn test_multi_file() {
+        let mut input = Vec::new();
+        input.extend_from_slice(b"d5:filesl");
+        input.extend_from_slice(b"d6:lengthi10e4:pathl3:dir5:a.txtee");
+        input.extend_from_slice(b"d6:lengthi5e4:pathl5:b.txtee");
+        input.extend_from_slice(b"e4:name4:root12:piece lengthi16e6:pieces20:");
+        input.extend_from_slice(&HASH_A);
+        input.extend_from_slice(b"e");
+
+        let info = Info::parse(&input).unwrap();
+        assert_eq!(info.name, "root");
+        assert_eq!(info.total_length(), 15);
+
+        let FileLayout::MultiFile { files } = &info.layout else {
+            panic!("expected a multi-file torrent");
+        };
+        assert_eq!(files.len(), 2);
+        let entries: Vec<_> = files.iter().collect();
+        assert_eq!(entries[0].length, 10);
+        assert!(entries[0].path.segments().eq(["dir", "a.txt"]));
+        assert_eq!(entries[1].length, 5);
+        assert!(entries[1].path.segments().eq(["b.txt"]));
+    }
+
+    #[test]
+    fn test_multi_file_invalid() {
+        let info = |files: &[u8]| {
+            let mut input = Vec::new();
+            input.extend_from_slice(files);
+            input.extend_from_slice(b"4:name4:root12:piece lengthi16e6:pieces0:e");
+            Info::parse(&input).map(|_| ()).unwrap_err()
+        };
+
+        // path traversal
+        let e = info(b"d5:filesld6:lengthi1e4:pathl2:..6:passwdeee");
+        assert_eq!(e.kind(), ErrorKind::InvalidSyntax);
+        assert!(e.path().eq(["files", "path"]));
+        assert_eq!(e.offset(), 27);
+        assert_eq!(
+            info(b"d5:filesld6:lengthi1e4:pathl5:a/../eeee").kind(),
+            ErrorKind::InvalidSyntax
+        );
+        // empty path and empty list of files
+        assert_eq!(
+            info(b"d5:filesld6:lengthi1e4:pathleeee").kind(),
+            ErrorKind::InvalidSyntax
+        );
+        assert_eq!(info(b"d5:filesle").kind(), ErrorKind::InvalidSyntax);
+        // an entry without length
+        let e = info(b"d5:filesld4:pathl1:aeee");
+        assert_eq!(e.kind(), ErrorKind::MissingField("length"));
+        assert!(e.path().eq(["files"]));
+        // both or neither of `length` and `files`
+        let e = info(b"d5:filesld6:lengthi1e4:pathl1:aeee6:lengthi1e");
+        assert_eq!(e.kind(), ErrorKind::InvalidSyntax);
+        assert!(e.path().eq(["files"]));
+        assert_eq!(info(b"d").kind(), ErrorKind::MissingField("length"));
+    }
+
+    #[test]
+    fn test_large_lengths() {
+        // 5 GiB, doesn't fit into a u32
+        let input = b"d6:lengthi5368709120e4:name3:iso12:piece lengthi16e6:pieces0:e";
+        assert_eq!(
+            Info::parse(input).unwrap().total_length(),
+            5 * 1024 * 1024 * 1024
+        );
+
+        // the files add up to more than u64::MAX
+        let mut input = Vec::new();
+        input.extend_from_slice(b"d5:filesl");
+        for name in [b'a', b'b', b'c'] {
+            // i64::MAX, the largest integer bencode can hold here
+            input.extend_from_slice(b"d6:lengthi9223372036854775807e4:pathl1:");
+            input.extend_from_slice(&[name, b'e', b'e']);
+        }
+        input.extend_from_slice(b"e4:name4:root12:piece lengthi16e6:pieces0:e");
+        let input = &input;
+        let e = Info::parse(input).map(|_| ()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::OutOfRange);
+        assert!(e.path().eq(["files"]));
+
+        // negative lengths are rejected instead of wrapping around
+        let input = b"d6:lengthi-1e4:name1:a12:piece lengthi16e6:pieces0:e";
+        assert_eq!(
+            Info::parse(input).map(|_| ()).unwrap_err().kind(),
+            ErrorKind::OutOfRange
+        );
+    }
+
     #[test]
     fn test_empty_input_or_wrong_type() {
         // Input starts with 'i' (integer) instead of 'd' (dict)
         let result = Info::parse(b"i42e");
-        assert!(matches!(result, Err(Error::ExpectedDict)));
+        assert!(matches!(
+            result.map_err(|e| e.kind()),
+            Err(ErrorKind::ExpectedDict)
+        ));
     }
 }
@@ -1,5 +1,12 @@
+pub mod builder;
+pub mod magnet;
 pub mod metainfo;
+pub mod scrape;
 pub mod tracker;
+pub mod tracker_tiers;
+pub mod udp_tracker;
 
 pub type InfoHash = [u8; 20];
+/// SHA-256, used by v2 torrents (BEP 52)
+pub type InfoHashV2 = [u8; 32];
 pub type PeerId = [u8; 20];
@@ -0,0 +1,221 @@
+//! Scrapes: how many peers share a torrent, without announcing (BEP 48, BEP 15).
+
+use alloc::{string::String, vec::Vec};
+use bencode::BencodeDecode;
+use core::fmt::Write;
+
+use crate::{core::InfoHash, net::percent_encode};
+
+/// How many torrents an HTTP tracker is asked about at once, the query has to fit into 512 bytes.
+pub const MAX_HTTP_SCRAPE: usize = 7;
+/// How many torrents a UDP tracker is asked about at once, more don't fit into a datagram.
+pub const MAX_UDP_SCRAPE: usize = 74;
+
+/// The numbers of a torrent a tracker knows about, missing ones are 0.
+#[derive(Clone, Copy, PartialEq, BencodeDecode)]
+#[defmt_or_log::derive_format_or_debug]
+pub struct ScrapeStats {
+    /// the number of seeds
+    #[bencode(default)]
+    pub complete: u32,
+    /// how often the download was completed
+    #[bencode(default)]
+    pub downloaded: u32,
+    /// the number of leechers
+    #[bencode(default)]
+    pub incomplete: u32,
+}
+
+/// The answer to a scrape, torrents the tracker doesn't know are missing.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "log", derive(Debug))]
+pub struct ScrapeResponse {
+    pub files: Vec<(InfoHash, ScrapeStats)>,
+}
+
+impl ScrapeResponse {
+    /// The numbers of the torrent with `info_hash`.
+    pub fn get(&self, info_hash: &InfoHash) -> Option<&ScrapeStats> {
+        self.files
+            .iter()
+            .find(|(hash, _)| hash == info_hash)
+            .map(|(_, stats)| stats)
+    }
+}
+
+/// The scrape URL of a tracker, `None` if it doesn't support scraping.
+///
+/// For HTTP trackers, the last path segment has to start with `announce`, which is replaced by
+/// `scrape` (e.g. `/x/announce.php?k=1` becomes `/x/scrape.php?k=1`). UDP trackers are scraped
+/// at the same address they are announced to.
+pub fn scrape_url(announce: &str) -> Option<String> {
+    if announce.starts_with("udp://") {
+        return Some(String::from(announce));
+    }
+    // only the path counts, not the host or the query
+    let (_, rest) = announce.split_once("://")?;
+    let path_start = announce.len() - rest.len() + rest.find(['/', '?', '#'])?;
+    let path_end = announce[path_start..]
+        .find(['?', '#'])
+        .map_or(announce.len(), |end| path_start + end);
+    let segment_start = path_start + announce[path_start..path_end].rfind('/')? + 1;
+    let rest = announce[segment_start..].strip_prefix("announce")?;
+
+    let mut url = String::with_capacity(announce.len());
+    url.push_str(&announce[..segment_start]);
+    url.push_str("scrape");
+    url.push_str(rest);
+    Some(url)
+}
+
+/// The query of an HTTP scrape, one `info_hash` per torrent.
+pub(crate) fn scrape_query(info_hashes: &[InfoHash]) -> String {
+    let mut query = String::with_capacity(info_hashes.len() * 71);
+    for (i, info_hash) in info_hashes.iter().enumerate() {
+        if i > 0 {
+            query.push('&');
+        }
+        write!(query, "info_hash={}", percent_encode(info_hash)).unwrap();
+    }
+    query
+}
+
+mod scrape_response_parser {
+    use alloc::{string::String, vec::Vec};
+    use bencode::{BencodeDecode, BencodeParser, Error, ErrorKind};
+
+    use crate::core::{
+        InfoHash,
+        scrape::{ScrapeResponse, ScrapeStats},
+        tracker::TrackerError,
+    };
+
+    impl ScrapeResponse {
+        /// Parses the bencoded response of an HTTP tracker's scrape.
+        /// A `failure reason` becomes [`TrackerError::Failure`].
+        pub fn parse(input: &[u8]) -> Result<Self, TrackerError> {
+            let mut p = BencodeParser::new(input);
+            let mut files = Vec::new();
+
+            p.expect_dict_start().map_err(TrackerError::Parse)?;
+            while !p.match_dict_end() {
+                match p.parse_str().map_err(TrackerError::Parse)? {
+                    "failure reason" => {
+                        let reason = p
+                            .parse_str()
+                            .map_err(|e| TrackerError::Parse(e.in_field("failure reason")))?;
+                        return Err(TrackerError::Failure(String::from(reason)));
+                    }
+                    "files" => {
+                        let in_files = |e: Error| TrackerError::Parse(e.in_field("files"));
+                        p.expect_dict_start().map_err(in_files)?;
+                        while !p.match_dict_end() {
+                            let start = p.offset();
+                            let info_hash: InfoHash =
+                                p.parse_bytes().map_err(in_files)?.try_into().map_err(|_| {
+                                    in_files(Error::new(ErrorKind::InvalidSyntax, start))
+                                })?;
+                            files.push((info_hash, ScrapeStats::decode(&mut p).map_err(in_files)?));
+                        }
+                    }
+                    _ => p.skip_any().map_err(TrackerError::Parse)?,
+                }
+            }
+
+            Ok(ScrapeResponse { files })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tracker::TrackerError;
+    use alloc::format;
+    use bencode::ErrorKind;
+
+    #[test]
+    fn test_scrape_url() {
+        let cases = [
+            (
+                "http://example.com/announce",
+                Some("http://example.com/scrape"),
+            ),
+            (
+                "http://example.com/x/announce",
+                Some("http://example.com/x/scrape"),
+            ),
+            (
+                "http://example.com/announce.php",
+                Some("http://example.com/scrape.php"),
+            ),
+            (
+                "http://example.com/announce?x2%0644",
+                Some("http://example.com/scrape?x2%0644"),
+            ),
+            ("http://example.com/a", None),
+            (
+                "http://example.com/announce?x=2/4",
+                Some("http://example.com/scrape?x=2/4"),
+            ),
+            ("http://example.com/x%064announce", None),
+            // no path, `announce` in the host or the query
+            ("http://example.com", None),
+            ("http://announce.example.com", None),
+            ("http://announce.example.com?announce", None),
+            (
+                "http://announce.example.com/announce",
+                Some("http://announce.example.com/scrape"),
+            ),
+            ("udp://example.com:80", Some("udp://example.com:80")),
+        ];
+        for (announce, scrape) in cases {
+            assert_eq!(scrape_url(announce).as_deref(), scrape, "{announce}");
+        }
+    }
+
+    #[test]
+    fn test_scrape_query() {
+        let query = scrape_query(&[[0xaa; 20], [0xbb; 20]]);
+        assert!(query.starts_with("info_hash=%AA%AA"));
+        assert!(query.contains("&info_hash=%BB%BB"));
+    }
+
+    #[test]
+    fn test_scrape_response() {
+        let mut input = b"d5:filesd20:".to_vec();
+        input.extend_from_slice(&[0xaa; 20]);
+        input.extend_from_slice(b"d8:completei5e10:downloadedi50e10:incompletei10e4:namei1eee");
+        input.extend_from_slice(b"5:flagsd20:min_request_intervali60eee");
+        let response = ScrapeResponse::parse(&input).unwrap();
+        assert_eq!(
+            response.get(&[0xaa; 20]),
+            Some(&ScrapeStats {
+                complete: 5,
+                downloaded: 50,
+                incomplete: 10
+            })
+        );
+        assert_eq!(response.get(&[0xbb; 20]), None);
+
+        let input = b"d14:failure reason9:forbiddene";
+        assert!(matches!(
+            ScrapeResponse::parse(input),
+            Err(TrackerError::Failure(reason)) if reason == "forbidden"
+        ));
+
+        // an info hash that isn't 20 bytes long
+        assert!(ScrapeResponse::parse(b"d5:filesd3:abcdeee").is_err());
+
+        // counts that don't fit into a u32
+        for count in ["i-1e", "i4294967296e"] {
+            let mut input = b"d5:filesd20:".to_vec();
+            input.extend_from_slice(&[0xaa; 20]);
+            input.extend_from_slice(format!("d8:complete{count}eee").as_bytes());
+            assert!(matches!(
+                ScrapeResponse::parse(&input),
+                Err(TrackerError::Parse(e)) if e.kind() == ErrorKind::OutOfRange
+            ));
+        }
+    }
+}
@@ -1,10 +1,45 @@
 use crate::{
-    core::{InfoHash, PeerId},
+    core::{
+        InfoHash, PeerId,
+        udp_tracker::{Action, header},
+    },
     net::percent_encode,
 };
-use alloc::string::String;
+use alloc::{string::String, vec::Vec};
 use core::fmt::Write;
-use heapless::Vec;
+use core::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Tells the tracker where we are in the lifecycle of the download.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[defmt_or_log::derive_format_or_debug]
+pub enum AnnounceEvent {
+    /// the first announce of a download
+    Started,
+    /// the download finished (not sent if it was complete from the start)
+    Completed,
+    /// we are shutting down gracefully
+    Stopped,
+}
+
+impl AnnounceEvent {
+    /// The value of the `event` parameter of HTTP trackers.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Started => "started",
+            Self::Completed => "completed",
+            Self::Stopped => "stopped",
+        }
+    }
+
+    /// The event field of the UDP announce (BEP 15), 0 is none.
+    const fn udp_id(self) -> u32 {
+        match self {
+            Self::Completed => 1,
+            Self::Started => 2,
+            Self::Stopped => 3,
+        }
+    }
+}
 
 #[derive(Clone)]
 #[defmt_or_log::derive_format_or_debug]
@@ -16,19 +51,27 @@ pub struct TrackerRequest<'a> {
     /// the port your client is listening on
     port: u16,
     /// the total amount uploaded so far
-    uploaded: u32,
+    uploaded: u64,
     /// the total amount downloaded so far
-    downloaded: u32,
+    downloaded: u64,
     /// the number of bytes left to download
-    left: u32,
+    left: u64,
     /// whether the peer list should use the compact representation
     /// The compact representation is more commonly used in the wild, the non-compact representation is mostly supported for backward-compatibility.
     compact: u8,
+    /// how many peers we'd like, the tracker's default if `None`
+    num_want: Option<u32>,
+    /// identifies us when our IP address changes
+    key: Option<u32>,
+    /// `None` for the regular announces in between
+    event: Option<AnnounceEvent>,
+    /// what a tracker told us to send in its last response
+    tracker_id: Option<&'a str>,
 }
 
 impl<'a> TrackerRequest<'a> {
     #[inline]
-    pub const fn new(info_hash: &'a InfoHash, peer_id: &'a PeerId, port: u16, left: u32) -> Self {
+    pub const fn new(info_hash: &'a InfoHash, peer_id: &'a PeerId, port: u16, left: u64) -> Self {
         Self {
             info_hash,
             peer_id,
@@ -37,9 +80,48 @@ impl<'a> TrackerRequest<'a> {
             downloaded: 0,
             left,
             compact: 1,
+            num_want: None,
+            key: None,
+            event: None,
+            tracker_id: None,
         }
     }
 
+    /// The bytes we uploaded and downloaded so far.
+    #[inline]
+    pub const fn with_transferred(mut self, uploaded: u64, downloaded: u64) -> Self {
+        self.uploaded = uploaded;
+        self.downloaded = downloaded;
+        self
+    }
+
+    #[inline]
+    pub const fn with_event(mut self, event: Option<AnnounceEvent>) -> Self {
+        self.event = event;
+        self
+    }
+
+    #[inline]
+    pub const fn with_tracker_id(mut self, tracker_id: Option<&'a str>) -> Self {
+        self.tracker_id = tracker_id;
+        self
+    }
+
+    /// Asks for as many peers as we can keep.
+    #[inline]
+    pub const fn with_num_want(mut self, num_want: u32) -> Self {
+        self.num_want = Some(num_want);
+        self
+    }
+
+    /// A random number that stays the same while we run, so the tracker can tell it's still us
+    /// when our IP address changes.
+    #[inline]
+    pub const fn with_key(mut self, key: u32) -> Self {
+        self.key = Some(key);
+        self
+    }
+
     pub(crate) fn to_url_encoded(&self) -> String {
         let mut url_encoded = String::with_capacity(256);
 
@@ -50,64 +132,216 @@ impl<'a> TrackerRequest<'a> {
         write!(url_encoded, "&downloaded={}", self.downloaded).unwrap();
         write!(url_encoded, "&left={}", self.left).unwrap();
         write!(url_encoded, "&compact={}", self.compact).unwrap();
+        if let Some(num_want) = self.num_want {
+            write!(url_encoded, "&numwant={}", num_want).unwrap();
+        }
+        if let Some(key) = self.key {
+            write!(url_encoded, "&key={:08X}", key).unwrap();
+        }
+        if let Some(event) = self.event {
+            write!(url_encoded, "&event={}", event.as_str()).unwrap();
+        }
+        if let Some(tracker_id) = self.tracker_id {
+            // can be longer than what `percent_encode` takes
+            url_encoded.push_str("&trackerid=");
+            for b in tracker_id.bytes() {
+                if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                    url_encoded.push(b as char);
+                } else {
+                    write!(url_encoded, "%{:02X}", b).unwrap();
+                }
+            }
+        }
         url_encoded
     }
+
+    /// The announce request of the UDP tracker protocol (BEP 15).
+    ///
+    /// The UDP protocol always returns compact IPv4 peers, so `compact` isn't sent. There is no
+    /// tracker id either.
+    pub(crate) fn to_udp_announce(&self, connection_id: u64, transaction_id: u32) -> [u8; 98] {
+        let mut packet = [0u8; 98];
+        packet[..16].copy_from_slice(&header(connection_id, Action::Announce, transaction_id));
+        packet[16..36].copy_from_slice(self.info_hash);
+        packet[36..56].copy_from_slice(self.peer_id);
+        packet[56..64].copy_from_slice(&self.downloaded.to_be_bytes());
+        packet[64..72].copy_from_slice(&self.left.to_be_bytes());
+        packet[72..80].copy_from_slice(&self.uploaded.to_be_bytes());
+        let event = self.event.map_or(0, AnnounceEvent::udp_id);
+        packet[80..84].copy_from_slice(&event.to_be_bytes());
+        // ip 0 is the sender's
+        packet[88..92].copy_from_slice(&self.key.unwrap_or(0).to_be_bytes());
+        // -1 is the tracker's default
+        let num_want = self.num_want.map_or(-1, |n| n.min(i32::MAX as u32) as i32);
+        packet[92..96].copy_from_slice(&num_want.to_be_bytes());
+        packet[96..].copy_from_slice(&self.port.to_be_bytes());
+        packet
+    }
 }
 
 #[defmt_or_log::derive_format_or_debug]
 pub struct TrackerResponse {
+    /// seconds to wait before announcing again
     pub interval: u32,
+    /// announcing more often than this is refused by the tracker
+    pub min_interval: Option<u32>,
+    /// IPv4 and IPv6 peers, possibly with duplicates
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+    pub peers: Vec<SocketAddr>,
+    /// the request went through, but something is off (e.g. the client is outdated)
     #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
-    pub peers: Vec<core::net::SocketAddrV4, 10>,
+    pub warning_message: Option<String>,
+    /// has to be sent back with the next announces
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+    pub tracker_id: Option<String>,
+    /// the number of seeds
+    pub complete: Option<u32>,
+    /// the number of leechers
+    pub incomplete: Option<u32>,
+}
+
+/// Why a tracker's response can't be used.
+#[defmt_or_log::derive_format_or_debug]
+pub enum TrackerError {
+    /// the response isn't a valid bencoded dict
+    Parse(bencode::Error),
+    /// the tracker refused the request, with a human-readable reason
+    Failure(#[cfg_attr(feature = "defmt", defmt(Debug2Format))] String),
+}
+
+impl TrackerResponse {
+    /// Seconds to wait before the next regular announce, which respects `min interval`.
+    pub fn next_announce_in(&self) -> u32 {
+        self.interval.max(self.min_interval.unwrap_or(0))
+    }
+}
+
+/// Compact IPv4 peers (BEP 23): 4 bytes of address and 2 of port each, big-endian.
+pub(crate) fn compact_peers(bytes: &[u8]) -> impl Iterator<Item = SocketAddr> + '_ {
+    bytes.as_chunks::<6>().0.iter().map(|chunk| {
+        let ip = Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3]);
+        SocketAddr::new(ip.into(), u16::from_be_bytes([chunk[4], chunk[5]]))
+    })
+}
+
+/// Compact IPv6 peers (BEP 7): 16 bytes of address and 2 of port each, big-endian.
+fn compact_peers6(bytes: &[u8]) -> impl Iterator<Item = SocketAddr> + '_ {
+    bytes.as_chunks::<18>().0.iter().map(|chunk| {
+        let (ip, port) = chunk
+            .split_first_chunk::<16>()
+            .expect("18 bytes are longer");
+        SocketAddr::new(
+            Ipv6Addr::from(*ip).into(),
+            u16::from_be_bytes([port[0], port[1]]),
+        )
+    })
 }
 
 mod tracker_response_parser {
-    use bencode::{BencodeParser, Result};
-    use heapless::Vec;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use bencode::{BencodeDecode, BencodeParser, Error, ErrorKind, List, Value};
+    use core::net::{IpAddr, SocketAddr};
+
+    use crate::core::tracker::{TrackerError, TrackerResponse, compact_peers, compact_peers6};
 
-    use crate::core::tracker::TrackerResponse;
+    /// The original peer list (BEP 3): dicts with `ip`, `port` and `peer id`.
+    /// The peer id is skipped, the handshake tells it anyway. Peers with a DNS name
+    /// instead of an address are skipped as well.
+    fn dict_peers(list: List<'_>) -> impl Iterator<Item = SocketAddr> + '_ {
+        list.iter().filter_map(|peer| {
+            let ip: IpAddr = peer.get("ip")?.as_str()?.parse().ok()?;
+            let port = u16::try_from(peer.get("port")?.as_int()?).ok()?;
+            Some(SocketAddr::new(ip, port))
+        })
+    }
 
     impl TrackerResponse {
-        pub fn parse(input: &[u8]) -> Result<Self> {
+        /// Parses the bencoded response of an HTTP tracker.
+        /// A `failure reason` becomes [`TrackerError::Failure`], the other keys are meaningless then.
+        /// Otherwise `interval` is required, without it we would announce all the time.
+        pub fn parse(input: &[u8]) -> Result<Self, TrackerError> {
             let mut p = BencodeParser::new(input);
+            let mut interval = None;
 
-            let mut interval = 0;
-            let mut peers: Vec<core::net::SocketAddrV4, 10> = Vec::new();
+            let mut response = TrackerResponse {
+                interval: 0,
+                min_interval: None,
+                peers: Vec::new(),
+                warning_message: None,
+                tracker_id: None,
+                complete: None,
+                incomplete: None,
+            };
+            let mut failure_reason = None;
 
-            p.expect_dict_start()?;
+            let dict_start = p.offset();
+            p.expect_dict_start().map_err(TrackerError::Parse)?;
 
             while !p.match_dict_end() {
-                let key = p.parse_str()?;
+                let key = p.parse_str().map_err(TrackerError::Parse)?;
+                // negative or too large counts are `OutOfRange`
+                let count = |p: &mut BencodeParser<'_>, field| {
+                    u32::decode(p).map_err(|e| TrackerError::Parse(e.in_field(field)))
+                };
+                let string = |p: &mut BencodeParser<'_>, field| {
+                    p.parse_str()
+                        .map(String::from)
+                        .map_err(|e| TrackerError::Parse(e.in_field(field)))
+                };
 
                 match key {
-                    "interval" => {
-                        interval = p.parse_int()? as u32;
+                    "failure reason" => failure_reason = Some(string(&mut p, "failure reason")?),
+                    "warning message" => {
+                        response.warning_message = Some(string(&mut p, "warning message")?);
                     }
+                    "interval" => interval = Some(count(&mut p, "interval")?),
+                    "min interval" => response.min_interval = Some(count(&mut p, "min interval")?),
+                    "tracker id" => response.tracker_id = Some(string(&mut p, "tracker id")?),
+                    "complete" => response.complete = Some(count(&mut p, "complete")?),
+                    "incomplete" => response.incomplete = Some(count(&mut p, "incomplete")?),
                     "peers" => {
-                        let peer_bytes = p.parse_bytes()?;
-                        // Compact peer list parsing
-                        let peer_chunks = peer_bytes.as_chunks::<6>();
-
-                        peers.extend(peer_chunks.0.iter().take(peers.capacity()).map(|chunk| {
-                            let ip =
-                                core::net::Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3]);
-                            let port = u16::from_be_bytes([chunk[4], chunk[5]]);
-                            core::net::SocketAddrV4::new(ip, port)
-                        }));
+                        let start = p.offset();
+                        match Value::from_parser(&mut p)
+                            .map_err(|e| TrackerError::Parse(e.in_field("peers")))?
+                        {
+                            Value::Bytes(bytes) => response.peers.extend(compact_peers(bytes)),
+                            Value::List(list) => response.peers.extend(dict_peers(list)),
+                            _ => {
+                                return Err(TrackerError::Parse(
+                                    Error::new(ErrorKind::InvalidSyntax, start).in_field("peers"),
+                                ));
+                            }
+                        }
+                    }
+                    "peers6" => {
+                        let bytes = p
+                            .parse_bytes()
+                            .map_err(|e| TrackerError::Parse(e.in_field("peers6")))?;
+                        response.peers.extend(compact_peers6(bytes));
                     }
                     _ => {
-                        p.skip_any()?;
+                        p.skip_any().map_err(TrackerError::Parse)?;
                     }
                 }
             }
 
-            Ok(TrackerResponse { interval, peers })
+            if let Some(reason) = failure_reason {
+                return Err(TrackerError::Failure(reason));
+            }
+            response.interval = interval.ok_or(TrackerError::Parse(Error::new(
+                ErrorKind::MissingField("interval"),
+                dict_start,
+            )))?;
+            Ok(response)
         }
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bencode::ErrorKind;
+
     #[test]
     fn test_tracker_request_url_encoding() {
         let info_hash: InfoHash = [0u8; 20];
@@ -128,6 +362,60 @@ mod tests {
         assert!(url_encoded.contains("downloaded=0"));
         assert!(url_encoded.contains("left=1000"));
         assert!(url_encoded.contains("compact=1"));
+        assert!(!url_encoded.contains("numwant"));
+        assert!(
+            request
+                .clone()
+                .with_num_want(30)
+                .to_url_encoded()
+                .ends_with("&numwant=30")
+        );
+        assert!(
+            request
+                .clone()
+                .with_key(0xab)
+                .to_url_encoded()
+                .ends_with("&key=000000AB")
+        );
+
+        let url_encoded = request
+            .with_transferred(5, 10)
+            .with_event(Some(AnnounceEvent::Started))
+            .with_tracker_id(Some("a b"))
+            .to_url_encoded();
+        assert!(url_encoded.contains("uploaded=5"));
+        assert!(url_encoded.contains("downloaded=10"));
+        assert!(url_encoded.ends_with("&event=started&trackerid=a%20b"));
+    }
+
+    #[test]
+    fn test_udp_announce() {
+        let info_hash: InfoHash = [0xaa; 20];
+        let peer_id: PeerId = [0xbb; 20];
+        let request = TrackerRequest::new(&info_hash, &peer_id, 6881, 1000);
+
+        let packet = request.to_udp_announce(0x0102030405060708, 42);
+        assert_eq!(packet[..8], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(packet[8..16], [0, 0, 0, 1, 0, 0, 0, 42]);
+        assert_eq!(packet[16..36], info_hash);
+        assert_eq!(packet[36..56], peer_id);
+        assert_eq!(packet[64..72], 1000u64.to_be_bytes());
+        assert_eq!(packet[80..84], [0; 4]);
+        assert_eq!(packet[92..96], [0xff; 4]);
+        assert_eq!(packet[96..], 6881u16.to_be_bytes());
+
+        let packet = request
+            .clone()
+            .with_num_want(30)
+            .with_transferred(5, 10)
+            .with_event(Some(AnnounceEvent::Completed))
+            .with_key(0xdeadbeef)
+            .to_udp_announce(1, 2);
+        assert_eq!(packet[56..64], 10u64.to_be_bytes());
+        assert_eq!(packet[72..80], 5u64.to_be_bytes());
+        assert_eq!(packet[80..84], 1u32.to_be_bytes());
+        assert_eq!(packet[88..92], 0xdeadbeefu32.to_be_bytes());
+        assert_eq!(packet[92..96], 30u32.to_be_bytes());
     }
 
     #[test]
@@ -140,13 +428,98 @@ mod tests {
         assert_eq!(response.peers.len(), 2);
         assert_eq!(
             response.peers[0].ip(),
-            &core::net::Ipv4Addr::new(127, 0, 0, 1)
+            core::net::Ipv4Addr::new(127, 0, 0, 1)
         );
         assert_eq!(response.peers[0].port(), 6881);
         assert_eq!(
             response.peers[1].ip(),
-            &core::net::Ipv4Addr::new(127, 0, 0, 2)
+            core::net::Ipv4Addr::new(127, 0, 0, 2)
         );
         assert_eq!(response.peers[1].port(), 6882);
+        assert_eq!(response.min_interval, None);
+        assert_eq!(response.next_announce_in(), 1800);
+    }
+
+    #[test]
+    fn test_tracker_response_all_fields() {
+        let bencoded_response = b"d8:completei5e5:extrai1e10:incompletei3e8:intervali60e\
+            12:min intervali900e5:peers0:10:tracker id3:abc15:warning message3:olde";
+        let response = TrackerResponse::parse(bencoded_response).unwrap();
+        assert_eq!(response.complete, Some(5));
+        assert_eq!(response.incomplete, Some(3));
+        assert_eq!(response.min_interval, Some(900));
+        assert_eq!(response.next_announce_in(), 900);
+        assert_eq!(response.tracker_id.as_deref(), Some("abc"));
+        assert_eq!(response.warning_message.as_deref(), Some("old"));
+        assert!(response.peers.is_empty());
+    }
+
+    #[test]
+    fn test_tracker_response_dict_peers_and_peers6() {
+        let mut bencoded_response = b"d8:intervali1800e5:peersl".to_vec();
+        bencoded_response
+            .extend_from_slice(b"d2:ip9:127.0.0.17:peer id20:aaaaaaaaaaaaaaaaaaaa4:porti6881ee");
+        bencoded_response.extend_from_slice(b"d2:ip3:::14:porti6882ee");
+        // a DNS name and a broken port are skipped
+        bencoded_response.extend_from_slice(b"d2:ip11:example.com4:porti1ee");
+        bencoded_response.extend_from_slice(b"d2:ip8:10.0.0.14:porti70000ee");
+        bencoded_response.extend_from_slice(b"e6:peers618:");
+        bencoded_response.extend_from_slice(&core::net::Ipv6Addr::LOCALHOST.octets());
+        bencoded_response.extend_from_slice(&[0x1a, 0xe3, b'e']);
+
+        let response = TrackerResponse::parse(&bencoded_response).unwrap();
+        assert_eq!(
+            response.peers,
+            [
+                "127.0.0.1:6881".parse().unwrap(),
+                "[::1]:6882".parse().unwrap(),
+                "[::1]:6883".parse().unwrap()
+            ]
+        );
+
+        // neither a string nor a list
+        assert!(matches!(
+            TrackerResponse::parse(b"d5:peersi1ee"),
+            Err(TrackerError::Parse(e)) if e.path().eq(["peers"])
+        ));
+    }
+
+    #[test]
+    fn test_tracker_response_failure() {
+        let bencoded_response = b"d14:failure reason20:unregistered torrente";
+        assert!(matches!(
+            TrackerResponse::parse(bencoded_response),
+            Err(TrackerError::Failure(reason)) if reason == "unregistered torrent"
+        ));
+        assert!(matches!(
+            TrackerResponse::parse(b"d8:intervali1800e"),
+            Err(TrackerError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_tracker_response_counts_out_of_range() {
+        for input in [
+            &b"d8:intervali-1e5:peers0:e"[..],
+            b"d8:intervali4294967296e5:peers0:e",
+            b"d8:completei-1e8:intervali1800e5:peers0:e",
+            b"d8:intervali1800e12:min intervali4294967296e5:peers0:e",
+        ] {
+            assert!(matches!(
+                TrackerResponse::parse(input),
+                Err(TrackerError::Parse(e)) if e.kind() == ErrorKind::OutOfRange
+            ));
+        }
+        // the largest interval still fits
+        let response = TrackerResponse::parse(b"d8:intervali4294967295e5:peers0:e").unwrap();
+        assert_eq!(response.interval, u32::MAX);
+    }
+
+    #[test]
+    fn test_tracker_response_missing_interval() {
+        assert!(matches!(
+            TrackerResponse::parse(b"d5:peers0:e"),
+            Err(TrackerError::Parse(e)) if e.kind() == ErrorKind::MissingField("interval")
+        ));
     }
 }
@@ -0,0 +1,138 @@
+//! The trackers of a torrent, grouped into tiers (BEP 12).
+//!
+//! The trackers of a tier are shuffled once, then tried in order. The first one that answers is
+//! moved to the front of its tier, so it's tried first next time.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::MetaInfoFile;
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "log", derive(Debug))]
+pub struct TrackerTiers {
+    /// never empty, and neither are the tiers
+    tiers: Vec<Vec<String>>,
+}
+
+impl TrackerTiers {
+    /// The `announce-list` of the torrent, `announce` is ignored then (as BEP 12 says).
+    /// Without a usable `announce-list` it's `announce` alone, `None` without any tracker.
+    pub fn from_metainfo(metainfo: &MetaInfoFile<'_>) -> Option<Self> {
+        let tiers: Vec<Vec<String>> = metainfo
+            .announce_list
+            .iter()
+            .flat_map(|list| list.tiers())
+            .map(|tier| {
+                tier.urls()
+                    .filter(|url| !url.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .filter(|tier: &Vec<String>| !tier.is_empty())
+            .collect();
+        if tiers.is_empty() {
+            let announce = metainfo.announce.filter(|url| !url.is_empty())?;
+            Some(Self {
+                tiers: alloc::vec![alloc::vec![String::from(announce)]],
+            })
+        } else {
+            Some(Self { tiers })
+        }
+    }
+
+    /// One tier per tracker, e.g. for the trackers of a magnet link, `None` without any.
+    pub fn from_urls(urls: &[String]) -> Option<Self> {
+        if urls.is_empty() {
+            return None;
+        }
+        Some(Self {
+            tiers: urls.iter().map(|url| alloc::vec![url.clone()]).collect(),
+        })
+    }
+
+    /// Shuffles the trackers within each tier, `random` gives the randomness.
+    pub fn shuffle(&mut self, mut random: impl FnMut() -> u32) {
+        for tier in &mut self.tiers {
+            // Fisher-Yates
+            for i in (1..tier.len()).rev() {
+                tier.swap(i, random() as usize % (i + 1));
+            }
+        }
+    }
+
+    /// The tiers in the order they are tried.
+    pub fn tiers(&self) -> impl Iterator<Item = &[String]> {
+        self.tiers.iter().map(Vec::as_slice)
+    }
+
+    /// Moves the tracker at `index` of `tier` to the front of the tier, after it answered.
+    pub fn promote(&mut self, tier: usize, index: usize) {
+        self.tiers[tier][..=index].rotate_right(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(tiers: &TrackerTiers) -> Vec<Vec<&str>> {
+        tiers
+            .tiers()
+            .map(|tier| tier.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_from_metainfo() {
+        let input = b"d8:announce3:url13:announce-listll1:a1:bel0:el1:cee\
+            4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:ee";
+        let torrent = MetaInfoFile::parse(input).unwrap();
+        // `announce` is ignored and empty tiers are skipped
+        assert_eq!(
+            urls(&TrackerTiers::from_metainfo(&torrent).unwrap()),
+            [alloc::vec!["a", "b"], alloc::vec!["c"]]
+        );
+
+        let input = b"d8:announce3:url4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:ee";
+        let torrent = MetaInfoFile::parse(input).unwrap();
+        assert_eq!(
+            urls(&TrackerTiers::from_metainfo(&torrent).unwrap()),
+            [["url"]]
+        );
+
+        // no tracker at all, there is no fallback
+        let input = b"d4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:ee";
+        let torrent = MetaInfoFile::parse(input).unwrap();
+        assert_eq!(TrackerTiers::from_metainfo(&torrent), None);
+        let input = b"d8:announce0:13:announce-listll0:ee\
+            4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:ee";
+        let torrent = MetaInfoFile::parse(input).unwrap();
+        assert_eq!(TrackerTiers::from_metainfo(&torrent), None);
+    }
+
+    #[test]
+    fn test_from_urls() {
+        let tiers = TrackerTiers::from_urls(&["a".into(), "b".into()]).unwrap();
+        assert_eq!(urls(&tiers), [["a"], ["b"]]);
+        // e.g. a magnet link without `tr`, there is no fallback
+        assert_eq!(TrackerTiers::from_urls(&[]), None);
+    }
+
+    #[test]
+    fn test_shuffle_and_promote() {
+        let mut tiers = TrackerTiers {
+            tiers: alloc::vec![
+                alloc::vec!["a".into(), "b".into(), "c".into()],
+                alloc::vec!["d".into()],
+            ],
+        };
+        // always swapping with the first one
+        tiers.shuffle(|| 0);
+        assert_eq!(urls(&tiers), [alloc::vec!["b", "c", "a"], alloc::vec!["d"]]);
+
+        tiers.promote(0, 2);
+        assert_eq!(urls(&tiers), [alloc::vec!["a", "b", "c"], alloc::vec!["d"]]);
+        tiers.promote(0, 0);
+        assert_eq!(urls(&tiers), [alloc::vec!["a", "b", "c"], alloc::vec!["d"]]);
+    }
+}
@@ -0,0 +1,227 @@
+//! The packets of the UDP tracker protocol (BEP 15).
+//!
+//! Every exchange starts with a connect request, the connection id of the response is then sent
+//! with announces and scrapes. Responses are matched to requests by their transaction id.
+//! All integers are big-endian.
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::core::{
+    InfoHash,
+    scrape::ScrapeStats,
+    tracker::{TrackerResponse, compact_peers},
+};
+
+/// Magic constant that starts every connect request.
+const PROTOCOL_ID: u64 = 0x0417_2710_1980;
+
+/// The first word of every packet.
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub enum Action {
+    Connect = 0,
+    Announce = 1,
+    Scrape = 2,
+    Error = 3,
+}
+
+/// A response of a UDP tracker.
+#[cfg_attr(feature = "log", derive(Debug))]
+pub enum UdpResponse<'a> {
+    Connect {
+        connection_id: u64,
+    },
+    Announce(Box<TrackerResponse>),
+    /// the stats in the order of the requested info hashes
+    Scrape(Vec<ScrapeStats>),
+    /// a human-readable message why the request failed
+    Error(&'a str),
+}
+
+impl UdpResponse<'_> {
+    /// The action the response was sent with.
+    pub const fn action(&self) -> Action {
+        match self {
+            UdpResponse::Connect { .. } => Action::Connect,
+            UdpResponse::Announce(_) => Action::Announce,
+            UdpResponse::Scrape(_) => Action::Scrape,
+            UdpResponse::Error(_) => Action::Error,
+        }
+    }
+}
+
+/// Asks the tracker for a connection id, which is valid for a minute.
+pub fn connect_request(transaction_id: u32) -> [u8; 16] {
+    let mut packet = [0u8; 16];
+    packet[..8].copy_from_slice(&PROTOCOL_ID.to_be_bytes());
+    packet[8..12].copy_from_slice(&(Action::Connect as u32).to_be_bytes());
+    packet[12..].copy_from_slice(&transaction_id.to_be_bytes());
+    packet
+}
+
+/// A scrape request for up to [`MAX_UDP_SCRAPE`](crate::core::scrape::MAX_UDP_SCRAPE) info hashes.
+pub fn scrape_request(
+    connection_id: u64,
+    transaction_id: u32,
+    info_hashes: &[InfoHash],
+) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(16 + info_hashes.len() * 20);
+    packet.extend_from_slice(&header(connection_id, Action::Scrape, transaction_id));
+    packet.extend_from_slice(info_hashes.as_flattened());
+    packet
+}
+
+/// The 16 bytes every announce and scrape request starts with.
+pub fn header(connection_id: u64, action: Action, transaction_id: u32) -> [u8; 16] {
+    let mut header = [0u8; 16];
+    header[..8].copy_from_slice(&connection_id.to_be_bytes());
+    header[8..12].copy_from_slice(&(action as u32).to_be_bytes());
+    header[12..].copy_from_slice(&transaction_id.to_be_bytes());
+    header
+}
+
+/// Parses a datagram received from the tracker.
+///
+/// Returns `None` for datagrams that don't answer the request with `transaction_id`, are too short
+/// or have an unknown action, as BEP 15 says to ignore them.
+pub fn parse_response(packet: &[u8], transaction_id: u32) -> Option<UdpResponse<'_>> {
+    let word = |i: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(packet.get(i..i + 4)?.try_into().ok()?))
+    };
+    if word(4)? != transaction_id {
+        return None;
+    }
+    let body = &packet[8..];
+
+    match word(0)? {
+        a if a == Action::Connect as u32 => Some(UdpResponse::Connect {
+            connection_id: u64::from_be_bytes(body.get(..8)?.try_into().ok()?),
+        }),
+        a if a == Action::Announce as u32 => {
+            // interval, leechers, seeders, then compact peers
+            let interval = word(8)?;
+            let leechers = word(12)?;
+            let seeders = word(16)?;
+            let peers = compact_peers(body.get(12..)?).collect();
+            Some(UdpResponse::Announce(Box::new(TrackerResponse {
+                interval,
+                min_interval: None,
+                peers,
+                warning_message: None,
+                tracker_id: None,
+                complete: Some(seeders),
+                incomplete: Some(leechers),
+            })))
+        }
+        a if a == Action::Scrape as u32 => {
+            let (chunks, _) = body.as_chunks::<12>();
+            let stats = chunks
+                .iter()
+                .map(|chunk| {
+                    let word = |i: usize| {
+                        u32::from_be_bytes([chunk[i], chunk[i + 1], chunk[i + 2], chunk[i + 3]])
+                    };
+                    // seeders, completed, leechers
+                    ScrapeStats {
+                        complete: word(0),
+                        downloaded: word(4),
+                        incomplete: word(8),
+                    }
+                })
+                .collect();
+            Some(UdpResponse::Scrape(stats))
+        }
+        a if a == Action::Error as u32 => Some(UdpResponse::Error(
+            core::str::from_utf8(body).unwrap_or("invalid utf-8 in error message"),
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect() {
+        let request = connect_request(0xdeadbeef);
+        assert_eq!(
+            request,
+            [
+                0x00, 0x00, 0x04, 0x17, 0x27, 0x10, 0x19, 0x80, 0, 0, 0, 0, 0xde, 0xad, 0xbe, 0xef
+            ]
+        );
+
+        let response = [0, 0, 0, 0, 0xde, 0xad, 0xbe, 0xef, 1, 2, 3, 4, 5, 6, 7, 8];
+        assert!(matches!(
+            parse_response(&response, 0xdeadbeef),
+            Some(UdpResponse::Connect {
+                connection_id: 0x0102030405060708
+            })
+        ));
+        // another transaction or too short
+        assert!(parse_response(&response, 1).is_none());
+        assert!(parse_response(&response[..12], 0xdeadbeef).is_none());
+    }
+
+    #[test]
+    fn test_announce_response() {
+        let mut response = alloc::vec![0, 0, 0, 1, 0, 0, 0, 7];
+        response.extend_from_slice(&1800u32.to_be_bytes()); // interval
+        response.extend_from_slice(&3u32.to_be_bytes()); // leechers
+        response.extend_from_slice(&5u32.to_be_bytes()); // seeders
+        response.extend_from_slice(&[127, 0, 0, 1, 0x1a, 0xe1, 10, 0, 0, 2, 0x1a, 0xe2]);
+
+        let Some(UdpResponse::Announce(announce)) = parse_response(&response, 7) else {
+            panic!("expected an announce response");
+        };
+        assert_eq!(announce.interval, 1800);
+        assert_eq!(announce.complete, Some(5));
+        assert_eq!(announce.incomplete, Some(3));
+        assert_eq!(
+            announce.peers,
+            [
+                "127.0.0.1:6881".parse().unwrap(),
+                "10.0.0.2:6882".parse().unwrap()
+            ]
+        );
+
+        // without peers, but not even the interval
+        assert!(parse_response(&response[..10], 7).is_none());
+    }
+
+    #[test]
+    fn test_scrape() {
+        let request = scrape_request(1, 2, &[[0xaa; 20], [0xbb; 20]]);
+        assert_eq!(request.len(), 56);
+        assert_eq!(request[..16], header(1, Action::Scrape, 2));
+        assert_eq!(request[36..], [0xbb; 20]);
+
+        let mut response = alloc::vec![0, 0, 0, 2, 0, 0, 0, 2];
+        for n in [10u32, 20, 30] {
+            response.extend_from_slice(&n.to_be_bytes());
+        }
+        let Some(UdpResponse::Scrape(stats)) = parse_response(&response, 2) else {
+            panic!("expected a scrape response");
+        };
+        assert_eq!(
+            stats,
+            [ScrapeStats {
+                complete: 10,
+                downloaded: 20,
+                incomplete: 30
+            }]
+        );
+    }
+
+    #[test]
+    fn test_error_response() {
+        let mut response = alloc::vec![0, 0, 0, 3, 0, 0, 0, 9];
+        response.extend_from_slice(b"unregistered torrent");
+        assert!(matches!(
+            parse_response(&response, 9),
+            Some(UdpResponse::Error("unregistered torrent"))
+        ));
+    }
+}
@@ -0,0 +1,390 @@
+use alloc::vec::Vec;
+use core::{fmt::Write as _, ops::Range};
+use embedded_sdmmc::ShortFileName;
+
+use crate::core::metainfo::{FileLayout, Info};
+
+/// A file of the torrent and where it lies in the byte stream the pieces are cut from.
+#[cfg_attr(feature = "log", derive(Debug))]
+pub struct TorrentFile {
+    /// offset of the file's first byte in the torrent
+    pub offset: u64,
+    pub length: u64,
+    /// the directories from the root of the filesystem and finally the file name
+    pub path: Vec<ShortFileName>,
+    /// padding files (BEP 47) only align the next file to a piece boundary and aren't written
+    pub padding: bool,
+}
+
+/// Maps the torrent's byte stream (all files concatenated) onto the files on disk.
+///
+/// Single-file torrents become a file called `name` in the root directory,
+/// multi-file torrents a directory `name` with the files' paths below it.
+/// In v2-only torrents every file starts at a piece boundary, the gaps are skipped like padding.
+#[cfg_attr(feature = "log", derive(Debug))]
+pub struct FileMap {
+    files: Vec<TorrentFile>,
+    total_length: u64,
+}
+
+/// The part of a write that belongs to a single file.
+pub struct FileSpan<'a> {
+    pub file: &'a TorrentFile,
+    /// where to write in the file
+    pub file_offset: u64,
+    /// the range of the written data that goes into the file
+    pub data: Range<usize>,
+}
+
+impl FileMap {
+    pub fn new(info: &Info<'_>) -> Self {
+        let mut files = Vec::new();
+        let mut offset = 0u64;
+        let mut names = ShortNames::default();
+
+        match &info.layout {
+            FileLayout::SingleFile { length } => {
+                files.push(TorrentFile {
+                    offset,
+                    length: *length,
+                    path: names.path([info.name]),
+                    padding: false,
+                });
+                offset += length;
+            }
+            FileLayout::MultiFile { files: entries } => {
+                for entry in entries.iter() {
+                    let path = names.path(core::iter::once(info.name).chain(entry.path.segments()));
+                    files.push(TorrentFile {
+                        offset,
+                        length: entry.length,
+                        path,
+                        padding: entry.is_padding(),
+                    });
+                    offset += entry.length;
+                }
+            }
+            FileLayout::Tree { tree } => {
+                // a piece length of zero is rejected by `MetaInfoFile::validate`
+                let piece_length = (info.piece_length as u64).max(1);
+                for file in tree.files() {
+                    offset = offset.next_multiple_of(piece_length);
+                    let path = names.path(core::iter::once(info.name).chain(file.path));
+                    files.push(TorrentFile {
+                        offset,
+                        length: file.length,
+                        path,
+                        padding: false,
+                    });
+                    offset += file.length;
+                }
+            }
+        }
+
+        Self {
+            files,
+            total_length: offset,
+        }
+    }
+
+    #[inline]
+    pub fn files(&self) -> &[TorrentFile] {
+        &self.files
+    }
+
+    #[inline]
+    pub const fn total_length(&self) -> u64 {
+        self.total_length
+    }
+
+    /// Splits a write of `len` bytes at the absolute `offset` into the parts for each file.
+    /// Empty and padding files are skipped.
+    pub fn spans(&self, offset: u64, len: usize) -> impl Iterator<Item = FileSpan<'_>> {
+        let end = offset + len as u64;
+        self.files
+            .iter()
+            .filter(move |f| {
+                !f.padding && f.length > 0 && f.offset < end && f.offset + f.length > offset
+            })
+            .map(move |file| {
+                let start = offset.max(file.offset);
+                let stop = end.min(file.offset + file.length);
+                FileSpan {
+                    file,
+                    file_offset: start - file.offset,
+                    data: (start - offset) as usize..(stop - offset) as usize,
+                }
+            })
+    }
+}
+
+/// Gives every file and directory of a torrent an 8.3 name, a directory keeps its name for all
+/// the files in it and names within a directory never collide.
+#[derive(Default)]
+struct ShortNames<'a> {
+    names: Vec<ShortNameEntry<'a>>,
+}
+
+struct ShortNameEntry<'a> {
+    /// the index of the directory it is in, `None` for the root
+    parent: Option<usize>,
+    long: &'a str,
+    short: ShortFileName,
+}
+
+impl<'a> ShortNames<'a> {
+    /// The short names of the directories along `segments` and finally the file name.
+    fn path(&mut self, segments: impl IntoIterator<Item = &'a str>) -> Vec<ShortFileName> {
+        let mut parent = None;
+        let mut path = Vec::new();
+        for segment in segments {
+            let index = self.get(parent, segment);
+            path.push(self.names[index].short.clone());
+            parent = Some(index);
+        }
+        path
+    }
+
+    /// The index of `name` in `parent`, it gets a short name if it hasn't got one yet.
+    fn get(&mut self, parent: Option<usize>, name: &'a str) -> usize {
+        let siblings = || {
+            self.names
+                .iter()
+                .enumerate()
+                .filter(move |(_, entry)| entry.parent == parent)
+        };
+        if let Some((index, _)) = siblings().find(|(_, entry)| entry.long == name) {
+            return index;
+        }
+
+        let taken = |short: &ShortFileName| siblings().any(|(_, entry)| entry.short == *short);
+        // names that fit are kept, unless another name already became the same (e.g. `a` and `A`)
+        let short = fitting_short_name(name)
+            .filter(|short| !taken(short))
+            .or_else(|| {
+                (1..1_000_000)
+                    .map(|n| shortened_name(name, n))
+                    .find(|short| !taken(short))
+            })
+            .expect("less than a million files in a directory");
+        self.names.push(ShortNameEntry {
+            parent,
+            long: name,
+            short,
+        });
+        self.names.len() - 1
+    }
+}
+
+/// `name` as an 8.3 name, if it fits without shortening.
+fn fitting_short_name(name: &str) -> Option<ShortFileName> {
+    if name == "." || name == ".." {
+        return None;
+    }
+    ShortFileName::create_from_str(name).ok()
+}
+
+/// Converts a name into an 8.3 name, which is all `embedded_sdmmc` can create.
+///
+/// Names that already fit are kept, everything else is cut down to the first valid characters
+/// of the base name, `~` and `n`, like Windows does it (e.g. `LONGFI~1.TXT` or `LONGF~12.TXT`).
+pub(crate) fn to_short_name(name: &str, n: usize) -> ShortFileName {
+    fitting_short_name(name).unwrap_or_else(|| shortened_name(name, n))
+}
+
+/// `name` cut down with the suffix `~n`, even if it would fit.
+fn shortened_name(name: &str, n: usize) -> ShortFileName {
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((base, ext)) if !base.is_empty() => (base, ext),
+        _ => (name, ""),
+    };
+    let valid = |c: &char| c.is_ascii_alphanumeric() || *c == '_' || *c == '-';
+
+    // at most 8 characters for the base name with "~n", then "." + 3 characters
+    let mut suffix: heapless::String<8> = heapless::String::new();
+    write!(suffix, "~{n}").expect("n has at most 7 digits");
+    let mut short: heapless::String<12> =
+        base.chars().filter(valid).take(8 - suffix.len()).collect();
+    if short.is_empty() {
+        short.push('F').expect("fits");
+    }
+    short.push_str(&suffix).expect("fits");
+
+    let mut ext = ext.chars().filter(valid).take(3).peekable();
+    if ext.peek().is_some() {
+        short.push('.').expect("fits");
+        for c in ext {
+            short.push(c).expect("fits");
+        }
+    }
+
+    ShortFileName::create_from_str(&short).expect("only valid characters were kept")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(offset: u64, length: u64) -> TorrentFile {
+        TorrentFile {
+            offset,
+            length,
+            path: Vec::new(),
+            padding: false,
+        }
+    }
+
+    #[test]
+    fn test_spans_across_files() {
+        // 10 + 0 + 5 + 20 bytes
+        let map = FileMap {
+            files: alloc::vec![file(0, 10), file(10, 0), file(10, 5), file(15, 20)],
+            total_length: 35,
+        };
+
+        // everything within the first file
+        let spans: Vec<_> = map.spans(2, 4).collect();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].file_offset, 2);
+        assert_eq!(spans[0].data, 0..4);
+
+        // across all files, the empty one is skipped
+        let spans: Vec<_> = map.spans(8, 10).collect();
+        assert_eq!(spans.len(), 3);
+        assert_eq!((spans[0].file.offset, spans[0].file_offset), (0, 8));
+        assert_eq!(spans[0].data, 0..2);
+        assert_eq!((spans[1].file.offset, spans[1].file_offset), (10, 0));
+        assert_eq!(spans[1].data, 2..7);
+        assert_eq!((spans[2].file.offset, spans[2].file_offset), (15, 0));
+        assert_eq!(spans[2].data, 7..10);
+
+        // the end of the last file
+        let spans: Vec<_> = map.spans(30, 5).collect();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].file_offset, 15);
+    }
+
+    #[test]
+    fn test_file_map_from_info() {
+        let input = b"d5:filesld6:lengthi10e4:pathl3:dir5:a.txteed6:lengthi5e4:pathl16:a long name.jpegeee4:name4:root12:piece lengthi16e6:pieces0:e";
+        let info = Info::parse(input).unwrap();
+        let map = FileMap::new(&info);
+
+        assert_eq!(map.total_length(), 15);
+        assert_eq!(map.files().len(), 2);
+
+        let first = &map.files()[0];
+        assert_eq!(first.offset, 0);
+        let path: Vec<_> = first.path.iter().map(|p| p.to_string()).collect();
+        assert_eq!(path, ["ROOT", "DIR", "A.TXT"]);
+
+        let second = &map.files()[1];
+        assert_eq!(second.offset, 10);
+        assert_eq!(second.length, 5);
+        let path: Vec<_> = second.path.iter().map(|p| p.to_string()).collect();
+        assert_eq!(path, ["ROOT", "ALONGN~1.JPE"]);
+    }
+
+    #[test]
+    fn test_file_map_v2() {
+        let mut input = Vec::new();
+        input.extend_from_slice(b"d9:file treed1:bd0:d6:lengthi10e11:pieces root32:");
+        input.extend_from_slice(&[b'r'; 32]);
+        input.extend_from_slice(b"ee1:cd0:d6:lengthi5e11:pieces root32:");
+        input.extend_from_slice(&[b's'; 32]);
+        input.extend_from_slice(b"eee12:meta versioni2e4:name4:root12:piece lengthi16384ee");
+        let info = Info::parse(&input).unwrap();
+        let map = FileMap::new(&info);
+
+        // every file starts at a piece boundary
+        assert_eq!(map.files()[0].offset, 0);
+        assert_eq!(map.files()[1].offset, 16384);
+        assert_eq!(map.total_length(), 16389);
+        let path: Vec<_> = map.files()[1].path.iter().map(|p| p.to_string()).collect();
+        assert_eq!(path, ["ROOT", "C"]);
+    }
+
+    #[test]
+    fn test_spans_skip_padding() {
+        let mut padding = file(10, 6);
+        padding.padding = true;
+        let map = FileMap {
+            files: alloc::vec![file(0, 10), padding, file(16, 4)],
+            total_length: 20,
+        };
+
+        let spans: Vec<_> = map.spans(8, 10).collect();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].data, 0..2);
+        assert_eq!((spans[1].file.offset, spans[1].file_offset), (16, 0));
+        assert_eq!(spans[1].data, 8..10);
+    }
+
+    #[test]
+    fn test_to_short_name() {
+        assert_eq!(to_short_name("sample.txt", 0).to_string(), "SAMPLE.TXT");
+        assert_eq!(to_short_name("README", 0).to_string(), "README");
+        assert_eq!(
+            to_short_name("ubuntu-24.04-desktop.iso", 3).to_string(),
+            "UBUNTU~3.ISO"
+        );
+        assert_eq!(to_short_name("...", 2).to_string(), "F~2");
+        assert_eq!(to_short_name(".hidden", 1).to_string(), "HIDDEN~1");
+        assert_eq!(
+            to_short_name("long name.txt", 12).to_string(),
+            "LONGN~12.TXT"
+        );
+    }
+
+    fn paths(map: &FileMap) -> Vec<alloc::string::String> {
+        map.files()
+            .iter()
+            .map(|f| {
+                let segments: Vec<_> = f.path.iter().map(|p| p.to_string()).collect();
+                segments.join("/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_file_map_long_directory() {
+        let input = b"d5:filesld6:lengthi1e4:pathl8:Season 15:a.mkveed6:lengthi1e4:pathl8:Season 15:b.mkveed6:lengthi1e4:pathl8:Season 25:a.mkveee4:name4:root12:piece lengthi16e6:pieces0:e";
+        let info = Info::parse(input).unwrap();
+        let map = FileMap::new(&info);
+
+        // one directory for both files of the first season
+        assert_eq!(
+            paths(&map),
+            [
+                "ROOT/SEASON~1/A.MKV",
+                "ROOT/SEASON~1/B.MKV",
+                "ROOT/SEASON~2/A.MKV"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_map_many_long_names() {
+        let mut input = b"d5:filesl".to_vec();
+        for i in 0..12 {
+            let name = alloc::format!("long name {i:02}.txt");
+            input.extend_from_slice(
+                alloc::format!("d6:lengthi1e4:pathl{}:{name}ee", name.len()).as_bytes(),
+            );
+        }
+        // a name that fits, but looks like a shortened one
+        input.extend_from_slice(b"d6:lengthi1e4:pathl12:LONGNA~1.TXTeee");
+        input.extend_from_slice(b"4:name4:root12:piece lengthi16e6:pieces0:e");
+        let info = Info::parse(&input).unwrap();
+        let map = FileMap::new(&info);
+
+        let paths = paths(&map);
+        assert_eq!(paths[0], "ROOT/LONGNA~1.TXT");
+        assert_eq!(paths[9], "ROOT/LONGN~10.TXT");
+        assert_eq!(paths[11], "ROOT/LONGN~12.TXT");
+        assert_eq!(paths[12], "ROOT/LONGN~13.TXT");
+        for (i, path) in paths.iter().enumerate() {
+            assert!(!paths[..i].contains(path), "{path} is used twice");
+        }
+    }
+}
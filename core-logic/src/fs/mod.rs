@@ -1,8 +1,12 @@
 use embedded_sdmmc::{RawDirectory, RawFile, RawVolume, filesystem::ToShortFileName};
 
+pub mod file_map;
 mod operations;
+pub mod torrent_creation;
 pub mod torrent_retrieval;
+mod torrent_storage;
 mod volume_mgr;
+pub use file_map::{FileMap, TorrentFile};
 pub use volume_mgr::VolumeMgr;
 
 /// A trait that provides some common operations for the filesystem.
@@ -0,0 +1,190 @@
+use alloc::{
+    string::{String, ToString as _},
+    vec::Vec,
+};
+use embedded_sdmmc::{BlockDevice, LfnBuffer, Mode, ShortFileName};
+
+use crate::{
+    Sha1Hasher,
+    core::{
+        InfoHash,
+        builder::{Source, SourceFile, TorrentBuilder},
+    },
+    fs::{FileSystem, FileSystemExt, VolumeMgr},
+};
+
+/// Errors while creating a torrent.
+#[defmt_or_log::derive_format_or_debug]
+pub enum BuildError<V>
+where
+    V: VolumeMgr,
+{
+    /// File system operation failed.
+    FsError(embedded_sdmmc::Error<<<V as VolumeMgr>::BlockDevice as BlockDevice>::Error>),
+    /// There is no file or directory with the given name in the root directory.
+    NotFound,
+    /// The piece length is zero.
+    ZeroPieceLength,
+    /// The torrent doesn't fit into the buffer.
+    BufferTooSmall,
+}
+
+/// An entry of a directory with its long name, if it has one.
+struct Entry {
+    short: ShortFileName,
+    name: String,
+    is_dir: bool,
+    size: u32,
+}
+
+impl<'a> TorrentBuilder<'a> {
+    /// Creates a torrent of the file or directory `name` in the root directory of `fs`.
+    /// Directories are walked recursively.
+    ///
+    /// The data is read piece by piece and hashed with `hasher`, the bencoded torrent is written
+    /// into `buf`. Returns its length, save it with [`FileSystem::save_torrent`] to seed it later.
+    pub async fn build<V: VolumeMgr>(
+        &self,
+        fs: &mut FileSystem<V>,
+        name: &str,
+        hasher: &impl Sha1Hasher,
+        buf: &mut [u8],
+    ) -> Result<usize, BuildError<V>> {
+        if self.piece_length == 0 {
+            return Err(BuildError::ZeroPieceLength);
+        }
+
+        fs.go_to_root_dir();
+        let root = fs
+            .list_current_dir()
+            .map_err(BuildError::FsError)?
+            .into_iter()
+            .find(|entry| entry.name == name || entry.short.to_string() == name)
+            .ok_or(BuildError::NotFound)?;
+
+        // the files with their paths in the torrent and on disk
+        let mut files: Vec<(SourceFile, Vec<ShortFileName>)> = Vec::new();
+        if root.is_dir {
+            let mut dirs = alloc::vec![(Vec::new(), alloc::vec![root.short])];
+            while let Some((path, short_path)) = dirs.pop() {
+                fs.open_dir_path(&short_path, false)
+                    .map_err(BuildError::FsError)?;
+                for entry in fs.list_current_dir().map_err(BuildError::FsError)? {
+                    let mut path = path.clone();
+                    path.push(entry.name);
+                    let mut short_path = short_path.clone();
+                    short_path.push(entry.short);
+                    if entry.is_dir {
+                        dirs.push((path, short_path));
+                    } else {
+                        let length = entry.size as u64;
+                        files.push((SourceFile { path, length }, short_path));
+                    }
+                }
+            }
+        } else {
+            let length = root.size as u64;
+            files.push((
+                SourceFile {
+                    path: Vec::new(),
+                    length,
+                },
+                alloc::vec![root.short],
+            ));
+        }
+
+        let pieces = fs
+            .hash_pieces(
+                files.iter().map(|(_, short_path)| short_path.as_slice()),
+                self.piece_length,
+                hasher,
+            )
+            .await
+            .map_err(BuildError::FsError)?;
+
+        let source = if root.is_dir {
+            Source::Dir {
+                files: files.into_iter().map(|(file, _)| file).collect(),
+            }
+        } else {
+            Source::File {
+                length: files[0].0.length,
+            }
+        };
+        self.encode(&root.name, &source, &pieces, buf)
+            .ok_or(BuildError::BufferTooSmall)
+    }
+}
+
+impl<V> FileSystem<V>
+where
+    V: VolumeMgr,
+{
+    /// Lists the files and directories in the current directory, without `.` and `..`.
+    fn list_current_dir(&mut self) -> Result<Vec<Entry>, <Self as FileSystemExt>::Error> {
+        let mut lfn_buffer_storage = [0; 256];
+        let mut lfn_buffer = LfnBuffer::new(&mut lfn_buffer_storage);
+        let mut entries = Vec::new();
+        self.get_volume_mgr().iterate_dir_lfn(
+            self.get_current_dir(),
+            &mut lfn_buffer,
+            |dir, name| {
+                if dir.attributes.is_volume()
+                    || dir.name == ShortFileName::this_dir()
+                    || dir.name == ShortFileName::parent_dir()
+                {
+                    return;
+                }
+                entries.push(Entry {
+                    short: dir.name.clone(),
+                    name: name.map_or_else(|| dir.name.to_string(), |name| name.to_string()),
+                    is_dir: dir.attributes.is_directory(),
+                    size: dir.size,
+                });
+            },
+        )?;
+        Ok(entries)
+    }
+
+    /// Hashes the files (given as paths from the root) concatenated, cut into pieces.
+    async fn hash_pieces<'p>(
+        &mut self,
+        files: impl Iterator<Item = &'p [ShortFileName]>,
+        piece_length: u32,
+        hasher: &impl Sha1Hasher,
+    ) -> Result<Vec<InfoHash>, <Self as FileSystemExt>::Error> {
+        let mut pieces = Vec::new();
+        let mut in_piece = 0;
+        let mut chunk = [0u8; 512];
+
+        for path in files {
+            let (name, dirs) = path.split_last().expect("paths are never empty");
+            self.open_dir_path(dirs, false)?;
+            self.open_file(name, Mode::ReadOnly)?;
+            loop {
+                let want = chunk.len().min((piece_length - in_piece) as usize);
+                let read = self.read_to_end(&mut chunk[..want]).await?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&chunk[..read]).await;
+                in_piece += read as u32;
+                if in_piece == piece_length {
+                    let mut digest = [0u8; 20];
+                    hasher.finalize(&mut digest).await;
+                    pieces.push(digest);
+                    in_piece = 0;
+                }
+            }
+        }
+
+        // the last piece may be shorter
+        if in_piece > 0 {
+            let mut digest = [0u8; 20];
+            hasher.finalize(&mut digest).await;
+            pieces.push(digest);
+        }
+        self.go_to_root_dir();
+        Ok(pieces)
+    }
+}
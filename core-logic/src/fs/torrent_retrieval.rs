@@ -1,7 +1,7 @@
 use alloc::string::ToString as _;
-use embedded_sdmmc::LfnBuffer;
+use embedded_sdmmc::{LfnBuffer, ShortFileName};
 
-use crate::fs::{FileSystem, FileSystemExt, VolumeMgr};
+use crate::fs::{FileSystem, FileSystemExt, VolumeMgr, file_map::to_short_name};
 
 impl<V> FileSystem<V>
 where
@@ -9,8 +9,44 @@ where
 {
     /// Get's the first torrent file in the 'torrents' directory.
     /// Make sure to put the torrent file in the 'torrents' directory as well as have the directory in the root of the filesystem.
+    /// Torrents saved by [`FileSystem::save_torrent`] only have a short name (`*.TOR`), they are found as well.
     /// Returns the length of the torrent file.
     pub async fn put_torrent_into_buf(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let file_name = self.find_in_torrents_dir("torrent", b"TOR")?;
+        self.read_into_buf(&file_name, buf).await
+    }
+
+    /// Get's the first magnet link in the 'torrents' directory, i.e. a text file ending in `magnet`
+    /// (or `.MAG` for short names) which contains the link.
+    /// Returns the length of the link.
+    pub async fn put_magnet_into_buf(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let file_name = self.find_in_torrents_dir("magnet", b"MAG")?;
+        self.read_into_buf(&file_name, buf).await
+    }
+
+    /// Saves a torrent (e.g. one fetched for a magnet link) as `torrents/<name>.tor`,
+    /// so it's found by [`FileSystem::put_torrent_into_buf`] next time.
+    /// The name is shortened to 8.3 if necessary.
+    pub async fn save_torrent(
+        &mut self,
+        name: &str,
+        torrent: &[u8],
+    ) -> Result<(), <Self as FileSystemExt>::Error> {
+        let file_name = to_short_name(&alloc::format!("{}.tor", name), 0);
+
+        self.go_to_root_dir();
+        self.open_dir("torrents")?;
+        self.open_file(&file_name, embedded_sdmmc::Mode::ReadWriteCreateOrTruncate)?;
+        self.write_to_opened_file(torrent).await?;
+        self.flush()?;
+
+        defmt_or_log::info!("Saved torrent as {}", file_name.to_string().as_str());
+        Ok(())
+    }
+
+    /// Returns the first file in the 'torrents' directory whose long name ends with `suffix`
+    /// or, if it has none, whose short name has the extension `short_ext`.
+    fn find_in_torrents_dir(&mut self, suffix: &str, short_ext: &[u8]) -> Option<ShortFileName> {
         self.go_to_root_dir();
         self.open_dir("torrents")
             .expect("'torrents' directory not found.");
@@ -20,11 +56,12 @@ where
         let mut file_name = None;
         self.get_volume_mgr()
             .iterate_dir_lfn(self.get_current_dir(), &mut lfn_buffer, |dir, name| {
-                if let Some(name) = name
-                    && name.ends_with("torrent")
-                    && file_name.is_none()
-                {
-                    defmt_or_log::trace!("found torrent: {}", name);
+                let matches = match name {
+                    Some(name) => name.ends_with(suffix),
+                    None => dir.name.extension() == short_ext,
+                };
+                if matches && file_name.is_none() {
+                    defmt_or_log::trace!("found {}: {:?}", suffix, name);
                     file_name = Some(dir.name.clone());
                 } else {
                     defmt_or_log::trace!("found file to ignore: {:?}", name);
@@ -32,24 +69,25 @@ where
             })
             .expect("Couldn't iterate dir");
 
-        if let Some(file_name) = file_name.as_ref() {
-            self.open_file(file_name, embedded_sdmmc::Mode::ReadOnly)
-                .expect("we just found the file with this name");
-
-            let file_length = self
-                .volume_mgr
-                .file_length(self.get_open_file().expect("we just opened it"))
-                .unwrap() as usize;
-            if file_length > buf.len() {
-                defmt_or_log::error!("Torrent file is too big. Max size is {}", buf.len());
-                return None;
-            }
-
-            self.read_to_end(buf).await.expect("Couldn't read file");
-            defmt_or_log::info!("Using torrent-file {}", file_name.to_string().as_str());
-            Some(file_length)
-        } else {
-            None
+        file_name
+    }
+
+    /// Reads the whole file `file_name` of the current directory into `buf`.
+    async fn read_into_buf(&mut self, file_name: &ShortFileName, buf: &mut [u8]) -> Option<usize> {
+        self.open_file(file_name, embedded_sdmmc::Mode::ReadOnly)
+            .expect("we just found the file with this name");
+
+        let file_length = self
+            .volume_mgr
+            .file_length(self.get_open_file().expect("we just opened it"))
+            .unwrap() as usize;
+        if file_length > buf.len() {
+            defmt_or_log::error!("File is too big. Max size is {}", buf.len());
+            return None;
         }
+
+        self.read_to_end(buf).await.expect("Couldn't read file");
+        defmt_or_log::info!("Using file {}", file_name.to_string().as_str());
+        Some(file_length)
     }
 }
@@ -0,0 +1,85 @@
+use embedded_sdmmc::{Mode, ShortFileName};
+
+use crate::fs::{FileSystem, FileSystemExt, VolumeMgr, file_map::FileMap};
+
+type FsResult<V> = Result<(), <FileSystem<V> as FileSystemExt>::Error>;
+
+impl<V> FileSystem<V>
+where
+    V: VolumeMgr,
+{
+    /// Creates the directory tree and all (empty) files of a torrent.
+    /// Existing files are truncated.
+    ///
+    /// Fails with `Unsupported` if a file is too large for FAT (4 GiB), before anything is created.
+    pub fn create_files(&mut self, files: &FileMap) -> FsResult<V> {
+        if files.files().iter().any(|f| f.length > u32::MAX as u64) {
+            return Err(embedded_sdmmc::Error::Unsupported);
+        }
+        for file in files.files().iter().filter(|f| !f.padding) {
+            let (name, dirs) = file.path.split_last().expect("paths are never empty");
+            self.open_dir_path(dirs, true)?;
+            self.open_file(name, Mode::ReadWriteCreateOrTruncate)?;
+        }
+        self.go_to_root_dir();
+        Ok(())
+    }
+
+    /// Writes `data` at the absolute `offset` of the torrent (i.e. all files concatenated),
+    /// split across the files it spans.
+    ///
+    /// The files have to exist already, see [`FileSystem::create_files`].
+    pub async fn write_at(&mut self, files: &FileMap, offset: u64, data: &[u8]) -> FsResult<V> {
+        for span in files.spans(offset, data.len()) {
+            let (name, dirs) = span.file.path.split_last().expect("paths are never empty");
+            self.open_dir_path(dirs, false)?;
+            self.open_file(name, Mode::ReadWriteAppend)?;
+            self.seek_open_file(span.file_offset).await?;
+            self.write_to_opened_file(&data[span.data]).await?;
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Opens the directory `path` relative to the root, optionally creating missing directories.
+    pub(super) fn open_dir_path(&mut self, path: &[ShortFileName], create: bool) -> FsResult<V> {
+        self.go_to_root_dir();
+        for dir in path {
+            if create {
+                match self
+                    .get_volume_mgr()
+                    .make_dir_in_dir(self.get_current_dir(), dir)
+                {
+                    Ok(()) | Err(embedded_sdmmc::Error::DirAlreadyExists) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            self.open_dir(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Moves to `offset` in the open file. FAT can't seek past the end of a file,
+    /// so if `offset` is behind the end the gap is filled with zeros.
+    async fn seek_open_file(&mut self, offset: u64) -> FsResult<V> {
+        let offset = u32::try_from(offset).map_err(|_| embedded_sdmmc::Error::InvalidOffset)?;
+        let file = self
+            .get_open_file()
+            .ok_or(embedded_sdmmc::Error::BadHandle)?;
+        let length = self.get_volume_mgr().file_length(file)?;
+
+        if offset <= length {
+            return self.get_volume_mgr().file_seek_from_start(file, offset);
+        }
+
+        const ZEROS: [u8; 512] = [0; 512];
+        self.get_volume_mgr().file_seek_from_end(file, 0)?;
+        let mut gap = (offset - length) as usize;
+        while gap > 0 {
+            let n = gap.min(ZEROS.len());
+            self.write_to_opened_file(&ZEROS[..n]).await?;
+            gap -= n;
+        }
+        Ok(())
+    }
+}
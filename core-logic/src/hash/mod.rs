@@ -1,8 +1,34 @@
+use core::cell::RefCell;
+
 #[allow(async_fn_in_trait)]
 pub trait Sha1Hasher {
     /// Process data.
     async fn update(&self, data: &[u8]);
 
     /// Extract the final hash.
+    /// Afterwards the hasher starts over, so it can be reused for the next piece.
     async fn finalize(&self, digest: &mut [u8; 20]);
 }
+
+/// A [`Sha1Hasher`] in software, for targets without a SHA peripheral.
+#[derive(Default)]
+pub struct SoftwareSha1 {
+    state: RefCell<sha1_smol::Sha1>,
+}
+
+impl SoftwareSha1 {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Sha1Hasher for SoftwareSha1 {
+    async fn update(&self, data: &[u8]) {
+        self.state.borrow_mut().update(data);
+    }
+
+    async fn finalize(&self, digest: &mut [u8; 20]) {
+        *digest = self.state.take().digest().bytes();
+    }
+}
@@ -8,12 +8,17 @@ pub mod fs;
 pub mod hash;
 pub mod net;
 mod peer;
+pub mod rng;
 
 pub use bittorrenter::{BitTorrenter, error::BitTorrenterError};
 pub use core::metainfo::{Info, MetaInfoFile};
-pub use hash::Sha1Hasher;
+pub use hash::{Sha1Hasher, SoftwareSha1};
 pub use net::tcp::TcpConnector;
+pub use net::udp::UdpConnector;
 pub use peer::BLOCK_SIZE;
 pub use peer::messages::error::MessageError;
+pub use rng::Rng;
 
+/// A public tracker, never used on its own: torrents without trackers fail with
+/// [`BitTorrenterError::NoTrackers`].
 pub const DEFAULT_TRACKER: &str = "http://tracker.opentrackr.org:1337/announce";
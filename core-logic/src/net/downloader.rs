@@ -1,89 +1,216 @@
-use embassy_time::Duration;
+use embassy_time::{Duration, Instant};
 use embedded_nal_async::Dns;
 
 use crate::{
-    BitTorrenter, BitTorrenterError, TcpConnector,
-    bittorrenter::states::Downloading,
-    fs::{FileSystemExt, VolumeMgr},
-    net::buffer::SocketBuffers,
-    peer::{NotHandshaken, Peer},
+    BitTorrenter, BitTorrenterError, TcpConnector, UdpConnector,
+    bittorrenter::{announce::AnnounceSchedule, peer_pool::PeerPool, states::Downloading},
+    core::{tracker::AnnounceEvent, tracker_tiers::TrackerTiers},
+    fs::VolumeMgr,
+    net::{
+        buffer::SocketBuffers,
+        udp_tracker_requester::{MAX_RETRANSMISSIONS, MAX_RETRANSMISSIONS_BEFORE_FAILOVER},
+    },
+    peer::{Peer, downloader_processer::DownloadError, piece_state::PieceState},
 };
 
 impl<NET, V, const RX: usize, const TX: usize> BitTorrenter<NET, V, Downloading, RX, TX>
 where
-    NET: TcpConnector + Dns,
+    NET: TcpConnector + UdpConnector + Dns,
     V: VolumeMgr,
 {
     #[inline]
-    pub fn get_peers(&self) -> &[core::net::SocketAddrV4] {
+    pub fn get_peers(&self) -> &[core::net::SocketAddr] {
         self.state.get_peers()
     }
 
-    pub async fn download(&mut self) -> Result<(), BitTorrenterError<NET, V>> {
-        defmt_or_log::info!("Starting download...");
+    /// The torrent's trackers, the ones that answered first in their tier.
+    pub fn get_trackers(&self) -> &TrackerTiers {
+        self.state.get_trackers()
+    }
 
-        let peer = connect_to_valid_peer(
-            &mut self.net,
-            &mut self.socket_buffers,
-            &self.state.get_peers()[0..],
-            self.state.get_piece_length(),
-            self.state.get_total_length(),
-        )
-        .await?
-        .expect("// TODO: no valid peers found");
+    /// When we announce next, and the bytes transferred so far.
+    pub fn get_announce(&self) -> &AnnounceSchedule {
+        self.state.get_announce()
+    }
 
-        defmt_or_log::info!("Connected to peer, performing handshake...");
+    /// Announces if the tracker's interval is over or there is an event to send, i.e. `completed`
+    /// after the download finished. New peers of the trackers refill the peer pool.
+    ///
+    /// Call it every now and then, the tracker forgets about us otherwise.
+    ///
+    /// # Returns
+    ///
+    /// Whether we announced.
+    pub async fn announce_if_due(
+        &mut self,
+        rx_buf: &mut [u8],
+    ) -> Result<bool, BitTorrenterError<NET, V>> {
+        self.announce_if_due_with(MAX_RETRANSMISSIONS, rx_buf).await
+    }
 
-        let mut handshake_peer = peer
-            .into_handshake_performed(self.state.get_info_hash())
+    /// Tells the trackers that we stop (`stopped`), call it before shutting down.
+    pub async fn stop(&mut self, rx_buf: &mut [u8]) -> Result<(), BitTorrenterError<NET, V>> {
+        self.announce(Some(AnnounceEvent::Stopped), MAX_RETRANSMISSIONS, rx_buf)
             .await
-            .map_err(BitTorrenterError::HandshakeFailed)?;
+    }
+
+    /// [`Self::announce_if_due`], with how often the last UDP tracker is asked again.
+    async fn announce_if_due_with(
+        &mut self,
+        max_retransmissions: u32,
+        rx_buf: &mut [u8],
+    ) -> Result<bool, BitTorrenterError<NET, V>> {
+        if !self.state.get_announce().is_due(Instant::now()) {
+            return Ok(false);
+        }
+        let event = self.state.get_announce().event();
+        self.announce(event, max_retransmissions, rx_buf).await?;
+        Ok(true)
+    }
+
+    async fn announce(
+        &mut self,
+        event: Option<AnnounceEvent>,
+        max_retransmissions: u32,
+        rx_buf: &mut [u8],
+    ) -> Result<(), BitTorrenterError<NET, V>> {
+        let info_hash = *self.state.get_info_hash();
+        let peer_id = self.peer_id;
+        // `announce_to_tiers` borrows all of `self`, so the request, the trackers and the peers
+        // must not borrow the state
+        let announce = self.state.get_announce().clone();
+        let num_want = match event {
+            Some(AnnounceEvent::Stopped) => 0,
+            _ => self.max_peers as u32,
+        };
+        let request = announce
+            .request(&info_hash, &peer_id, self.port)
+            .with_event(event)
+            .with_num_want(num_want)
+            .with_key(self.key);
+        let mut trackers = self.state.get_trackers().clone();
+        let mut peers = core::mem::replace(self.state.get_peers_mut(), PeerPool::new(0));
+
+        let response = self
+            .announce_to_tiers(
+                &mut trackers,
+                &mut peers,
+                &request,
+                max_retransmissions,
+                rx_buf,
+            )
+            .await;
+        *self.state.get_trackers_mut() = trackers;
+        *self.state.get_peers_mut() = peers;
+
+        let response = response.inspect_err(|_| {
+            self.state.get_announce_mut().failed(Instant::now());
+        })?;
+        defmt_or_log::info!("Re-announced, {} peers known", self.state.get_peers().len());
+        self.state
+            .get_announce_mut()
+            .announced(&response, Instant::now());
+        Ok(())
+    }
 
-        let name = self.state.get_name();
+    /// Downloads the torrent, piece by piece.
+    ///
+    /// The bytes downloaded are counted after every piece. When an announce is due (see
+    /// [`Self::announce_if_due`]), the peer connection is closed for it, as there is only one
+    /// connection at a time, and the download goes on with a peer afterwards. The `completed`
+    /// announce follows the last piece. A failed announce doesn't stop the download, it's tried
+    /// again later.
+    ///
+    /// # Arguments
+    ///
+    /// * `rx_buf` - Buffer for the trackers' responses
+    pub async fn download(&mut self, rx_buf: &mut [u8]) -> Result<(), BitTorrenterError<NET, V>> {
+        defmt_or_log::info!("Starting download...");
 
-        self.fs.go_to_root_dir();
         self.fs
-            .open_file(name, embedded_sdmmc::Mode::ReadWriteCreateOrAppend)
+            .create_files(self.state.get_files())
             .map_err(BitTorrenterError::FsError)?;
 
-        handshake_peer
-            .download_process_incoming_data(&mut self.fs)
-            .await
-            .map_err(BitTorrenterError::TcpError)?;
-
-        // let unchoked_peer = interested_peer
-        //     .wait_for_unchoke()
-        //     .await
-        //     .map_err(BitTorrenterError::TcpError)?;
-
-        // defmt_or_log::info!(
-        //     "Peer unchoked, starting file download for file {:?}...",
-        //     name
-        // );
-
-        // let piece_length = self.state.get_piece_length();
-        // let total_length = self.state.get_total_length();
-        // let _finished_peer = unchoked_peer
-        //     .download_file(piece_length, total_length, &mut self.fs)
-        //     .await
-        //     .map_err(BitTorrenterError::TcpError)?;
-        Ok(())
+        // where the download continues after an announce
+        let mut piece = Some(PieceState::new(
+            0,
+            self.state.get_piece_length(),
+            self.state.get_total_length(),
+        ));
+        loop {
+            let connection = connect_to_valid_peer(
+                &mut self.net,
+                &mut self.socket_buffers,
+                self.state.get_peers_mut(),
+            )
+            .await?
+            .ok_or(BitTorrenterError::NoPeers)?;
+
+            defmt_or_log::info!("Connected to peer, performing handshake...");
+
+            let peer = Peer::new(
+                connection,
+                piece.take().expect("is put back after every peer"),
+            );
+            let mut handshake_peer = peer
+                .into_handshake_performed(self.state.get_info_hash(), &self.peer_id)
+                .await
+                .map_err(BitTorrenterError::HandshakeFailed)?;
+
+            let (files, announce) = self.state.get_files_and_announce_mut();
+            let finished = loop {
+                let before = handshake_peer.downloaded();
+                let more = handshake_peer.download_piece(&mut self.fs, files).await;
+                announce.add_downloaded(handshake_peer.downloaded() - before);
+                let more = more.map_err(|e| match e {
+                    DownloadError::Connection(e) => BitTorrenterError::TcpError(e),
+                    DownloadError::Closed => BitTorrenterError::PeerDisconnected,
+                    DownloadError::WriteFailed(e) => BitTorrenterError::FsError(e),
+                })?;
+                if !more {
+                    break true;
+                }
+                if announce.is_due(Instant::now()) {
+                    break false;
+                }
+            };
+            // the tracker can only be reached without the peer's connection, so a silent UDP
+            // tracker mustn't keep us waiting for hours
+            piece = Some(handshake_peer.into_piece());
+
+            if self
+                .announce_if_due_with(MAX_RETRANSMISSIONS_BEFORE_FAILOVER, rx_buf)
+                .await
+                .is_err()
+            {
+                defmt_or_log::warn!("No tracker answered, announcing again later");
+            }
+            if finished {
+                return Ok(());
+            }
+        }
     }
 }
 
-/// tries to connect to the first peer in a list that responds within a timeout
+/// tries to connect to the first peer in the pool that responds within a timeout
+///
+/// Peers that can't be reached (or are IPv6) are removed, which makes room for the peers of the
+/// next announce.
 async fn connect_to_valid_peer<'a, NET, V, const RX: usize, const TX: usize>(
     net: &'a mut NET,
     socket_buffers: &'a mut SocketBuffers<RX, TX>,
-    peer_list: &[core::net::SocketAddrV4],
-    piece_length: u32,
-    file_size: u32,
-) -> Result<Option<Peer<'a, NET, NotHandshaken>>, BitTorrenterError<NET, V>>
+    peers: &mut PeerPool,
+) -> Result<Option<NET::Connection<'a>>, BitTorrenterError<NET, V>>
 where
-    NET: TcpConnector + Dns,
+    NET: TcpConnector + UdpConnector + Dns,
     V: VolumeMgr,
 {
-    for peer_addr in peer_list {
+    while let Some(&peer) = peers.as_slice().first() {
+        let core::net::SocketAddr::V4(peer_addr) = peer else {
+            defmt_or_log::debug!("Dropping IPv6 peer, the network stack only speaks IPv4");
+            peers.remove(&peer);
+            continue;
+        };
         defmt_or_log::info!("Connecting to peer at: {:?}", peer_addr);
 
         // SAFETY: On failure paths, the connection (and its borrows) is dropped
@@ -98,22 +225,22 @@ where
 
         let conn = embassy_time::with_timeout(
             Duration::from_secs(20),
-            net_ref.connect(*peer_addr, rx_ref, tx_ref),
+            net_ref.connect(peer_addr, rx_ref, tx_ref),
         )
         .await;
 
         match conn {
             Ok(Ok(c)) => {
                 defmt_or_log::info!("Connected to peer at: {:?}", peer_addr);
-                return Ok(Some(Peer::new(c, piece_length, file_size)));
+                return Ok(Some(c));
             }
-            Ok(Err(_)) => continue,
+            Ok(Err(_)) => defmt_or_log::warn!("Connection to peer failed"),
             Err(_) => {
                 drop(conn);
                 defmt_or_log::warn!("Connection to peer timed out");
-                continue;
             }
         }
+        peers.remove(&peer);
     }
     Ok(None)
 }
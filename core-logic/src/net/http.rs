@@ -0,0 +1,373 @@
+//! A minimal HTTP/1.1 client, enough to talk to trackers.
+//!
+//! Only `GET` with `Connection: close` is supported. The whole response has to fit into the
+//! caller's buffer, the body is delimited by `Content-Length`, `Transfer-Encoding: chunked` or the
+//! end of the connection.
+
+use ::core::ops::Range;
+use alloc::{string::String, vec::Vec};
+use embedded_io_async::{Read, Write};
+
+use crate::net::percent_decode;
+
+#[defmt_or_log::derive_format_or_debug]
+pub enum HttpError<E> {
+    /// Reading from or writing to the connection failed
+    Io(E),
+    /// The server answered with another status than `200 OK`
+    Status(u16),
+    /// The status line, a header or a chunk is malformed
+    InvalidResponse,
+    /// The response doesn't fit into the buffer
+    BufferFull,
+    /// The connection was closed before the response was complete
+    UnexpectedEof,
+}
+
+/// What the server answered, the ranges are in the caller's buffer.
+#[derive(Clone, PartialEq)]
+#[defmt_or_log::derive_format_or_debug]
+pub enum Response {
+    /// `200 OK`, with the body
+    Body(Range<usize>),
+    /// `301`, `302`, `303`, `307` or `308`, with the `Location` to ask instead
+    Redirect(Range<usize>),
+}
+
+/// How the end of the body is found.
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "log", derive(Debug))]
+enum Framing {
+    Length(usize),
+    Chunked,
+    UntilClose,
+}
+
+/// Sends a `GET` request and reads the response into `buf`.
+///
+/// # Arguments
+///
+/// * `connection` - A fresh connection to the server, which is closed by the server afterwards
+/// * `host` - The value of the `Host` header, with the port unless it is the default one
+/// * `target` - The path and the query, e.g. `/announce?info_hash=...`
+/// * `authorization` - Credentials for `Authorization: Basic`, see [`basic_credentials`]
+/// * `buf` - Buffer for the response, headers included
+///
+/// # Returns
+///
+/// Where the body is in `buf` (chunks are already joined), or where the `Location` is for a
+/// redirect, which isn't followed.
+pub async fn get<C: Read + Write>(
+    connection: &mut C,
+    host: &str,
+    target: &str,
+    authorization: Option<&str>,
+    buf: &mut [u8],
+) -> Result<Response, HttpError<C::Error>> {
+    let (authorization_header, credentials) = match authorization {
+        Some(credentials) => ("\r\nAuthorization: Basic ", credentials),
+        None => ("", ""),
+    };
+    for part in [
+        "GET ",
+        target,
+        " HTTP/1.1\r\nHost: ",
+        host,
+        authorization_header,
+        credentials,
+        "\r\nConnection: close\r\n\r\n",
+    ] {
+        connection
+            .write_all(part.as_bytes())
+            .await
+            .map_err(HttpError::Io)?;
+    }
+    connection.flush().await.map_err(HttpError::Io)?;
+
+    let mut len = 0;
+    // where the body starts and how it ends, once the headers are complete
+    let mut head = None;
+    loop {
+        if head.is_none()
+            && let Some(end) = find(&buf[..len], b"\r\n\r\n")
+        {
+            let head_fields = parse_head(&buf[..end])?;
+            match (head_fields.status, head_fields.location) {
+                (200, _) => {}
+                (301 | 302 | 303 | 307 | 308, Some(location)) => {
+                    return Ok(Response::Redirect(location));
+                }
+                (status, _) => return Err(HttpError::Status(status)),
+            }
+            head = Some((end + 4, head_fields.framing));
+        }
+
+        match head {
+            Some((start, Framing::Length(length))) => {
+                if length > buf.len() - start {
+                    return Err(HttpError::BufferFull);
+                }
+                if len - start >= length {
+                    return Ok(Response::Body(start..start + length));
+                }
+            }
+            Some((start, Framing::Chunked)) => {
+                if let Some(length) = dechunk(&mut buf[start..len])? {
+                    return Ok(Response::Body(start..start + length));
+                }
+            }
+            Some((_, Framing::UntilClose)) | None => {}
+        }
+
+        if len == buf.len() {
+            return Err(HttpError::BufferFull);
+        }
+        let read = connection
+            .read(&mut buf[len..])
+            .await
+            .map_err(HttpError::Io)?;
+        if read == 0 {
+            return match head {
+                Some((start, Framing::UntilClose)) => Ok(Response::Body(start..len)),
+                _ => Err(HttpError::UnexpectedEof),
+            };
+        }
+        len += read;
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// What we need from the status line and the headers.
+#[cfg_attr(feature = "log", derive(Debug))]
+struct Head {
+    status: u16,
+    framing: Framing,
+    /// where the value of the `Location` header is
+    location: Option<Range<usize>>,
+}
+
+/// Parses the status line and the headers (without the empty line at the end).
+fn parse_head<E>(head: &[u8]) -> Result<Head, HttpError<E>> {
+    let head = ::core::str::from_utf8(head).map_err(|_| HttpError::InvalidResponse)?;
+    let mut lines = head.split("\r\n");
+
+    // e.g. `HTTP/1.1 200 OK`, the reason phrase is optional
+    let mut status_line = lines.next().unwrap_or_default().splitn(3, ' ');
+    if !status_line
+        .next()
+        .is_some_and(|version| version.starts_with("HTTP/1."))
+    {
+        return Err(HttpError::InvalidResponse);
+    }
+    let status = status_line
+        .next()
+        .and_then(|status| status.parse().ok())
+        .ok_or(HttpError::InvalidResponse)?;
+
+    let mut framing = Framing::UntilClose;
+    let mut location = None;
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or(HttpError::InvalidResponse)?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("transfer-encoding") {
+            // chunked has to be the last encoding, and it wins over `Content-Length`
+            let last = value.rsplit(',').next().unwrap_or_default().trim();
+            if last.eq_ignore_ascii_case("chunked") {
+                framing = Framing::Chunked;
+            }
+        } else if name.eq_ignore_ascii_case("content-length") && framing != Framing::Chunked {
+            let length = value.parse().map_err(|_| HttpError::InvalidResponse)?;
+            framing = Framing::Length(length);
+        } else if name.eq_ignore_ascii_case("location") {
+            let start = value.as_ptr() as usize - head.as_ptr() as usize;
+            location = Some(start..start + value.len());
+        }
+    }
+    Ok(Head {
+        status,
+        framing,
+        location,
+    })
+}
+
+/// Joins the chunks of a chunked body in place, once the last chunk arrived.
+///
+/// Returns the length of the joined body, or `None` if more data is needed (`body` is unchanged
+/// then).
+fn dechunk<E>(body: &mut [u8]) -> Result<Option<usize>, HttpError<E>> {
+    let mut chunks = Vec::new();
+    let mut pos = 0;
+    loop {
+        let Some(line_end) = find(&body[pos..], b"\r\n") else {
+            return Ok(None);
+        };
+        // the size in hex, maybe followed by extensions
+        let line = &body[pos..pos + line_end];
+        let size = line.split(|&b| b == b';').next().unwrap_or_default();
+        let size = ::core::str::from_utf8(size)
+            .ok()
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or(HttpError::InvalidResponse)?;
+        pos += line_end + 2;
+
+        if size == 0 {
+            // the trailers are skipped, up to the empty line
+            loop {
+                let Some(line_end) = find(&body[pos..], b"\r\n") else {
+                    return Ok(None);
+                };
+                pos += line_end + 2;
+                if line_end == 0 {
+                    break;
+                }
+            }
+            break;
+        }
+
+        // a huge size from the server must not overflow
+        let Some((end, next)) = pos
+            .checked_add(size)
+            .and_then(|end| Some((end, end.checked_add(2)?)))
+            .filter(|(_, next)| *next <= body.len())
+        else {
+            return Ok(None);
+        };
+        if &body[end..next] != b"\r\n" {
+            return Err(HttpError::InvalidResponse);
+        }
+        chunks.push(pos..end);
+        pos = next;
+    }
+
+    let mut length = 0;
+    for chunk in chunks {
+        let size = chunk.len();
+        body.copy_within(chunk, length);
+        length += size;
+    }
+    Ok(Some(length))
+}
+
+/// The credentials for `Authorization: Basic` from the userinfo of a URL (`user:password`, still
+/// percent-encoded). `None` if an escape is broken.
+pub(crate) fn basic_credentials(userinfo: &str) -> Option<String> {
+    // unlike in a query, a `+` isn't a space
+    let decoded = percent_decode(&userinfo.replace('+', "%2B"))?;
+    Some(base64(decoded.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, &b)| group | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Error = HttpError<()>;
+
+    #[test]
+    fn test_parse_head() {
+        let head = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\ncontent-length: 12";
+        let parsed = parse_head::<()>(head).unwrap();
+        assert_eq!(
+            (parsed.status, parsed.framing, parsed.location),
+            (200, Framing::Length(12), None)
+        );
+
+        let head = b"HTTP/1.0 404 Not Found";
+        let parsed = parse_head::<()>(head).unwrap();
+        assert_eq!((parsed.status, parsed.framing), (404, Framing::UntilClose));
+
+        // chunked wins, no matter the order
+        let head = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, Chunked\r\nContent-Length: 5";
+        assert_eq!(parse_head::<()>(head).unwrap().framing, Framing::Chunked);
+
+        let head = b"HTTP/1.1 302 Found\r\nLocation:  /announce?a=b ";
+        let location = parse_head::<()>(head).unwrap().location.unwrap();
+        assert_eq!(&head[location], b"/announce?a=b");
+
+        for head in [
+            &b"ICY 200 OK"[..],
+            b"HTTP/1.1 abc",
+            b"HTTP/1.1 200 OK\r\nno colon",
+            b"HTTP/1.1 200 OK\r\nContent-Length: -1",
+        ] {
+            assert!(matches!(
+                parse_head::<()>(head),
+                Err(Error::InvalidResponse)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_dechunk() {
+        let mut body =
+            b"4\r\nd8:a\r\n6;ext=1\r\ni1e1:b\r\n4\r\n1:ce\r\n0\r\nTrailer: x\r\n\r\n".to_vec();
+        let length = dechunk::<()>(&mut body).unwrap().unwrap();
+        assert_eq!(&body[..length], b"d8:ai1e1:b1:ce");
+
+        // incomplete, the body is left alone
+        let incomplete = b"4\r\nd8:a\r\n6\r\ni1e".to_vec();
+        let mut body = incomplete.clone();
+        assert!(matches!(dechunk::<()>(&mut body), Ok(None)));
+        assert_eq!(body, incomplete);
+        assert!(matches!(dechunk::<()>(&mut b"0\r\n".to_vec()), Ok(None)));
+
+        assert!(matches!(
+            dechunk::<()>(&mut b"x\r\n".to_vec()),
+            Err(Error::InvalidResponse)
+        ));
+        assert!(matches!(
+            dechunk::<()>(&mut b"1\r\nab\r\n".to_vec()),
+            Err(Error::InvalidResponse)
+        ));
+
+        // sizes that would overflow the end of the chunk, they never fit
+        for size in [usize::MAX, usize::MAX - 19] {
+            let mut body = alloc::format!("{size:x}\r\nab\r\n0\r\n\r\n").into_bytes();
+            assert!(matches!(dechunk::<()>(&mut body), Ok(None)));
+        }
+    }
+
+    #[test]
+    fn test_basic_credentials() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(&[0xfb, 0xff]), "+/8=");
+
+        assert_eq!(
+            basic_credentials("Aladdin:open%20sesame").as_deref(),
+            Some("QWxhZGRpbjpvcGVuIHNlc2FtZQ==")
+        );
+        // `+` is kept, `user:a+b`
+        assert_eq!(
+            basic_credentials("user:a+b").as_deref(),
+            Some("dXNlcjphK2I=")
+        );
+        assert_eq!(basic_credentials("user:%zz"), None);
+    }
+}
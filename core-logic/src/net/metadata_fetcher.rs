@@ -0,0 +1,112 @@
+use ::core::net::SocketAddr;
+use embassy_time::Duration;
+use embedded_nal_async::Dns;
+
+use crate::{
+    BitTorrenter, BitTorrenterError, TcpConnector, UdpConnector,
+    bittorrenter::{peer_pool::PeerPool, states::RequestingTracker},
+    core::{magnet::MagnetLink, tracker::TrackerRequest, tracker_tiers::TrackerTiers},
+    fs::VolumeMgr,
+    net::udp_tracker_requester::MAX_RETRANSMISSIONS,
+    peer::metadata::fetch_metadata,
+};
+
+impl<NET, V, const RX: usize, const TX: usize> BitTorrenter<NET, V, RequestingTracker, RX, TX>
+where
+    NET: TcpConnector + UdpConnector + Dns,
+    V: VolumeMgr,
+{
+    /// Turns a magnet link into a `.torrent` by downloading the info dict from the peers
+    /// the tracker knows about.
+    ///
+    /// # Arguments
+    ///
+    /// * `magnet` - The parsed magnet link
+    /// * `rx_buf` - Buffer for the tracker's response and then the info dict, has to fit it
+    /// * `torrent_buf` - Buffer the `.torrent` is written into
+    ///
+    /// # Returns
+    ///
+    /// The length of the `.torrent` in `torrent_buf`, parse it with `MetaInfoFile::parse`.
+    pub async fn fetch_torrent(
+        &mut self,
+        magnet: &MagnetLink,
+        rx_buf: &mut [u8],
+        torrent_buf: &mut [u8],
+    ) -> Result<usize, BitTorrenterError<NET, V>> {
+        let mut trackers =
+            TrackerTiers::from_urls(&magnet.trackers).ok_or(BitTorrenterError::NoTrackers)?;
+        // the size is unknown until we have the metadata, but with `left=0` we'd count as a seed
+        // and some trackers only return leechers to seeds
+        let mut peers = PeerPool::new(self.max_peers);
+        let peer_id = self.peer_id;
+        let request = TrackerRequest::new(&magnet.info_hash, &peer_id, self.port, 1)
+            .with_num_want(self.max_peers as u32)
+            .with_key(self.key);
+        let tracker_response = self
+            .announce_to_tiers(
+                &mut trackers,
+                &mut peers,
+                &request,
+                MAX_RETRANSMISSIONS,
+                rx_buf,
+            )
+            .await?;
+
+        defmt_or_log::info!("Received tracker response: {:?}", tracker_response);
+
+        let mut last_error = None;
+        for peer_addr in peers.as_slice() {
+            let SocketAddr::V4(peer_addr) = peer_addr else {
+                defmt_or_log::debug!("Skipping IPv6 peer, the network stack only speaks IPv4");
+                continue;
+            };
+            defmt_or_log::info!("Fetching metadata from peer at: {:?}", peer_addr);
+
+            let conn = embassy_time::with_timeout(
+                Duration::from_secs(20),
+                self.net.connect(
+                    *peer_addr,
+                    &mut self.socket_buffers.rx,
+                    &mut self.socket_buffers.tx,
+                ),
+            )
+            .await;
+            let mut conn = match conn {
+                Ok(Ok(conn)) => conn,
+                Ok(Err(e)) => {
+                    defmt_or_log::warn!("Connection to peer failed: {:?}", e);
+                    continue;
+                }
+                Err(_) => {
+                    defmt_or_log::warn!("Connection to peer timed out");
+                    continue;
+                }
+            };
+
+            let fetched = embassy_time::with_timeout(
+                Duration::from_secs(60),
+                fetch_metadata::<NET>(&mut conn, &magnet.info_hash, &self.peer_id, rx_buf),
+            )
+            .await;
+            match fetched {
+                Ok(Ok(len)) => {
+                    defmt_or_log::info!("Received {} bytes of metadata", len);
+                    return magnet
+                        .to_torrent(&rx_buf[..len], torrent_buf)
+                        .map_err(BitTorrenterError::InvalidMagnet);
+                }
+                Ok(Err(e)) => {
+                    defmt_or_log::warn!("Fetching metadata failed, trying the next peer");
+                    last_error = Some(e);
+                }
+                Err(_) => defmt_or_log::warn!("Fetching metadata timed out"),
+            }
+        }
+
+        Err(last_error.map_or(
+            BitTorrenterError::NoPeers,
+            BitTorrenterError::MetadataFailed,
+        ))
+    }
+}
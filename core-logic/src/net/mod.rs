@@ -3,9 +3,16 @@ use heapless::String;
 
 pub(crate) mod buffer;
 mod downloader;
+pub mod http;
+mod metadata_fetcher;
+mod scraper;
 pub mod tcp;
+#[cfg(feature = "tls")]
+pub mod tls;
 mod tracker_requester;
-mod url;
+pub mod udp;
+pub(crate) mod udp_tracker_requester;
+pub mod url;
 
 pub(crate) fn percent_encode(bytes: &[u8]) -> String<60> {
     let mut encoded = String::<60>::new();
@@ -15,11 +22,30 @@ pub(crate) fn percent_encode(bytes: &[u8]) -> String<60> {
     encoded
 }
 
+/// Decodes `%XX` escapes and `+` (a space in query strings).
+/// Returns `None` for broken escapes or if the result isn't utf-8.
+pub(crate) fn percent_decode(s: &str) -> Option<alloc::string::String> {
+    let mut decoded = alloc::vec::Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let hi = (bytes.next()? as char).to_digit(16)?;
+                let lo = (bytes.next()? as char).to_digit(16)?;
+                decoded.push((hi << 4 | lo) as u8);
+            }
+            b'+' => decoded.push(b' '),
+            b => decoded.push(b),
+        }
+    }
+    alloc::string::String::from_utf8(decoded).ok()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         core::{InfoHash, PeerId},
-        net::percent_encode,
+        net::{percent_decode, percent_encode},
     };
 
     #[test]
@@ -36,4 +62,16 @@ mod tests {
             "%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01%01"
         );
     }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(
+            percent_decode("http%3A%2F%2Ftracker.com%3a6969/announce").as_deref(),
+            Some("http://tracker.com:6969/announce")
+        );
+        assert_eq!(percent_decode("a+b%20c").as_deref(), Some("a b c"));
+        assert_eq!(percent_decode("%4"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%ff"), None); // not utf-8
+    }
 }
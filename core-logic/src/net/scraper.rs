@@ -0,0 +1,60 @@
+use ::core::net::SocketAddrV4;
+use embedded_nal_async::Dns;
+
+use crate::{
+    BitTorrenter, BitTorrenterError, TcpConnector, UdpConnector,
+    core::{
+        InfoHash,
+        scrape::{MAX_HTTP_SCRAPE, MAX_UDP_SCRAPE, ScrapeResponse, scrape_query, scrape_url},
+    },
+    fs::VolumeMgr,
+    net::{tracker_requester::tracker_error, url::SimpleUrl},
+};
+
+impl<NET, V, STATE, const RX: usize, const TX: usize> BitTorrenter<NET, V, STATE, RX, TX>
+where
+    NET: TcpConnector + UdpConnector + Dns,
+    V: VolumeMgr,
+{
+    /// Asks a tracker how many seeds and leechers the torrents have, e.g. to decide whether a
+    /// download is worth it.
+    ///
+    /// # Arguments
+    ///
+    /// * `announce` - The tracker's announce URL, the scrape URL is derived from it
+    /// * `info_hashes` - The torrents, only the first [`MAX_HTTP_SCRAPE`] (HTTP) or
+    ///   [`MAX_UDP_SCRAPE`] (UDP) are asked about
+    /// * `rx_buf` - Buffer to store the tracker's response
+    ///
+    /// # Returns
+    ///
+    /// The numbers of the torrents the tracker knows about.
+    pub async fn scrape(
+        &mut self,
+        announce: &str,
+        info_hashes: &[InfoHash],
+        rx_buf: &mut [u8],
+    ) -> Result<ScrapeResponse, BitTorrenterError<NET, V>> {
+        let scrape = scrape_url(announce).ok_or(BitTorrenterError::ScrapeUnsupported)?;
+        let mut url =
+            SimpleUrl::parse(&scrape).map_err(|_| BitTorrenterError::ScrapeUnsupported)?;
+
+        if url.scheme() == "udp" {
+            let info_hashes = &info_hashes[..info_hashes.len().min(MAX_UDP_SCRAPE)];
+            let port = url.port().ok_or(BitTorrenterError::ScrapeUnsupported)?;
+            let ip = self.resolve(url.host_str().unwrap_or_default()).await?;
+            let stats = self
+                .make_udp_scrape_request(SocketAddrV4::new(ip, port), info_hashes, rx_buf)
+                .await?;
+            return Ok(ScrapeResponse {
+                files: info_hashes.iter().copied().zip(stats).collect(),
+            });
+        }
+
+        let info_hashes = &info_hashes[..info_hashes.len().min(MAX_HTTP_SCRAPE)];
+        url.append_query(&scrape_query(info_hashes))
+            .map_err(|_| BitTorrenterError::QueryTooLong)?;
+        let body = self.make_http_request(&url, rx_buf).await?;
+        ScrapeResponse::parse(&rx_buf[body]).map_err(tracker_error)
+    }
+}
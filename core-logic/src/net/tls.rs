@@ -0,0 +1,192 @@
+//! HTTPS trackers: the TCP connection wrapped in a TLS 1.3 client (`embedded-tls`).
+//!
+//! Only `TLS_AES_128_GCM_SHA256` is offered, which every TLS 1.3 server has to support.
+
+use alloc::boxed::Box;
+use embedded_io_async::{ErrorType, Read, Write};
+use embedded_tls::{
+    Aes128GcmSha256, CryptoProvider, TlsConfig, TlsConnection, TlsContext, TlsVerifier,
+};
+pub use embedded_tls::{CryptoRngCore, TlsError};
+
+/// The buffer for received records, a record can be 16 KiB plus some overhead.
+pub(crate) const READ_BUF_SIZE: usize = 16640;
+/// The buffer for sent records, we only send small requests.
+pub(crate) const WRITE_BUF_SIZE: usize = 2048;
+
+/// How the certificate of an HTTPS tracker is checked.
+#[derive(Clone, Copy)]
+#[defmt_or_log::derive_format_or_debug]
+pub enum Verification {
+    /// Any certificate is accepted. The connection is encrypted, but anyone in between can
+    /// pretend to be the tracker, so this is only for testing and trusted networks.
+    None,
+    /// The certificate has to be valid for the tracker's hostname and signed by this CA (DER
+    /// encoded). Intermediates aren't supported, so for most trackers this is the intermediate
+    /// CA, e.g. the one of Let's Encrypt.
+    #[cfg(feature = "tls-webpki")]
+    Ca(&'static [u8]),
+}
+
+/// What is needed to talk to HTTPS trackers, set with `BitTorrenter::with_tls`.
+pub struct TlsSettings {
+    rng: Box<dyn CryptoRngCore>,
+    verification: Verification,
+}
+
+impl TlsSettings {
+    /// # Arguments
+    ///
+    /// * `rng` - A cryptographically secure random number generator, for the key exchange
+    /// * `verification` - How the trackers' certificates are checked
+    pub fn new(rng: impl CryptoRngCore + 'static, verification: Verification) -> Self {
+        Self {
+            rng: Box::new(rng),
+            verification,
+        }
+    }
+
+    #[inline]
+    pub const fn verification(&self) -> Verification {
+        self.verification
+    }
+
+    /// The current time, certificates are only valid for a while. Without it, no certificate
+    /// is accepted by [`Verification::Ca`].
+    #[cfg(feature = "tls-webpki")]
+    pub fn with_unix_time(self, seconds: u64) -> Self {
+        ca::set_unix_time(seconds);
+        self
+    }
+}
+
+#[cfg(feature = "log")]
+impl ::core::fmt::Debug for TlsSettings {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        f.debug_struct("TlsSettings")
+            .field("verification", &self.verification)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for TlsSettings {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "TlsSettings {{ verification: {} }}", self.verification);
+    }
+}
+
+/// Opens a TLS connection to `server_name` over `connection`.
+///
+/// The buffers hold one record each, see [`READ_BUF_SIZE`] and [`WRITE_BUF_SIZE`].
+pub(crate) async fn open<'a, C: Read + Write + 'a>(
+    connection: C,
+    server_name: &str,
+    settings: &mut TlsSettings,
+    read_buf: &'a mut [u8],
+    write_buf: &'a mut [u8],
+) -> Result<TlsStream<'a, C>, TlsError> {
+    let config = TlsConfig::new().with_server_name(server_name);
+    let mut tls = TlsConnection::new(connection, read_buf, write_buf);
+    match settings.verification {
+        Verification::None => {
+            defmt_or_log::warn!("The certificate of {} isn't verified", server_name);
+            let provider = Provider {
+                rng: &mut *settings.rng,
+                verifier: None::<embedded_tls::NoVerify>,
+            };
+            tls.open(TlsContext::new(&config, provider)).await?;
+        }
+        #[cfg(feature = "tls-webpki")]
+        Verification::Ca(ca) => {
+            let config = config.with_ca(embedded_tls::Certificate::X509(ca));
+            let provider = Provider {
+                rng: &mut *settings.rng,
+                verifier: Some(ca::CertVerifier::new()),
+            };
+            tls.open(TlsContext::new(&config, provider)).await?;
+        }
+    }
+    Ok(TlsStream(tls))
+}
+
+/// An open TLS connection. The server's `close_notify` ends it like a closed TCP connection,
+/// which [`http::get`](super::http::get) relies on without a `Content-Length`.
+pub(crate) struct TlsStream<'a, C: Read + Write>(TlsConnection<'a, C, Aes128GcmSha256>);
+
+impl<C: Read + Write> ErrorType for TlsStream<'_, C> {
+    type Error = TlsError;
+}
+
+impl<C: Read + Write> Read for TlsStream<'_, C> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, TlsError> {
+        match self.0.read(buf).await {
+            Err(TlsError::ConnectionClosed) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+impl<C: Read + Write> Write for TlsStream<'_, C> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, TlsError> {
+        self.0.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), TlsError> {
+        self.0.flush().await
+    }
+}
+
+/// The settings borrowed for one handshake.
+struct Provider<'a, V> {
+    rng: &'a mut dyn CryptoRngCore,
+    verifier: Option<V>,
+}
+
+impl<V: TlsVerifier<Aes128GcmSha256>> CryptoProvider for Provider<'_, V> {
+    type CipherSuite = Aes128GcmSha256;
+    // we don't have a client certificate
+    type Signature = &'static [u8];
+
+    fn rng(&mut self) -> impl CryptoRngCore {
+        &mut *self.rng
+    }
+
+    fn verifier(&mut self) -> Result<&mut impl TlsVerifier<Aes128GcmSha256>, TlsError> {
+        // without a verifier, `embedded-tls` skips the checks
+        self.verifier.as_mut().ok_or(TlsError::Unimplemented)
+    }
+}
+
+#[cfg(feature = "tls-webpki")]
+mod ca {
+    use ::core::sync::atomic::{AtomicU32, Ordering};
+    use embassy_time::Instant;
+    use embedded_tls::{Aes128GcmSha256, TlsClock};
+
+    /// The certificate chain has to fit into this many bytes.
+    const MAX_CHAIN_SIZE: usize = 6144;
+
+    pub(super) type CertVerifier =
+        embedded_tls::webpki::CertVerifier<Aes128GcmSha256, UnixClock, MAX_CHAIN_SIZE>;
+
+    /// The unix time when `Instant` was zero, `0` if unknown. `embedded-tls` asks a type for the
+    /// time, so it has to be global.
+    static UNIX_TIME_AT_BOOT: AtomicU32 = AtomicU32::new(0);
+
+    pub(super) fn set_unix_time(seconds: u64) {
+        let boot = seconds.saturating_sub(Instant::now().as_secs());
+        UNIX_TIME_AT_BOOT.store(boot as u32, Ordering::Relaxed);
+    }
+
+    pub(super) struct UnixClock;
+
+    impl TlsClock for UnixClock {
+        fn now() -> Option<u64> {
+            match UNIX_TIME_AT_BOOT.load(Ordering::Relaxed) {
+                0 => None,
+                boot => Some(u64::from(boot) + Instant::now().as_secs()),
+            }
+        }
+    }
+}
@@ -1,20 +1,36 @@
-use ::core::fmt::Write as _;
-use ::core::net::SocketAddrV4;
-use embedded_io_async::{Read, Write};
+use ::core::net::{Ipv4Addr, SocketAddrV4};
+use ::core::ops::Range;
+use alloc::{format, string::String, vec::Vec};
+use embassy_time::Instant;
 use embedded_nal_async::Dns;
-use heapless::string::String;
 
+#[cfg(feature = "tls")]
+use crate::net::tls;
 use crate::{
-    BitTorrenter, BitTorrenterError, DEFAULT_TRACKER, MetaInfoFile, TcpConnector,
-    bittorrenter::states::{Downloading, RequestingTracker},
-    core::tracker::{TrackerRequest, TrackerResponse},
+    BitTorrenter, BitTorrenterError, MetaInfoFile, TcpConnector, UdpConnector,
+    bittorrenter::{
+        announce::AnnounceSchedule,
+        peer_pool::PeerPool,
+        states::{Downloading, RequestingTracker},
+    },
+    core::{
+        tracker::{TrackerError, TrackerRequest, TrackerResponse},
+        tracker_tiers::TrackerTiers,
+    },
     fs::VolumeMgr,
-    net::url::SimpleUrl,
+    net::{
+        http::{self, HttpError, Response},
+        udp_tracker_requester::{MAX_RETRANSMISSIONS, MAX_RETRANSMISSIONS_BEFORE_FAILOVER},
+        url::{QUERY_TOO_LONG, SimpleUrl},
+    },
 };
 
+/// How many redirects of an HTTP tracker are followed.
+const MAX_REDIRECTS: usize = 5;
+
 impl<NET, V, const RX: usize, const TX: usize> BitTorrenter<NET, V, RequestingTracker, RX, TX>
 where
-    NET: TcpConnector + Dns,
+    NET: TcpConnector + UdpConnector + Dns,
     V: VolumeMgr,
 {
     pub async fn into_downloader(
@@ -22,6 +38,10 @@ where
         metainfo: &MetaInfoFile<'_>,
         rx_buf: &mut [u8],
     ) -> Result<BitTorrenter<NET, V, Downloading, RX, TX>, BitTorrenterError<NET, V>> {
+        metainfo
+            .validate()
+            .map_err(BitTorrenterError::InvalidTorrent)?;
+
         // defmt and log handle hex formatting differently
         #[cfg(feature = "defmt")]
         defmt::trace!(
@@ -33,11 +53,27 @@ where
             "Requesting tracker with info_hash: {:x?}",
             metainfo.info_hash
         );
-        let bytes_written = self.make_tracker_request(metainfo, rx_buf).await?;
-        // Here you would typically parse the tracker's response and transition to the next state
-        // For this example, we'll just log the raw response
-        let tracker_response = TrackerResponse::parse(&rx_buf[..bytes_written])
-            .map_err(BitTorrenterError::TrackerResponseParseError)?;
+        let mut trackers =
+            TrackerTiers::from_metainfo(metainfo).ok_or(BitTorrenterError::NoTrackers)?;
+        trackers.shuffle(|| self.rng.next_u32());
+        let mut peers = PeerPool::new(self.max_peers);
+        let mut announce = AnnounceSchedule::new(metainfo.info.total_length())
+            .with_min_interval(self.min_announce_interval);
+        let peer_id = self.peer_id;
+        let request = announce
+            .request(&metainfo.info_hash, &peer_id, self.port)
+            .with_num_want(self.max_peers as u32)
+            .with_key(self.key);
+        let tracker_response = self
+            .announce_to_tiers(
+                &mut trackers,
+                &mut peers,
+                &request,
+                MAX_RETRANSMISSIONS,
+                rx_buf,
+            )
+            .await?;
+        announce.announced(&tracker_response, Instant::now());
 
         defmt_or_log::info!("Received tracker response: {:?}", tracker_response);
 
@@ -47,73 +83,227 @@ where
             socket_buffers: self.socket_buffers,
             peer_id: self.peer_id,
             port: self.port,
-            state: Downloading::new(tracker_response.peers, metainfo),
+            key: self.key,
+            rng: self.rng,
+            max_peers: self.max_peers,
+            udp_connection: self.udp_connection,
+            udp_timeout: self.udp_timeout,
+            min_announce_interval: self.min_announce_interval,
+            #[cfg(feature = "tls")]
+            tls: self.tls,
+            state: Downloading::new(peers, metainfo, trackers, announce),
         })
     }
+}
+
+impl<NET, V, STATE, const RX: usize, const TX: usize> BitTorrenter<NET, V, STATE, RX, TX>
+where
+    NET: TcpConnector + UdpConnector + Dns,
+    V: VolumeMgr,
+{
+    /// Announce to the trackers tier by tier (BEP 12) and add the peers they know about to
+    /// `peers`.
+    ///
+    /// Within a tier, the trackers are tried in order until one answers, which is then moved
+    /// to the front of its tier. The next tier is only asked while there is room for more peers,
+    /// so one dead tracker doesn't stop the download. A UDP tracker gets only one retransmission
+    /// while there are other trackers to fall back on, BEP 15's full backoff takes hours. The
+    /// last one gets `max_retransmissions`, unless another tracker already answered.
+    ///
+    /// # Returns
+    ///
+    /// The response of the first tracker that answered, or the error of the last tracker if none
+    /// answered.
+    pub(crate) async fn announce_to_tiers(
+        &mut self,
+        trackers: &mut TrackerTiers,
+        peers: &mut PeerPool,
+        request: &TrackerRequest<'_>,
+        max_retransmissions: u32,
+        rx_buf: &mut [u8],
+    ) -> Result<TrackerResponse, BitTorrenterError<NET, V>> {
+        let mut first_response: Option<TrackerResponse> = None;
+        let mut last_error = None;
+
+        let tiers: Vec<Vec<String>> = trackers.tiers().map(<[String]>::to_vec).collect();
+        for (tier, urls) in tiers.iter().enumerate() {
+            for (index, url) in urls.iter().enumerate() {
+                let last = tier + 1 == tiers.len() && index + 1 == urls.len();
+                let max_retransmissions = if last && first_response.is_none() {
+                    max_retransmissions
+                } else {
+                    MAX_RETRANSMISSIONS_BEFORE_FAILOVER
+                };
+                match self
+                    .make_tracker_request(url, request, max_retransmissions, rx_buf)
+                    .await
+                {
+                    Ok(response) => {
+                        trackers.promote(tier, index);
+                        let added = peers.extend(response.peers.iter().copied());
+                        defmt_or_log::debug!("Tracker {} gave {} new peers", url.as_str(), added);
+                        first_response.get_or_insert(response);
+                        break;
+                    }
+                    Err(e) => {
+                        defmt_or_log::warn!("Tracker {} failed, trying the next one", url.as_str());
+                        last_error = Some(e);
+                    }
+                }
+            }
+
+            if peers.is_full() {
+                break;
+            }
+        }
+
+        first_response.ok_or_else(|| last_error.expect("there is at least one tracker"))
+    }
 
     /// Send a request to the BitTorrent tracker and receive the response.
     ///
-    /// This performs an HTTP GET request to the tracker's announce URL with
-    /// the required BitTorrent parameters (info_hash, peer_id, port, etc.).
+    /// For `udp://` trackers this is the UDP tracker protocol (BEP 15), otherwise an HTTP GET
+    /// request to the tracker's announce URL with the required BitTorrent parameters
+    /// (info_hash, peer_id, port, etc.).
     ///
     /// # Arguments
     ///
-    /// * `metadata` - The parsed .torrent file containing the announce URL
-    /// * `rx_buf` - Buffer to store the tracker's bencoded response
+    /// * `announce` - The tracker's announce URL, UDP trackers need a port
+    /// * `request` - The announce to send
+    /// * `max_retransmissions` - How often an unanswered UDP request is sent again
+    /// * `rx_buf` - Buffer to store the tracker's response
     ///
     /// # Returns
     ///
-    /// The number of bytes written to `rx_buf` (the response body only,
-    /// HTTP headers are stripped).
-    async fn make_tracker_request(
+    /// The parsed response of the tracker.
+    pub(crate) async fn make_tracker_request(
         &mut self,
-        metadata: &MetaInfoFile<'_>,
+        announce: &str,
+        request: &TrackerRequest<'_>,
+        max_retransmissions: u32,
         rx_buf: &mut [u8],
-    ) -> Result<usize, BitTorrenterError<NET, V>> {
-        let mut url = SimpleUrl::parse(metadata.announce)
-            .unwrap_or_else(|_| SimpleUrl::parse(DEFAULT_TRACKER).expect("Valid hardcoded url"));
-        let tracker_request = TrackerRequest::new(
-            &metadata.info_hash,
-            &self.peer_id,
-            self.port,
-            metadata.info.length,
-        );
-        let query = tracker_request.to_url_encoded();
-        url.set_query(Some(&query));
-        let bytes_written = self.make_http_request(&url, rx_buf).await?;
-
-        // Move the body of the HTTP response to the beginning of the buffer
-        let body_start = http_header_end_pos(&rx_buf[..bytes_written]);
-        rx_buf.copy_within(body_start..bytes_written, 0);
-        Ok(bytes_written - body_start)
+    ) -> Result<TrackerResponse, BitTorrenterError<NET, V>> {
+        let mut url = SimpleUrl::parse(announce).map_err(|e| match e {
+            QUERY_TOO_LONG => BitTorrenterError::QueryTooLong,
+            e => BitTorrenterError::InvalidUrl(e),
+        })?;
+
+        if url.scheme() == "udp" {
+            let port = url
+                .port()
+                .ok_or(BitTorrenterError::InvalidUrl("UDP trackers need a port"))?;
+            let ip = self.resolve(url.host_str().unwrap_or_default()).await?;
+            return self
+                .make_udp_tracker_request(
+                    SocketAddrV4::new(ip, port),
+                    request,
+                    max_retransmissions,
+                    rx_buf,
+                )
+                .await;
+        }
+
+        // keep what is already in the query, e.g. the passkey of a private tracker
+        url.append_query(&request.to_url_encoded())
+            .map_err(|_| BitTorrenterError::QueryTooLong)?;
+        let body = self.make_http_request(&url, rx_buf).await?;
+        let response = TrackerResponse::parse(&rx_buf[body]).map_err(tracker_error)?;
+        if let Some(warning) = &response.warning_message {
+            defmt_or_log::warn!("Tracker warning: {}", warning.as_str());
+        }
+        Ok(response)
+    }
+
+    /// Resolve a hostname to its IPv4 address using DNS (UDP-based, no buffers needed).
+    pub(crate) async fn resolve(&self, host: &str) -> Result<Ipv4Addr, BitTorrenterError<NET, V>> {
+        // an IPv6 address in a URL, e.g. `[::1]`
+        if host.starts_with('[') {
+            return Err(BitTorrenterError::Ipv6Unsupported);
+        }
+        let ip = self
+            .net
+            .get_host_by_name(host, embedded_nal_async::AddrType::IPv4)
+            .await
+            .map_err(BitTorrenterError::DnsError)?;
+
+        match ip {
+            core::net::IpAddr::V4(ipv4) => Ok(ipv4),
+            core::net::IpAddr::V6(_) => Err(BitTorrenterError::Ipv6Unsupported),
+        }
     }
 
-    /// Perform an HTTP GET request and read the response.
+    /// Perform an HTTP GET request and read the response, following up to [`MAX_REDIRECTS`]
+    /// redirects.
     ///
     /// Uses the internal socket buffers owned by `BitTorrenter` for the TCP
     /// connection. The response (headers + body) is written to `rx_buf`.
-    async fn make_http_request(
+    ///
+    /// # Returns
+    ///
+    /// Where the body is in `rx_buf`.
+    pub(crate) async fn make_http_request(
         &mut self,
         url: &SimpleUrl<'_>,
         rx_buf: &mut [u8],
-    ) -> Result<usize, BitTorrenterError<NET, V>> {
+    ) -> Result<Range<usize>, BitTorrenterError<NET, V>> {
+        let mut location: Option<String> = None;
+        let mut https = false;
+        for _ in 0..=MAX_REDIRECTS {
+            let current = match &location {
+                Some(location) => SimpleUrl::parse(location)
+                    .ok()
+                    // only to HTTP(S), and a redirect never leaves HTTPS
+                    .filter(|next| match next.scheme() {
+                        "https" => true,
+                        "http" => !https,
+                        _ => false,
+                    })
+                    .ok_or(BitTorrenterError::HttpFailed(HttpError::InvalidResponse))?,
+                None => url.clone(),
+            };
+            https = current.scheme() == "https";
+
+            match self.http_get(&current, rx_buf).await? {
+                Response::Body(body) => return Ok(body),
+                Response::Redirect(target) => {
+                    let target = ::core::str::from_utf8(&rx_buf[target])
+                        .map_err(|_| BitTorrenterError::HttpFailed(HttpError::InvalidResponse))?;
+                    defmt_or_log::debug!("Tracker redirected to {}", target);
+                    location = Some(current.join(target));
+                }
+            }
+        }
+
+        Err(BitTorrenterError::TooManyRedirects)
+    }
+
+    /// A single HTTP(S) GET request, the host is resolved every time as a redirect may lead to
+    /// another one.
+    async fn http_get(
+        &mut self,
+        url: &SimpleUrl<'_>,
+        rx_buf: &mut [u8],
+    ) -> Result<Response, BitTorrenterError<NET, V>> {
         let host = url.host_str().unwrap_or_default();
         let port = url.port().unwrap_or(80);
-        let path = url.path();
 
-        // Resolve hostname to IP address using DNS (UDP-based, no buffers needed)
-        let ip = self
-            .net
-            .get_host_by_name(host, embedded_nal_async::AddrType::IPv4)
-            .await
-            .map_err(BitTorrenterError::DnsError)?;
+        match url.scheme() {
+            "http" => {}
+            #[cfg(feature = "tls")]
+            "https" if self.tls.is_none() => return Err(BitTorrenterError::TlsNotConfigured),
+            #[cfg(feature = "tls")]
+            "https" => {}
+            _ => return Err(BitTorrenterError::UnsupportedScheme),
+        }
+        let authorization = url
+            .userinfo()
+            .map(|userinfo| {
+                http::basic_credentials(userinfo)
+                    .ok_or(BitTorrenterError::InvalidUrl("Invalid userinfo"))
+            })
+            .transpose()?;
 
-        let ip = match ip {
-            core::net::IpAddr::V4(ipv4) => ipv4,
-            core::net::IpAddr::V6(_) => {
-                unreachable!("IPv6 not supported in this application, we only use IPv4 trackers")
-            }
-        };
+        let ip = self.resolve(host).await?;
 
         // Connect to server using our owned socket buffers
         let mut tcp = self
@@ -126,36 +316,53 @@ where
             .await
             .map_err(BitTorrenterError::TcpError)?;
 
-        // Construct HTTP GET request
-        let mut request = String::<512>::new();
-        write!(
-            request,
-            "GET {}?{} HTTP/1.1\r\n",
-            path,
-            url.query()
-                .expect("We set them when url-encoding the tracker-request")
-        )
-        .unwrap();
-        write!(request, "Host: {}\r\n", host).unwrap();
-        write!(request, "Connection: close\r\n").unwrap();
-        write!(request, "\r\n").unwrap();
+        let host_header = match (url.scheme(), url.port()) {
+            ("http", Some(80)) | ("https", Some(443)) | (_, None) => String::from(host),
+            (_, Some(port)) => format!("{host}:{port}"),
+        };
+        let target = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => String::from(url.path()),
+        };
 
-        // Send request
-        tcp.write_all(request.as_bytes())
+        #[cfg(feature = "tls")]
+        if url.scheme() == "https" {
+            let settings = self.tls.as_mut().expect("checked before connecting");
+            let mut read_buf = alloc::vec![0; tls::READ_BUF_SIZE];
+            let mut write_buf = alloc::vec![0; tls::WRITE_BUF_SIZE];
+            let mut tls = tls::open(tcp, host, settings, &mut read_buf, &mut write_buf)
+                .await
+                .map_err(|e| BitTorrenterError::HttpsFailed(HttpError::Io(e)))?;
+            return http::get(
+                &mut tls,
+                &host_header,
+                &target,
+                authorization.as_deref(),
+                rx_buf,
+            )
             .await
-            .map_err(BitTorrenterError::TcpError)?;
-        tcp.flush().await.map_err(BitTorrenterError::TcpError)?;
+            .map_err(BitTorrenterError::HttpsFailed);
+        }
 
-        // Read response
-        tcp.read(rx_buf).await.map_err(BitTorrenterError::TcpError)
+        http::get(
+            &mut tcp,
+            &host_header,
+            &target,
+            authorization.as_deref(),
+            rx_buf,
+        )
+        .await
+        .map_err(BitTorrenterError::HttpFailed)
     }
 }
 
-fn http_header_end_pos(response: &[u8]) -> usize {
-    // Find the end of the HTTP header (indicated by \r\n\r\n)
-    if let Some(pos) = response.windows(4).position(|window| window == b"\r\n\r\n") {
-        pos + 4
-    } else {
-        0 // If no header found, return the whole response
+pub(super) fn tracker_error<NET, V>(e: TrackerError) -> BitTorrenterError<NET, V>
+where
+    NET: TcpConnector + UdpConnector + Dns,
+    V: VolumeMgr,
+{
+    match e {
+        TrackerError::Parse(e) => BitTorrenterError::TrackerResponseParseError(e),
+        TrackerError::Failure(reason) => BitTorrenterError::TrackerFailure(reason),
     }
 }
@@ -0,0 +1,51 @@
+use ::core::net::SocketAddrV4;
+
+use embedded_nal_async::ConnectedUdp;
+
+/// A trait for opening UDP sockets where the **caller provides buffers**, the UDP counterpart
+/// of [`TcpConnector`](crate::TcpConnector).
+///
+/// It's used to talk to UDP trackers (BEP 15), so a socket only ever exchanges datagrams with
+/// one remote.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut rx = [0u8; 1024];
+/// let mut tx = [0u8; 256];
+/// let mut socket = connector.connect_udp(addr, &mut rx, &mut tx).await?;
+/// socket.send(&request).await?;
+/// let len = socket.receive_into(&mut response).await?;
+/// ```
+///
+/// # Note on `&mut self`
+///
+/// Unlike TCP sockets, smoltcp's `UdpSocket` also needs storage for the metadata of the queued
+/// datagrams. Taking `&mut self` lets an implementation lend its own metadata storage to the
+/// socket, so callers only have to provide the payload buffers. The method isn't called `connect`
+/// so it doesn't clash with [`TcpConnector::connect`](crate::TcpConnector::connect) on types
+/// implementing both.
+#[allow(async_fn_in_trait)]
+pub trait UdpConnector {
+    /// The error type returned when opening a socket or sending/receiving fails.
+    type Error: defmt_or_log::FormatOrDebug;
+
+    /// The connected UDP socket type.
+    type Socket<'a>: ConnectedUdp<Error = Self::Error>
+    where
+        Self: 'a;
+
+    /// Open a UDP socket on an ephemeral port that sends to and receives from `remote`.
+    ///
+    /// # Arguments
+    ///
+    /// * `remote` - The socket address (IP + port) to exchange datagrams with
+    /// * `rx_buffer` - Buffer for incoming datagrams
+    /// * `tx_buffer` - Buffer for outgoing datagrams
+    async fn connect_udp<'a>(
+        &'a mut self,
+        remote: SocketAddrV4,
+        rx_buffer: &'a mut [u8],
+        tx_buffer: &'a mut [u8],
+    ) -> Result<Self::Socket<'a>, Self::Error>;
+}
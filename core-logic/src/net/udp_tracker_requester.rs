@@ -0,0 +1,243 @@
+use ::core::net::SocketAddrV4;
+use alloc::{string::ToString as _, vec::Vec};
+use embassy_time::{Duration, Instant};
+use embedded_nal_async::{ConnectedUdp, Dns};
+
+use crate::{
+    BitTorrenter, BitTorrenterError, TcpConnector, UdpConnector,
+    core::{
+        InfoHash,
+        scrape::ScrapeStats,
+        tracker::{TrackerRequest, TrackerResponse},
+        udp_tracker::{Action, UdpResponse, connect_request, parse_response, scrape_request},
+    },
+    fs::VolumeMgr,
+};
+
+/// How long the tracker accepts a connection id after handing it out.
+const CONNECTION_ID_LIFETIME: Duration = Duration::from_secs(60);
+/// How long to wait for the first response, BEP 15 doubles it with every retransmission.
+pub(crate) const DEFAULT_UDP_TIMEOUT: Duration = Duration::from_secs(15);
+/// BEP 15 stops increasing the timeout after the 8th retransmission (15 * 2^8 seconds),
+/// we give up then.
+pub(crate) const MAX_RETRANSMISSIONS: u32 = 8;
+/// With other trackers left to try, a dead one is given up after 15 + 30 seconds instead of
+/// hours.
+pub(crate) const MAX_RETRANSMISSIONS_BEFORE_FAILOVER: u32 = 1;
+
+/// A connection id and the tracker that handed it out.
+#[derive(Clone, Copy)]
+#[defmt_or_log::derive_format_or_debug]
+pub(crate) struct UdpConnectionId {
+    #[cfg_attr(feature = "defmt", defmt(Debug2Format))]
+    tracker: SocketAddrV4,
+    id: u64,
+    received: Instant,
+}
+
+impl<NET, V, STATE, const RX: usize, const TX: usize> BitTorrenter<NET, V, STATE, RX, TX>
+where
+    NET: TcpConnector + UdpConnector + Dns,
+    V: VolumeMgr,
+{
+    /// Announce to a UDP tracker (BEP 15).
+    ///
+    /// # Arguments
+    ///
+    /// * `tracker` - The resolved address of the tracker
+    /// * `request` - The announce to send
+    /// * `max_retransmissions` - How often an unanswered request is sent again
+    /// * `rx_buf` - Buffer for the datagrams from the tracker
+    pub(crate) async fn make_udp_tracker_request(
+        &mut self,
+        tracker: SocketAddrV4,
+        request: &TrackerRequest<'_>,
+        max_retransmissions: u32,
+        rx_buf: &mut [u8],
+    ) -> Result<TrackerResponse, BitTorrenterError<NET, V>> {
+        let response = self
+            .make_udp_request(
+                tracker,
+                Action::Announce,
+                |connection_id, transaction_id| {
+                    request.to_udp_announce(connection_id, transaction_id)
+                },
+                max_retransmissions,
+                rx_buf,
+            )
+            .await?;
+        match response {
+            UdpResponse::Announce(response) => Ok(*response),
+            _ => unreachable!("only announce responses are accepted"),
+        }
+    }
+
+    /// Scrape a UDP tracker (BEP 15), the stats are in the order of `info_hashes`.
+    pub(crate) async fn make_udp_scrape_request(
+        &mut self,
+        tracker: SocketAddrV4,
+        info_hashes: &[InfoHash],
+        rx_buf: &mut [u8],
+    ) -> Result<Vec<ScrapeStats>, BitTorrenterError<NET, V>> {
+        let response = self
+            .make_udp_request(
+                tracker,
+                Action::Scrape,
+                |connection_id, transaction_id| {
+                    scrape_request(connection_id, transaction_id, info_hashes)
+                },
+                MAX_RETRANSMISSIONS,
+                rx_buf,
+            )
+            .await?;
+        match response {
+            UdpResponse::Scrape(stats) => Ok(stats),
+            _ => unreachable!("only scrape responses are accepted"),
+        }
+    }
+
+    /// Sends the request that `request` builds from a connection id and a transaction id, and
+    /// waits for the response of type `action`.
+    ///
+    /// A connection id is requested first, unless the last one from this tracker is still valid.
+    /// Unanswered requests are sent again after 15 * 2^n seconds (see
+    /// `BitTorrenter::with_udp_timeout`), where n counts the timeouts so far, up to
+    /// `max_retransmissions` times. If the connection id expires meanwhile, a new one is
+    /// requested.
+    ///
+    /// # Returns
+    ///
+    /// The response, which is never an [`UdpResponse::Error`], that's a
+    /// [`BitTorrenterError::TrackerFailure`].
+    async fn make_udp_request<R: AsRef<[u8]>>(
+        &mut self,
+        tracker: SocketAddrV4,
+        action: Action,
+        request: impl Fn(u64, u32) -> R,
+        max_retransmissions: u32,
+        rx_buf: &mut [u8],
+    ) -> Result<UdpResponse<'static>, BitTorrenterError<NET, V>> {
+        let mut socket = self
+            .net
+            .connect_udp(
+                tracker,
+                &mut self.socket_buffers.rx,
+                &mut self.socket_buffers.tx,
+            )
+            .await
+            .map_err(BitTorrenterError::UdpError)?;
+
+        // transaction ids should be hard to guess for anyone spoofing the tracker
+        let mut timeouts = 0;
+        while timeouts <= max_retransmissions {
+            let timeout = self.udp_timeout * (1 << timeouts);
+
+            let cached = self.udp_connection.filter(|connection| {
+                connection.tracker == tracker
+                    && connection.received.elapsed() < CONNECTION_ID_LIFETIME
+            });
+            let connection_id = match cached {
+                Some(connection) => connection.id,
+                None => {
+                    let transaction_id = self.rng.next_u32();
+                    let response = exchange(
+                        &mut socket,
+                        &connect_request(transaction_id),
+                        transaction_id,
+                        Action::Connect,
+                        timeout,
+                        rx_buf,
+                    )
+                    .await
+                    .map_err(BitTorrenterError::UdpError)?;
+                    match response {
+                        Some(UdpResponse::Connect { connection_id }) => {
+                            self.udp_connection = Some(UdpConnectionId {
+                                tracker,
+                                id: connection_id,
+                                received: Instant::now(),
+                            });
+                            connection_id
+                        }
+                        Some(UdpResponse::Error(message)) => {
+                            return Err(BitTorrenterError::TrackerFailure(message.to_string()));
+                        }
+                        // timed out
+                        _ => {
+                            defmt_or_log::debug!("UDP tracker didn't answer the connect request");
+                            timeouts += 1;
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            let transaction_id = self.rng.next_u32();
+            let response = exchange(
+                &mut socket,
+                request(connection_id, transaction_id).as_ref(),
+                transaction_id,
+                action,
+                timeout,
+                rx_buf,
+            )
+            .await
+            .map_err(BitTorrenterError::UdpError)?;
+            match response {
+                Some(UdpResponse::Error(message)) => {
+                    return Err(BitTorrenterError::TrackerFailure(message.to_string()));
+                }
+                Some(UdpResponse::Connect { connection_id }) => {
+                    return Ok(UdpResponse::Connect { connection_id });
+                }
+                Some(UdpResponse::Announce(response)) => {
+                    return Ok(UdpResponse::Announce(response));
+                }
+                Some(UdpResponse::Scrape(stats)) => return Ok(UdpResponse::Scrape(stats)),
+                // timed out
+                None => {
+                    defmt_or_log::debug!("UDP tracker didn't answer the request");
+                    timeouts += 1;
+                }
+            }
+        }
+
+        Err(BitTorrenterError::TrackerTimeout)
+    }
+}
+
+/// Sends `request` and waits up to `timeout` for the tracker's response of type `action` (or an
+/// error) with `transaction_id`. Other datagrams are ignored.
+///
+/// Returns `None` if no response arrived in time.
+async fn exchange<'b, S: ConnectedUdp>(
+    socket: &mut S,
+    request: &[u8],
+    transaction_id: u32,
+    action: Action,
+    timeout: Duration,
+    buf: &'b mut [u8],
+) -> Result<Option<UdpResponse<'b>>, S::Error> {
+    socket.send(request).await?;
+
+    let received: Result<Result<usize, S::Error>, _> = embassy_time::with_timeout(timeout, async {
+        loop {
+            // longer datagrams are truncated
+            let len = socket.receive_into(buf).await?.min(buf.len());
+            match parse_response(&buf[..len], transaction_id) {
+                Some(response)
+                    if response.action() == action || response.action() == Action::Error =>
+                {
+                    return Ok(len);
+                }
+                _ => defmt_or_log::debug!("Ignoring a datagram that doesn't answer the request"),
+            }
+        }
+    })
+    .await;
+
+    match received {
+        Ok(len) => Ok(parse_response(&buf[..len?], transaction_id)),
+        Err(_) => Ok(None),
+    }
+}
@@ -1,14 +1,25 @@
 //! A simple URL parser for embedded systems without atomic operations (`url` uses `fetch_add`)
+use ::core::fmt::Write as _;
 use heapless::String;
 
+/// How long the query can get, together with what we append to it.
+const MAX_QUERY_LEN: usize = 512;
+/// The error if the query gets longer than [`MAX_QUERY_LEN`].
+pub(crate) const QUERY_TOO_LONG: &str = "Query too long";
+
+/// An absolute URL such as `http://user@[::1]:6969/announce?passkey=abc#top`.
+///
+/// The fragment is dropped, it is never sent to the server.
 #[derive(Clone)]
 #[defmt_or_log::derive_format_or_debug]
 pub struct SimpleUrl<'a> {
     scheme: &'a str,
+    userinfo: Option<&'a str>,
+    /// IPv6 addresses keep their brackets
     host: &'a str,
     port: Option<u16>,
     path: &'a str,
-    query: Option<String<512>>,
+    query: Option<String<MAX_QUERY_LEN>>,
 }
 
 impl<'a> SimpleUrl<'a> {
@@ -16,55 +27,77 @@ impl<'a> SimpleUrl<'a> {
     pub fn parse(url: &'a str) -> Result<Self, &'static str> {
         // Parse scheme (e.g., "http://")
         let (scheme, rest) = url.split_once("://").ok_or("Invalid URL: missing scheme")?;
-        if scheme == "https" {
-            return Err("HTTPS is not supported in this simple URL parser");
-        }
+        let rest = rest.split_once('#').map_or(rest, |(rest, _fragment)| rest);
 
-        // Parse host and optional port
-        let (host_port, path_query) = if let Some(pos) = rest.find('/') {
-            (&rest[..pos], &rest[pos..])
-        } else if let Some(pos) = rest.find('?') {
-            // No path, but query exists: http://example.com?query
-            (&rest[..pos], &rest[pos..])
-        } else {
-            // No path or query
-            (rest, "")
+        // The authority ends with the path or the query: http://example.com?query
+        let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+        let (authority, path_query) = rest.split_at(authority_end);
+
+        // the password may contain a `@` as well
+        let (userinfo, host_port) = match authority.rsplit_once('@') {
+            Some((userinfo, host_port)) => (Some(userinfo), host_port),
+            None => (None, authority),
         };
 
-        let (host, port) = if let Some((h, p)) = host_port.split_once(':') {
-            let port_num = p.parse::<u16>().map_err(|_| "Invalid port number")?;
-            (h, Some(port_num))
+        let (host, port) = if host_port.starts_with('[') {
+            // e.g. [::1]:6969
+            let host_end = host_port.find(']').ok_or("Invalid IPv6 host")? + 1;
+            let (host, port) = host_port.split_at(host_end);
+            host[1..host_end - 1]
+                .parse::<::core::net::Ipv6Addr>()
+                .map_err(|_| "Invalid IPv6 host")?;
+            match port {
+                "" => (host, None),
+                port => (
+                    host,
+                    Some(port.strip_prefix(':').ok_or("Invalid IPv6 host")?),
+                ),
+            }
         } else {
-            (host_port, None)
+            match host_port.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (host_port, None),
+            }
         };
+        if host.is_empty() {
+            return Err("Invalid URL: missing host");
+        }
+        let port = port
+            .map(|p| p.parse::<u16>().map_err(|_| "Invalid port number"))
+            .transpose()?;
 
         // Separate path from query
-        let (path, query_str) = if path_query.is_empty() {
-            ("/", None)
-        } else if let Some(pos) = path_query.find('?') {
-            let p = &path_query[..pos];
-            let path = if p.is_empty() { "/" } else { p };
-            (path, Some(&path_query[pos + 1..]))
-        } else {
-            (path_query, None)
+        let (path, query_str) = match path_query.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (path_query, None),
         };
+        let path = if path.is_empty() { "/" } else { path };
 
-        let query = query_str.map(|q| {
-            let mut s = String::<512>::new();
-            s.push_str(q).ok();
-            s
-        });
-
-        Ok(SimpleUrl {
+        let mut url = SimpleUrl {
             scheme,
+            userinfo,
             host,
             port,
             path,
-            query,
-        })
+            query: None,
+        };
+        url.set_query(query_str)?;
+        Ok(url)
+    }
+
+    /// Get the scheme, e.g. `http` or `udp`
+    #[inline]
+    pub const fn scheme(&self) -> &str {
+        self.scheme
+    }
+
+    /// Get the user and password (`user:password`) as they are in the URL, still percent-encoded
+    #[inline]
+    pub const fn userinfo(&self) -> Option<&str> {
+        self.userinfo
     }
 
-    /// Get the host string
+    /// Get the host string, IPv6 addresses are in brackets (e.g. `[::1]`)
     #[inline]
     pub const fn host_str(&self) -> Option<&str> {
         Some(self.host)
@@ -92,14 +125,68 @@ impl<'a> SimpleUrl<'a> {
         self.query.as_ref().map(|s| s.as_str())
     }
 
-    /// Set the query string
+    /// Set the query string, fails if it's longer than 512 bytes
     #[inline]
-    pub fn set_query(&mut self, query: Option<&str>) {
-        self.query = query.map(|q| {
-            let mut s = String::<512>::new();
-            s.push_str(q).ok();
-            s
+    pub fn set_query(&mut self, query: Option<&str>) -> Result<(), &'static str> {
+        self.query = query
+            .map(|q| String::try_from(q).map_err(|_| QUERY_TOO_LONG))
+            .transpose()?;
+        Ok(())
+    }
+
+    /// Add `params` (already encoded) to the query, keeping the parameters that are already
+    /// there (e.g. a private tracker's passkey). Fails if the query gets longer than 512 bytes,
+    /// the query is unchanged then.
+    pub fn append_query(&mut self, params: &str) -> Result<(), &'static str> {
+        let Some(query) = &mut self.query else {
+            return self.set_query(Some(params));
+        };
+        let len = query.len();
+        let appended = (query.is_empty() || query.ends_with('&') || query.push('&').is_ok())
+            && query.push_str(params).is_ok();
+        if !appended {
+            query.truncate(len);
+            return Err(QUERY_TOO_LONG);
+        }
+        Ok(())
+    }
+
+    /// Resolve `reference` (e.g. the `Location` of a redirect) against this URL.
+    /// Returns an absolute URL, parse it again to use it.
+    pub fn join(&self, reference: &str) -> alloc::string::String {
+        let is_absolute = reference.split_once("://").is_some_and(|(scheme, _)| {
+            !scheme.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+')
         });
+        if is_absolute {
+            return reference.into();
+        }
+        if reference.starts_with("//") {
+            // another host, same scheme
+            return alloc::format!("{}:{}", self.scheme, reference);
+        }
+
+        let mut joined = alloc::format!("{}://{}", self.scheme, self.host);
+        if let Some(port) = self.port {
+            write!(joined, ":{}", port).unwrap();
+        }
+        if reference.starts_with('/') {
+            joined.push_str(reference);
+        } else if reference.starts_with('?') {
+            joined.push_str(self.path);
+            joined.push_str(reference);
+        } else {
+            // relative to the "directory" of the path
+            let dir_end = self.path.rfind('/').map_or(0, |i| i + 1);
+            if dir_end == 0 {
+                joined.push('/');
+            }
+            joined.push_str(&self.path[..dir_end]);
+            joined.push_str(reference);
+        }
+        joined
     }
 }
 
@@ -152,8 +239,20 @@ mod tests {
     }
 
     #[test]
-    fn test_simple_url_https_unsupported() {
-        assert!(SimpleUrl::parse("https://secure.example.com").is_err());
+    fn test_simple_url_udp() {
+        let url = SimpleUrl::parse("udp://tracker.opentrackr.org:1337/announce").unwrap();
+        assert_eq!(url.scheme(), "udp");
+        assert_eq!(url.host_str(), Some("tracker.opentrackr.org"));
+        assert_eq!(url.port(), Some(1337));
+        // there is no default port for UDP trackers
+        assert_eq!(SimpleUrl::parse("udp://tracker.com").unwrap().port(), None);
+    }
+
+    #[test]
+    fn test_simple_url_https() {
+        let url = SimpleUrl::parse("https://secure.example.com/announce").unwrap();
+        assert_eq!(url.scheme(), "https");
+        assert_eq!(url.port(), Some(443));
     }
 
     #[test]
@@ -161,11 +260,90 @@ mod tests {
         let mut url = SimpleUrl::parse("http://example.com/path").unwrap();
         assert_eq!(url.query(), None);
 
-        url.set_query(Some("new=query&param=value"));
+        url.set_query(Some("new=query&param=value")).unwrap();
         assert_eq!(url.query(), Some("new=query&param=value"));
 
-        url.set_query(None);
+        url.set_query(None).unwrap();
         assert_eq!(url.query(), None);
+
+        let long = "x".repeat(MAX_QUERY_LEN + 1);
+        assert_eq!(url.set_query(Some(&long)), Err("Query too long"));
+        let long = alloc::format!("http://example.com/?{long}");
+        assert_eq!(SimpleUrl::parse(&long).unwrap_err(), "Query too long");
+    }
+
+    #[test]
+    fn test_simple_url_append_query() {
+        let mut url = SimpleUrl::parse("http://tracker.com/announce?passkey=abc").unwrap();
+        url.append_query("info_hash=%01&port=6881").unwrap();
+        assert_eq!(url.query(), Some("passkey=abc&info_hash=%01&port=6881"));
+
+        let mut url = SimpleUrl::parse("http://tracker.com/announce.php?").unwrap();
+        url.append_query("port=6881").unwrap();
+        assert_eq!(url.query(), Some("port=6881"));
+
+        let mut url = SimpleUrl::parse("http://tracker.com/announce").unwrap();
+        url.append_query("port=6881").unwrap();
+        assert_eq!(url.query(), Some("port=6881"));
+
+        // a failed append leaves the query alone
+        let long = "x".repeat(MAX_QUERY_LEN);
+        assert_eq!(url.append_query(&long), Err("Query too long"));
+        assert_eq!(url.query(), Some("port=6881"));
+    }
+
+    #[test]
+    fn test_simple_url_ipv6() {
+        let url = SimpleUrl::parse("http://[::1]:6969/announce").unwrap();
+        assert_eq!(url.host_str(), Some("[::1]"));
+        assert_eq!(url.port(), Some(6969));
+        assert_eq!(url.path(), "/announce");
+        assert_eq!(url.join("/scrape"), "http://[::1]:6969/scrape");
+
+        let url = SimpleUrl::parse("udp://[2001:db8::1]").unwrap();
+        assert_eq!(url.host_str(), Some("[2001:db8::1]"));
+        assert_eq!(url.port(), None);
+
+        for invalid in ["http://[::1", "http://[::1]6969", "http://[example.com]/"] {
+            assert_eq!(SimpleUrl::parse(invalid).unwrap_err(), "Invalid IPv6 host");
+        }
+    }
+
+    #[test]
+    fn test_simple_url_userinfo_and_fragment() {
+        let url = SimpleUrl::parse("http://user:p%40ss@w@tracker.com:80/announce?a=1#top").unwrap();
+        assert_eq!(url.userinfo(), Some("user:p%40ss@w"));
+        assert_eq!(url.host_str(), Some("tracker.com"));
+        assert_eq!(url.port(), Some(80));
+        assert_eq!(url.query(), Some("a=1"));
+
+        // an `@` in the path or the query isn't userinfo
+        let url = SimpleUrl::parse("http://tracker.com/announce?mail=a@b#x").unwrap();
+        assert_eq!(url.userinfo(), None);
+        assert_eq!(url.host_str(), Some("tracker.com"));
+        assert_eq!(url.path(), "/announce");
+        assert_eq!(url.query(), Some("mail=a@b"));
+
+        assert_eq!(
+            SimpleUrl::parse("http://user@:80/").unwrap_err(),
+            "Invalid URL: missing host"
+        );
+    }
+
+    #[test]
+    fn test_simple_url_join() {
+        let url = SimpleUrl::parse("http://tracker.com:6969/a/announce?x=1").unwrap();
+        let cases = [
+            ("http://other.com/announce", "http://other.com/announce"),
+            ("//other.com/announce?y=2", "http://other.com/announce?y=2"),
+            ("/b/announce?y=2", "http://tracker.com:6969/b/announce?y=2"),
+            ("?y=2", "http://tracker.com:6969/a/announce?y=2"),
+            ("announce.php", "http://tracker.com:6969/a/announce.php"),
+            ("b?url=http://x", "http://tracker.com:6969/a/b?url=http://x"),
+        ];
+        for (reference, joined) in cases {
+            assert_eq!(url.join(reference), joined, "{reference}");
+        }
     }
 
     #[test]
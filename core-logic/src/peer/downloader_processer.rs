@@ -1,22 +1,49 @@
 use embedded_io_async::{Read, Write};
+use embedded_sdmmc::BlockDevice;
 
 use crate::{
     TcpConnector,
-    fs::{FileSystem, FileSystemExt, VolumeMgr},
+    fs::{FileMap, FileSystem, VolumeMgr},
     peer::{BLOCK_SIZE, Handshaken, Peer, State, buf_reader::BufReader, messages::PeerMessage},
 };
 
+#[defmt_or_log::derive_format_or_debug]
+pub(crate) enum DownloadError<NET, V>
+where
+    NET: TcpConnector,
+    V: VolumeMgr,
+{
+    /// Talking to the peer failed
+    Connection(NET::Error),
+    /// The peer closed the connection
+    Closed,
+    /// Writing a piece failed (e.g. the file is too large for FAT or its directory is missing)
+    WriteFailed(embedded_sdmmc::Error<<<V as VolumeMgr>::BlockDevice as BlockDevice>::Error>),
+}
+
+/// How far the download got with a message.
+enum Progress {
+    Pending,
+    /// a piece was written completely, the next one is requested
+    PieceDone,
+    /// the last piece was written
+    Finished,
+}
+
 impl<'a, NET> Peer<'a, NET, Handshaken>
 where
     NET: TcpConnector + 'a,
 {
-    /// main entry
+    /// main entry, downloads the next piece
     /// - reads data
     /// - parses & handles messages
-    pub(crate) async fn download_process_incoming_data(
+    ///
+    /// Returns whether there are more pieces to download.
+    pub(crate) async fn download_piece<V: VolumeMgr>(
         &mut self,
-        fs: &mut FileSystem<impl VolumeMgr>,
-    ) -> Result<(), NET::Error> {
+        fs: &mut FileSystem<V>,
+        files: &FileMap,
+    ) -> Result<bool, DownloadError<NET, V>> {
         let mut buf = BufReader::<
             {
                 BLOCK_SIZE as usize + 4 /* length */ + 1 /* id */ + 8 /* index, begin of payload */
@@ -26,11 +53,11 @@ where
         loop {
             // read data from the peer connection into the buffer
             let bytes_read = match self.connection().read(buf.remaining_mut()).await {
-                Ok(0) => 0,
+                Ok(0) => return Err(DownloadError::Closed),
                 Ok(len) => len,
                 Err(e) => {
                     defmt_or_log::error!("Failed to read from peer: {:?}", e);
-                    break; // TODO
+                    return Err(DownloadError::Connection(e));
                 }
             };
 
@@ -57,8 +84,10 @@ where
             };
 
             // process the message
-            if self.process_msg(msg, fs).await? {
-                break;
+            match self.process_msg(msg, fs, files).await? {
+                Progress::Pending => {}
+                Progress::PieceDone => return Ok(true),
+                Progress::Finished => return Ok(false),
             }
 
             // reset the buffer for the next message
@@ -66,17 +95,15 @@ where
                 buf.reset();
             }
         }
-
-        Ok(())
     }
+
     /// Processes an incoming peer message.
-    ///
-    /// Returns Ok(true) if we're finished.
-    async fn process_msg(
+    async fn process_msg<V: VolumeMgr>(
         &mut self,
         msg: Option<PeerMessage<'_>>,
-        fs: &mut FileSystem<impl VolumeMgr>,
-    ) -> Result<bool, NET::Error> {
+        fs: &mut FileSystem<V>,
+        files: &FileMap,
+    ) -> Result<Progress, DownloadError<NET, V>> {
         match (self.state, msg) {
             (State::NotHandshaken, _) => {
                 unreachable!("this method isn't callable here");
@@ -86,14 +113,20 @@ where
                 let msg = PeerMessage::Interested;
                 self.connection()
                     .write_all(&msg.as_bittorrent_bytes())
-                    .await?;
-                self.connection().flush().await?;
+                    .await
+                    .map_err(DownloadError::Connection)?;
+                self.connection()
+                    .flush()
+                    .await
+                    .map_err(DownloadError::Connection)?;
                 self.state = State::ChokedInterested;
             }
             (State::ChokedInterested, Some(PeerMessage::Unchoke)) => {
                 defmt_or_log::info!("Peer unchoked us");
                 self.state = State::UnchokedInterested;
-                self.send_request().await?;
+                self.send_request()
+                    .await
+                    .map_err(DownloadError::Connection)?;
             }
             (
                 State::UnchokedInterested,
@@ -103,12 +136,19 @@ where
                     block,
                 }),
             ) => {
-                self.handle_piece_message(index, begin, block, fs).await?;
+                let current = self.piece.index();
+                self.handle_piece_message(index, begin, block, fs, files)
+                    .await?;
                 // move onto the next piece
                 if !self.piece.increment() {
-                    return Ok(true);
+                    return Ok(Progress::Finished);
+                }
+                self.send_request()
+                    .await
+                    .map_err(DownloadError::Connection)?;
+                if self.piece.index() != current {
+                    return Ok(Progress::PieceDone);
                 }
-                self.send_request().await?;
             }
             (State::UnchokedInterested, Some(PeerMessage::Choke)) => {
                 self.state = State::ChokedInterested;
@@ -116,7 +156,7 @@ where
             _ => {}
         }
 
-        Ok(false)
+        Ok(Progress::Pending)
     }
 
     async fn send_request(&mut self) -> Result<(), NET::Error> {
@@ -149,13 +189,14 @@ where
         Ok(())
     }
 
-    async fn handle_piece_message(
+    async fn handle_piece_message<V: VolumeMgr>(
         &mut self,
         index: u32,
         begin: u32,
         block: &[u8],
-        fs: &mut FileSystem<impl VolumeMgr>,
-    ) -> Result<(), NET::Error> {
+        fs: &mut FileSystem<V>,
+        files: &FileMap,
+    ) -> Result<(), DownloadError<NET, V>> {
         defmt_or_log::trace!(
             "Received block at begin: {} from piece {} from peer",
             begin,
@@ -174,6 +215,7 @@ where
 
         // add block
         self.piece.add_block(begin, block);
+        self.downloaded += block.len() as u64;
         // TODO: update SHA1
 
         // check whether complete
@@ -185,12 +227,9 @@ where
 
             // TODO: check SHA1
 
-            // TODO: seek?
-            assert!(fs.get_open_file().is_some());
-            fs.write_to_opened_file(self.piece.get_piece_data())
+            fs.write_at(files, self.piece.data_offset(), self.piece.get_piece_data())
                 .await
-                .expect("Failed to write piece to file system");
-            fs.flush().unwrap(); // TODO: handle error
+                .map_err(DownloadError::WriteFailed)?;
         }
 
         Ok(())
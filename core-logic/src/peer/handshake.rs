@@ -4,8 +4,8 @@ use embedded_io_async::{Read, Write};
 
 use crate::{
     TcpConnector,
-    core::InfoHash,
-    peer::{Handshaken, NotHandshaken, PEER_ID, Peer, messages::PeerMessage},
+    core::{InfoHash, PeerId},
+    peer::{Handshaken, NotHandshaken, Peer, messages::PeerMessage},
 };
 
 impl<'a, NET> Peer<'a, NET, NotHandshaken>
@@ -17,8 +17,9 @@ where
     pub(crate) async fn into_handshake_performed(
         mut self,
         info_hash: &InfoHash,
+        peer_id: &PeerId,
     ) -> Result<Peer<'a, NET, Handshaken>, HandshakeError<NET>> {
-        let handshake_msg = construct_handshake(info_hash, &PEER_ID);
+        let handshake_msg = construct_handshake(info_hash, peer_id);
         self.connection()
             .write_all(handshake_msg.as_slice())
             .await
@@ -59,14 +60,21 @@ where
             _handshake_state: PhantomData,
             state: crate::peer::State::ChokedNotInterested,
             piece: self.piece,
+            downloaded: self.downloaded,
         })
     }
 }
 
-fn construct_handshake(info_hash: &InfoHash, peer_id: &[u8; 20]) -> [u8; 68] {
+/// Bit in the reserved bytes of the handshake that announces the extension protocol (BEP 10).
+const EXTENSION_BYTE: usize = 5;
+const EXTENSION_FLAG: u8 = 0x10;
+
+pub(super) fn construct_handshake(info_hash: &InfoHash, peer_id: &[u8; 20]) -> [u8; 68] {
     let mut handshake_msg: [u8; 68] = [0; 68];
     let protocol_str = b"BitTorrent protocol";
-    let reserved = [0u8; 8];
+    let mut reserved = [0u8; 8];
+    // we understand extended messages (needed to download the metadata of magnet links)
+    reserved[EXTENSION_BYTE] |= EXTENSION_FLAG;
 
     handshake_msg[0] = 19; // Protocol string length
     handshake_msg[1..20].copy_from_slice(protocol_str);
@@ -77,6 +85,11 @@ fn construct_handshake(info_hash: &InfoHash, peer_id: &[u8; 20]) -> [u8; 68] {
     handshake_msg
 }
 
+/// Whether the peer's handshake announces support for extended messages.
+pub(super) const fn supports_extensions(handshake: &[u8; 68]) -> bool {
+    handshake[20 + EXTENSION_BYTE] & EXTENSION_FLAG != 0
+}
+
 #[defmt_or_log::derive_format_or_debug]
 pub enum HandshakeError<NET>
 where
@@ -89,3 +102,20 @@ where
     /// Hash mismatch in handshake response
     InvalidHash,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_announces_extensions() {
+        let handshake = construct_handshake(&[1; 20], b"-MT0100-000000000000");
+        assert_eq!(&handshake[1..20], b"BitTorrent protocol");
+        assert_eq!(handshake[20..28], [0, 0, 0, 0, 0, 0x10, 0, 0]);
+        assert!(supports_extensions(&handshake));
+
+        let mut handshake = handshake;
+        handshake[25] = 0;
+        assert!(!supports_extensions(&handshake));
+    }
+}
@@ -15,6 +15,8 @@ pub(crate) enum PeerMessageTypes {
     Request = 6,
     Piece = 7,
     Cancel = 8,
+    /// BEP 10
+    Extended = 20,
 }
 
 #[defmt_or_log::derive_format_or_debug]
@@ -41,6 +43,11 @@ pub enum PeerMessage<'a> {
         begin: u32,
         length: u32,
     },
+    /// A message of the extension protocol (BEP 10), `id` 0 is the extended handshake.
+    Extended {
+        id: u8,
+        payload: &'a [u8], // bencoded dict, possibly followed by raw data
+    },
 }
 
 impl<'a> PeerMessage<'a> {
@@ -55,6 +62,7 @@ impl<'a> PeerMessage<'a> {
             PeerMessage::Request { .. } => Some(PeerMessageTypes::Request as u8),
             PeerMessage::Piece { .. } => Some(PeerMessageTypes::Piece as u8),
             PeerMessage::Cancel { .. } => Some(PeerMessageTypes::Cancel as u8),
+            PeerMessage::Extended { .. } => Some(PeerMessageTypes::Extended as u8),
             PeerMessage::KeepAlive => None, // KeepAlive messages have no payload and no message type
         }
     }
@@ -80,6 +88,10 @@ impl<'a> PeerMessage<'a> {
                 bitfield_len as u32 + 1 // +1 for the message type
             }
             PeerMessage::Request { .. } | PeerMessage::Cancel { .. } => 13,
+            PeerMessage::Extended { payload, .. } => {
+                bytes.reserve(payload.len() + 6);
+                payload.len() as u32 + 2 // +2 for the message type and the extended id
+            }
             PeerMessage::Piece { .. } => unimplemented!("Piece messages are not supported yet"), // + 7
         };
 
@@ -135,6 +147,10 @@ impl<'a> PeerMessage<'a> {
                 bytes.extend_from_slice(&begin.to_be_bytes());
                 bytes.extend_from_slice(&length.to_be_bytes());
             }
+            PeerMessage::Extended { id, payload } => {
+                bytes.push(*id);
+                bytes.extend_from_slice(payload);
+            }
             PeerMessage::Piece { .. } => unimplemented!("Piece messages are not supported yet"),
         }
 
@@ -173,6 +189,9 @@ impl<'a> PeerMessage<'a> {
             b if b == PeerMessageTypes::Request as u8 => parse_request_message(payload),
             b if b == PeerMessageTypes::Piece as u8 => parse_piece_message(payload),
             b if b == PeerMessageTypes::Cancel as u8 => parse_cancel_message(payload),
+            b if b == PeerMessageTypes::Extended as u8 => {
+                parse_extended_message(&payload[..len as usize])
+            }
             b => Err(MessageError::UnknownMessageType(b)),
         }
     }
@@ -250,6 +269,17 @@ fn parse_cancel_message<'a>(data: &'a [u8]) -> Result<Option<PeerMessage<'a>>, M
     }))
 }
 
+fn parse_extended_message<'a>(data: &'a [u8]) -> Result<Option<PeerMessage<'a>>, MessageError> {
+    if data.len() < 2 {
+        return Err(MessageError::InvalidLength);
+    }
+
+    Ok(Some(PeerMessage::Extended {
+        id: data[1],
+        payload: &data[2..],
+    }))
+}
+
 impl<'a> TryInto<u8> for PeerMessage<'a> {
     type Error = ();
 
@@ -463,4 +493,37 @@ mod tests {
         //     .concat()
         // )
     }
+
+    #[test]
+    fn test_extended_message() {
+        let payload = b"d1:md11:ut_metadatai1eee";
+        let mut buf = BufReader::<40>::new();
+        buf.remaining_mut()[..4].copy_from_slice(&(payload.len() as u32 + 2).to_be_bytes());
+        buf.remaining_mut()[4] = PeerMessageTypes::Extended as u8;
+        buf.remaining_mut()[5] = 0;
+        buf.remaining_mut()[6..6 + payload.len()].copy_from_slice(payload);
+        buf.advance_n(6 + payload.len());
+
+        let msg = PeerMessage::from_bytes(&mut buf).unwrap().unwrap();
+
+        assert!(matches!(msg, PeerMessage::Extended { id: 0, payload: p } if p == payload));
+        assert_eq!(
+            msg.as_bittorrent_bytes().as_slice(),
+            [
+                &(payload.len() as u32 + 2).to_be_bytes()[..],
+                &[PeerMessageTypes::Extended as u8, 0],
+                payload
+            ]
+            .concat()
+        );
+
+        // the extended id is missing
+        buf.reset();
+        buf.remaining_mut()[..5].copy_from_slice(&[0, 0, 0, 1, PeerMessageTypes::Extended as u8]);
+        buf.advance_n(5);
+        assert!(matches!(
+            PeerMessage::from_bytes(&mut buf),
+            Err(MessageError::InvalidLength)
+        ));
+    }
 }
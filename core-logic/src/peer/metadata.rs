@@ -0,0 +1,279 @@
+//! Downloading the info dict of a torrent from a peer (BEP 9, `ut_metadata`).
+//!
+//! Flow:
+//!     handshake (with the extension bit) -> extended handshake -> request every 16 KiB piece
+//!     of the metadata in order -> check the SHA-1 against the info hash
+
+use ::core::fmt::Write as _;
+
+use bencode::{BencodeDecode, BencodeParser, Dict};
+use embedded_io_async::{Read, ReadExactError, Write};
+
+use crate::{
+    TcpConnector,
+    core::{InfoHash, PeerId},
+    peer::{
+        BLOCK_SIZE,
+        buf_reader::BufReader,
+        handshake::{construct_handshake, supports_extensions},
+        messages::{PeerMessage, error::MessageError},
+    },
+};
+
+/// The id peers have to use for `ut_metadata` messages to us, announced in our extended handshake.
+const UT_METADATA_ID: u8 = 1;
+/// The metadata is sent in pieces of 16 KiB, only the last one may be shorter.
+const METADATA_PIECE_SIZE: usize = BLOCK_SIZE as usize;
+
+/// `msg_type` of `ut_metadata` messages
+const MSG_REQUEST: u8 = 0;
+const MSG_DATA: u8 = 1;
+const MSG_REJECT: u8 = 2;
+
+/// Downloads the info dict of the torrent with `info_hash` from an already connected peer into
+/// `buf`. Returns the length of the info dict.
+pub(crate) async fn fetch_metadata<NET: TcpConnector>(
+    conn: &mut NET::Connection<'_>,
+    info_hash: &InfoHash,
+    peer_id: &PeerId,
+    buf: &mut [u8],
+) -> Result<usize, MetadataError<NET>> {
+    let handshake = construct_handshake(info_hash, peer_id);
+    send(conn, &handshake).await?;
+
+    let mut response = [0u8; 68];
+    conn.read_exact(&mut response)
+        .await
+        .map_err(MetadataError::ReadFailed)?;
+    if response[28..48] != handshake[28..48] {
+        return Err(MetadataError::InvalidHash);
+    }
+    if !supports_extensions(&response) {
+        return Err(MetadataError::NotSupported);
+    }
+
+    let extended_handshake = PeerMessage::Extended {
+        id: 0,
+        payload: b"d1:md11:ut_metadatai1eee",
+    };
+    send(conn, &extended_handshake.as_bittorrent_bytes()).await?;
+
+    // the peer may send a bitfield and haves first
+    let mut msg = BufReader::<
+        {
+            METADATA_PIECE_SIZE + 4 /* length */ + 2 /* ids */ + 128 /* dict */
+        },
+    >::new();
+    let (peer_id, size) = loop {
+        if let Some(PeerMessage::Extended { id: 0, payload }) = read_message(conn, &mut msg).await?
+        {
+            break parse_extended_handshake(payload).ok_or(MetadataError::NotSupported)?;
+        }
+    };
+    if size > buf.len() {
+        return Err(MetadataError::TooLarge(size));
+    }
+
+    defmt_or_log::info!("Peer has {} bytes of metadata", size);
+
+    for piece in 0..size.div_ceil(METADATA_PIECE_SIZE) {
+        let mut request = heapless::String::<48>::new();
+        write!(request, "d8:msg_typei{}e5:piecei{}ee", MSG_REQUEST, piece).expect("fits");
+        let request = PeerMessage::Extended {
+            id: peer_id,
+            payload: request.as_bytes(),
+        };
+        send(conn, &request.as_bittorrent_bytes()).await?;
+
+        let data = loop {
+            let Some(PeerMessage::Extended {
+                id: UT_METADATA_ID,
+                payload,
+            }) = read_message(conn, &mut msg).await?
+            else {
+                continue;
+            };
+            let received = MetadataPiece::parse(payload).map_err(MetadataError::InvalidResponse)?;
+            match received.msg_type {
+                MSG_DATA if received.piece == piece => break received.data,
+                MSG_REJECT => return Err(MetadataError::Rejected(piece)),
+                _ => continue,
+            }
+        };
+
+        let start = piece * METADATA_PIECE_SIZE;
+        let end = size.min(start + METADATA_PIECE_SIZE);
+        if data.len() != end - start {
+            return Err(MetadataError::InvalidLength);
+        }
+        buf[start..end].copy_from_slice(data);
+    }
+
+    if sha1_smol::Sha1::from(&buf[..size]).digest().bytes() != *info_hash {
+        return Err(MetadataError::HashMismatch);
+    }
+
+    Ok(size)
+}
+
+async fn send<NET: TcpConnector>(
+    conn: &mut NET::Connection<'_>,
+    bytes: &[u8],
+) -> Result<(), MetadataError<NET>> {
+    conn.write_all(bytes)
+        .await
+        .map_err(MetadataError::WriteFailed)?;
+    conn.flush().await.map_err(MetadataError::WriteFailed)
+}
+
+/// Reads the next whole message. Messages that don't fit into `msg` are skipped (`None`).
+async fn read_message<'m, NET: TcpConnector, const CAP: usize>(
+    conn: &mut NET::Connection<'_>,
+    msg: &'m mut BufReader<CAP>,
+) -> Result<Option<PeerMessage<'m>>, MetadataError<NET>> {
+    msg.reset();
+    conn.read_exact(&mut msg.remaining_mut()[..4])
+        .await
+        .map_err(MetadataError::ReadFailed)?;
+    msg.advance_n(4);
+    let len = u32::from_be_bytes([msg[0], msg[1], msg[2], msg[3]]) as usize;
+
+    if len > msg.remaining_mut().len() {
+        let mut left = len;
+        while left > 0 {
+            let chunk = left.min(msg.remaining_mut().len());
+            conn.read_exact(&mut msg.remaining_mut()[..chunk])
+                .await
+                .map_err(MetadataError::ReadFailed)?;
+            left -= chunk;
+        }
+        return Ok(None);
+    }
+
+    conn.read_exact(&mut msg.remaining_mut()[..len])
+        .await
+        .map_err(MetadataError::ReadFailed)?;
+    msg.advance_n(len);
+    PeerMessage::from_bytes(msg).map_err(MetadataError::InvalidMessage)
+}
+
+/// The extended handshake as far as we need it.
+#[derive(BencodeDecode)]
+struct ExtendedHandshake<'a> {
+    /// extension name -> the id the peer wants us to use
+    m: Dict<'a>,
+    metadata_size: Option<u32>,
+}
+
+/// Returns the peer's id for `ut_metadata` messages and the size of the metadata,
+/// or `None` if the peer can't send it.
+///
+/// Peers are untrusted, so non-canonical bencode is rejected.
+fn parse_extended_handshake(payload: &[u8]) -> Option<(u8, usize)> {
+    let handshake = ExtendedHandshake::decode(&mut BencodeParser::new(payload).strict()).ok()?;
+    let id = handshake.m.get("ut_metadata")?.as_int()?;
+    // an id of 0 means the extension was disabled
+    let id = u8::try_from(id).ok().filter(|id| *id != 0)?;
+    Some((id, handshake.metadata_size? as usize))
+}
+
+/// A `ut_metadata` message, the data of the piece follows right after the dict.
+struct MetadataPiece<'a> {
+    msg_type: u8,
+    piece: usize,
+    data: &'a [u8],
+}
+
+#[derive(BencodeDecode)]
+struct RawMetadataPiece {
+    msg_type: u8,
+    piece: usize,
+}
+
+impl<'a> MetadataPiece<'a> {
+    /// Parses the message strictly, like the extended handshake.
+    fn parse(payload: &'a [u8]) -> bencode::Result<Self> {
+        let mut p = BencodeParser::new(payload).strict();
+        let raw = RawMetadataPiece::decode(&mut p)?;
+        Ok(Self {
+            msg_type: raw.msg_type,
+            piece: raw.piece,
+            data: p.remaining(),
+        })
+    }
+}
+
+#[defmt_or_log::derive_format_or_debug]
+pub enum MetadataError<NET>
+where
+    NET: TcpConnector,
+{
+    /// Writing has failed
+    WriteFailed(NET::Error),
+    /// Reading has failed
+    ReadFailed(ReadExactError<NET::Error>),
+    /// Hash mismatch in handshake response
+    InvalidHash,
+    /// The peer doesn't support the extension protocol or `ut_metadata`
+    NotSupported,
+    /// The metadata doesn't fit into the buffer
+    TooLarge(usize),
+    /// The peer sent a broken message
+    InvalidMessage(MessageError),
+    /// The dict of a `ut_metadata` message is broken
+    InvalidResponse(bencode::Error),
+    /// The peer rejected the request for a piece
+    Rejected(usize),
+    /// A piece doesn't have the expected length
+    InvalidLength,
+    /// The metadata doesn't hash to the info hash
+    HashMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_extended_handshake() {
+        let payload = b"d1:md11:lt_donthavei7e11:ut_metadatai3ee13:metadata_sizei31235e1:v4:teste";
+        assert_eq!(parse_extended_handshake(payload), Some((3, 31235)));
+
+        // ut_metadata disabled or missing
+        assert_eq!(
+            parse_extended_handshake(b"d1:md11:ut_metadatai0ee13:metadata_sizei1ee"),
+            None
+        );
+        assert_eq!(
+            parse_extended_handshake(b"d1:mde13:metadata_sizei1ee"),
+            None
+        );
+        // no metadata_size
+        assert_eq!(parse_extended_handshake(b"d1:md11:ut_metadatai3eee"), None);
+        // not canonical: unsorted keys, a leading zero
+        assert_eq!(
+            parse_extended_handshake(b"d13:metadata_sizei1e1:md11:ut_metadatai3eee"),
+            None
+        );
+        assert_eq!(
+            parse_extended_handshake(b"d1:md11:ut_metadatai03ee13:metadata_sizei1ee"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata_piece() {
+        let piece =
+            MetadataPiece::parse(b"d8:msg_typei1e5:piecei2e10:total_sizei34256eexxxx").unwrap();
+        assert_eq!(piece.msg_type, MSG_DATA);
+        assert_eq!(piece.piece, 2);
+        assert_eq!(piece.data, b"xxxx");
+
+        let reject = MetadataPiece::parse(b"d8:msg_typei2e5:piecei0ee").unwrap();
+        assert_eq!(reject.msg_type, MSG_REJECT);
+        assert!(reject.data.is_empty());
+
+        assert!(MetadataPiece::parse(b"d5:piecei0ee").is_err());
+        assert!(MetadataPiece::parse(b"d5:piecei0e8:msg_typei1eexxxx").is_err());
+    }
+}
@@ -1,14 +1,29 @@
 use ::core::marker::PhantomData;
 
-use crate::{TcpConnector, core::PeerId, peer::piece_state::PieceState};
+use crate::{Rng, TcpConnector, core::PeerId, peer::piece_state::PieceState};
 pub(super) mod buf_reader;
 pub mod downloader_processer;
 pub mod handshake;
 pub(crate) mod messages;
-mod piece_state;
+pub mod metadata;
+pub(crate) mod piece_state;
 
 pub const BLOCK_SIZE: u32 = 16 * 1024; // 16KB
-const PEER_ID: PeerId = *b"AwesomeESP32C3Client";
+/// Who we are in Azureus-style peer ids: `MT` (minitorrent) version 0.1.0.0
+const CLIENT_PREFIX: &[u8; 8] = b"-MT0100-";
+
+/// An Azureus-style peer id, [`CLIENT_PREFIX`] and 12 random alphanumeric characters.
+pub(crate) fn generate_peer_id(rng: &mut dyn Rng) -> PeerId {
+    const CHARS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+    let mut peer_id = [0u8; 20];
+    peer_id[..8].copy_from_slice(CLIENT_PREFIX);
+    rng.fill_bytes(&mut peer_id[8..]);
+    for b in &mut peer_id[8..] {
+        *b = CHARS[*b as usize % CHARS.len()];
+    }
+    peer_id
+}
 
 /// A Peer in the BitTorrent protocol, parameterized by its handshake, choke, and interest states.
 ///
@@ -19,7 +34,7 @@ const PEER_ID: PeerId = *b"AwesomeESP32C3Client";
 ///
 /// ```ignore
 /// // Create a new peer connection, the tcp-connection comes from the `BitTorrenter`
-/// let peer = Peer::new(tcp_connection); // NotHandshaken, Choked, NotInterested
+/// let peer = Peer::new(tcp_connection, piece); // NotHandshaken, Choked, NotInterested
 /// // Perform handshake
 /// let peer = peer.into_handshake_performed(info_hash).await?; // Handshaken, Choked, NotInterested
 pub(crate) struct Peer<'a, NET, HandshakeState = NotHandshaken>
@@ -28,6 +43,8 @@ where
 {
     state: State,
     piece: PieceState,
+    /// bytes of piece data received from this peer
+    downloaded: u64,
     connection: NET::Connection<'a>,
     _handshake_state: PhantomData<HandshakeState>,
 }
@@ -36,15 +53,26 @@ impl<'a, NET, HandshakeState> Peer<'a, NET, HandshakeState>
 where
     NET: TcpConnector + 'a,
 {
-    pub(crate) fn new(connection: NET::Connection<'a>, piece_length: u32, file_size: u32) -> Self {
+    /// `piece` is where the download continues, it's handed from peer to peer.
+    pub(crate) fn new(connection: NET::Connection<'a>, piece: PieceState) -> Self {
         Self {
             connection,
             _handshake_state: PhantomData,
             state: State::default(),
-            piece: PieceState::new(0, piece_length, file_size),
+            piece,
+            downloaded: 0,
         }
     }
 
+    /// Closes the connection, the download can continue with the next peer.
+    pub(crate) fn into_piece(self) -> PieceState {
+        self.piece
+    }
+
+    pub(crate) const fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
     pub(crate) const fn connection(&mut self) -> &mut NET::Connection<'a> {
         &mut self.connection
     }
@@ -64,3 +92,18 @@ pub(super) enum State {
 pub(super) struct Handshaken;
 #[defmt_or_log::derive_format_or_debug]
 pub(super) struct NotHandshaken;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::XorShift;
+
+    #[test]
+    fn test_generate_peer_id() {
+        let mut rng = XorShift::from_clock();
+        let peer_id = generate_peer_id(&mut rng);
+        assert!(peer_id.starts_with(b"-MT0100-"));
+        assert!(peer_id[8..].iter().all(u8::is_ascii_alphanumeric));
+        assert_ne!(generate_peer_id(&mut rng), peer_id);
+    }
+}
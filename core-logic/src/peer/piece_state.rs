@@ -1,4 +1,4 @@
-use static_cell::StaticCell;
+use alloc::{boxed::Box, vec};
 
 use crate::BLOCK_SIZE;
 
@@ -6,36 +6,40 @@ use crate::BLOCK_SIZE;
 pub(super) const NUM_BLOCKS: u32 = 2;
 
 /// Represents the state of a piece being downloaded from a peer.
-/// The block data lives in a static buffer to avoid heap fragmentation.
-pub(super) struct PieceState {
+/// The block buffer is allocated once per download, not per piece, to avoid heap fragmentation.
+pub(crate) struct PieceState {
     /// current piece index (0-based)
     index: u32,
-    /// bitfield tracking which blocks have been received
-    have: u32,
-    /// reference to a static buffer holding one block's worth of data
-    piece: &'static mut [u8; (NUM_BLOCKS * BLOCK_SIZE) as usize],
+    /// bitfield tracking which blocks have been received, big enough for every block of a piece
+    /// (1 bit per 16 KiB, so 128 bytes for 16 MiB pieces)
+    have: Box<[u32]>,
+    /// buffer holding `NUM_BLOCKS` blocks' worth of data
+    piece: Box<[u8]>,
     /// number of bytes received so far for this piece
     len_bytes: u32,
+    /// offset within the piece of the first buffered byte
+    buf_begin: u32,
     /// actual number of blocks for this piece (recomputed on increment)
     num_blocks: u32,
     /// size of the current piece in bytes (last piece of the file may be smaller)
     piece_size: u32,
     /// standard piece size from the torrent metadata
     piece_length: u32,
-    /// total file size
-    file_size: u32,
+    /// total size of the torrent
+    file_size: u64,
 }
 
 impl PieceState {
-    pub(super) fn new(index: u32, piece_length: u32, file_size: u32) -> Self {
-        static PIECE_BUF: StaticCell<[u8; (NUM_BLOCKS * BLOCK_SIZE) as usize]> = StaticCell::new();
+    pub(crate) fn new(index: u32, piece_length: u32, file_size: u64) -> Self {
         let piece_size = piece_size_for(index, piece_length, file_size);
         let num_blocks = piece_size.div_ceil(BLOCK_SIZE);
         Self {
             index,
-            have: 0,
-            piece: PIECE_BUF.init([0u8; (NUM_BLOCKS * BLOCK_SIZE) as usize]),
+            have: vec![0; piece_length.div_ceil(BLOCK_SIZE).div_ceil(u32::BITS) as usize]
+                .into_boxed_slice(),
+            piece: vec![0u8; (NUM_BLOCKS * BLOCK_SIZE) as usize].into_boxed_slice(),
             len_bytes: 0,
+            buf_begin: 0,
             num_blocks,
             piece_size,
             piece_length,
@@ -44,30 +48,41 @@ impl PieceState {
     }
 
     pub(super) fn get_piece_data(&self) -> &[u8] {
-        &self.piece.as_slice()[..self.len_bytes as usize]
+        &self.piece[..self.len_bytes as usize]
     }
 
     /// returns if all blocks for this piece have been received or the buffer for this piece is full
-    pub(super) const fn should_write(&self) -> bool {
-        self.have.count_ones() == self.num_blocks || self.len_bytes == NUM_BLOCKS * BLOCK_SIZE
+    pub(super) fn should_write(&self) -> bool {
+        self.is_complete() || self.len_bytes == NUM_BLOCKS * BLOCK_SIZE
     }
 
     pub(super) const fn index(&self) -> u32 {
         self.index
     }
 
+    /// Offset of the buffered data in the whole torrent.
+    pub(super) const fn data_offset(&self) -> u64 {
+        self.index as u64 * self.piece_length as u64 + self.buf_begin as u64
+    }
+
     pub(super) fn add_block(&mut self, begin: u32, block_data: &[u8]) {
+        if self.len_bytes == 0 {
+            self.buf_begin = begin;
+        }
         self.piece[self.len_bytes as usize..self.len_bytes as usize + block_data.len()]
             .copy_from_slice(block_data);
         // here we need the real begin offset to set the bitfield
-        self.have |= 1u32 << (begin as usize / BLOCK_SIZE as usize);
+        let block_index = begin / BLOCK_SIZE;
+        if let Some(bits) = self.have.get_mut((block_index / u32::BITS) as usize) {
+            *bits |= 1 << (block_index % u32::BITS);
+        }
         self.len_bytes += block_data.len() as u32;
     }
 
     /// returns the index, begin and length of the next block to request, or None if all blocks have been received
     pub(super) fn get_next_block_request(&self) -> Option<(u32, u32, u32)> {
         for block_index in 0..self.num_blocks {
-            if self.have & (1u32 << block_index) == 0 {
+            if !self.has_block(block_index) {
                 // we don't have this block, request it
                 let begin = block_index * BLOCK_SIZE;
                 let block_length = if block_index != self.num_blocks - 1 {
@@ -92,7 +107,7 @@ impl PieceState {
             self.len_bytes = 0;
             return true;
         }
-        if (self.index() + 1) * self.piece_length >= self.file_size {
+        if (self.index as u64 + 1) * self.piece_length as u64 >= self.file_size {
             // no more pieces to request
             return false;
         }
@@ -104,28 +119,32 @@ impl PieceState {
         true
     }
 
-    pub(super) const fn num_pieces(&self) -> u32 {
-        self.file_size.div_ceil(self.piece_length)
+    pub(super) const fn num_pieces(&self) -> u64 {
+        self.file_size.div_ceil(self.piece_length as u64)
+    }
+
+    fn has_block(&self, block_index: u32) -> bool {
+        self.have[(block_index / u32::BITS) as usize] & (1 << (block_index % u32::BITS)) != 0
     }
 
-    //
-    const fn is_complete(&self) -> bool {
-        self.have.count_ones() == self.num_blocks
+    fn is_complete(&self) -> bool {
+        self.have.iter().map(|bits| bits.count_ones()).sum::<u32>() == self.num_blocks
     }
 
     /// Resets the received state without changing the piece index.
-    const fn reset(&mut self) {
-        self.have = 0;
+    fn reset(&mut self) {
+        self.have.fill(0);
         self.len_bytes = 0;
     }
 }
 
-const fn piece_size_for(index: u32, piece_length: u32, file_size: u32) -> u32 {
-    let offset = index * piece_length;
+const fn piece_size_for(index: u32, piece_length: u32, file_size: u64) -> u32 {
+    let offset = index as u64 * piece_length as u64;
     // workaround, since `min()` isn't const yet
     // basically `(file_size - offset).min(piece_length)`
-    if file_size - offset < piece_length {
-        file_size - offset
+    if file_size - offset < piece_length as u64 {
+        // smaller than `piece_length`, so it fits
+        (file_size - offset) as u32
     } else {
         piece_length
     }
@@ -138,7 +157,7 @@ mod tests {
     #[test]
     fn test_piece_state() {
         let piece_size: u32 = NUM_BLOCKS * BLOCK_SIZE; // 32KB
-        let file_size: u32 = piece_size * 2 + 1024; // 2 pieces: 64KB, 1KB
+        let file_size = piece_size as u64 * 2 + 1024; // 2 pieces: 64KB, 1KB
 
         let mut piece_state = PieceState::new(0, piece_size, file_size);
         assert_eq!(piece_state.piece_size, piece_size);
@@ -164,6 +183,7 @@ mod tests {
         // block 1
         piece_state.add_block(BLOCK_SIZE, &[0u8; BLOCK_SIZE as usize]);
         assert!(piece_state.should_write());
+        assert_eq!(piece_state.data_offset(), BLOCK_SIZE as u64);
         piece_state.increment();
 
         // PIECE 1
@@ -197,6 +217,7 @@ mod tests {
         // receive first and only block for piece 2
         piece_state.add_block(0, &[0u8; 1024]);
         assert!(piece_state.should_write());
+        assert_eq!(piece_state.data_offset(), piece_size as u64 * 2);
         piece_state.increment();
         assert!(piece_state.should_write());
 
@@ -204,4 +225,34 @@ mod tests {
         piece_state.increment();
         assert_eq!(piece_state.get_next_block_request(), None);
     }
+
+    #[test]
+    fn test_piece_state_large_pieces() {
+        // 16 MiB pieces have 1024 blocks, more than fit into one word of the bitfield
+        let piece_length = 16 * 1024 * 1024;
+        let mut piece_state = PieceState::new(0, piece_length, piece_length as u64 * 2);
+        assert_eq!(piece_state.num_blocks, 1024);
+
+        for block in 0..1024 {
+            assert!(!piece_state.is_complete());
+            let (index, begin, length) = piece_state.get_next_block_request().unwrap();
+            assert_eq!((index, begin, length), (0, block * BLOCK_SIZE, BLOCK_SIZE));
+            piece_state.add_block(begin, &[0u8; BLOCK_SIZE as usize]);
+            if piece_state.should_write() {
+                assert!(piece_state.increment());
+            }
+        }
+        assert_eq!(piece_state.index(), 1);
+        assert_eq!(
+            piece_state.get_next_block_request(),
+            Some((1, 0, BLOCK_SIZE))
+        );
+
+        // a block beyond the piece is ignored rather than overflowing the bitfield
+        piece_state.add_block(piece_length, &[0u8; 16]);
+        assert_eq!(
+            piece_state.get_next_block_request(),
+            Some((1, 0, BLOCK_SIZE))
+        );
+    }
 }
@@ -0,0 +1,63 @@
+//! Random numbers for peer ids, announce keys, transaction ids and the order of trackers.
+
+/// A source of random numbers, e.g. the hardware RNG of the device.
+///
+/// The numbers only have to be hard to guess from the outside, they don't protect secrets.
+pub trait Rng {
+    fn next_u32(&mut self) -> u32;
+
+    /// Fills `dest` with random bytes.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let random = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+    }
+}
+
+impl ::core::fmt::Debug for dyn Rng {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        f.write_str("Rng")
+    }
+}
+
+/// The default [`Rng`], a xorshift generator seeded from the clock. Use the hardware RNG
+/// instead if there is one.
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn from_clock() -> Self {
+        Self(embassy_time::Instant::now().as_ticks() | 1)
+    }
+}
+
+impl Rng for XorShift {
+    fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 ^ (self.0 >> 32)) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_bytes() {
+        let mut counter = Counter(0);
+        let mut buf = [0u8; 6];
+        counter.fill_bytes(&mut buf);
+        assert_eq!(buf, [1, 0, 0, 0, 2, 0]);
+    }
+
+    struct Counter(u32);
+
+    impl Rng for Counter {
+        fn next_u32(&mut self) -> u32 {
+            self.0 += 1;
+            self.0
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use core_logic::fs::FileSystemExt;
+use core_logic::{
+    MetaInfoFile, SoftwareSha1,
+    core::builder::TorrentBuilder,
+    fs::{FileSystemExt, torrent_creation::BuildError},
+};
 use embedded_sdmmc::{Directory, Error, ShortFileName};
 
 use crate::fs_helper::{
@@ -18,6 +22,73 @@ async fn test_retrieve_torrent() {
     assert_eq!(&buf[..file_length.unwrap()], TORRENT_STRING);
 }
 
+#[tokio::test]
+async fn test_save_torrent_and_magnet() {
+    let magnet = b"magnet:?xt=urn:btih:d69f91e6b2ae4c542468d1073a71d4ea13879a7f";
+
+    let mut fs_duple = init_fs_duple();
+    let mut buf = [0u8; 1024 * 10];
+
+    // the saved torrent only has a short name
+    fs_duple
+        .save_torrent("sample file", TORRENT_STRING)
+        .await
+        .unwrap();
+    fs_duple
+        .open_file("SAMPLE~0.TOR", embedded_sdmmc::Mode::ReadOnly)
+        .unwrap();
+    let len = fs_duple.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(&buf[..len], TORRENT_STRING);
+
+    fs_duple
+        .open_file("LINK.MAG", embedded_sdmmc::Mode::ReadWriteCreateOrTruncate)
+        .unwrap();
+    fs_duple.write_to_opened_file(magnet).await.unwrap();
+    fs_duple.flush().unwrap();
+    let len = fs_duple.put_magnet_into_buf(&mut buf).await.unwrap();
+    assert_eq!(&buf[..len], magnet);
+}
+
+#[tokio::test]
+async fn test_build_torrent() {
+    let mut fs_duple = init_fs_duple();
+    let mut buf = [0u8; 1024];
+    let builder = TorrentBuilder::new("http://tracker.com/announce", 16);
+
+    fs_duple
+        .open_file("log.csv", embedded_sdmmc::Mode::ReadWriteCreateOrTruncate)
+        .unwrap();
+    fs_duple
+        .write_to_opened_file(b"Hello from FAT32!")
+        .await
+        .unwrap();
+    fs_duple.flush().unwrap();
+
+    // 17 bytes, so there's a short second piece
+    let len = builder
+        .build(&mut fs_duple, "LOG.CSV", &SoftwareSha1::new(), &mut buf)
+        .await
+        .unwrap();
+    let torrent = MetaInfoFile::parse(&buf[..len]).unwrap();
+    assert!(torrent.validate().is_ok());
+    assert_eq!(torrent.info.name, "LOG.CSV");
+    assert_eq!(torrent.info.total_length(), 17);
+    assert_eq!(
+        torrent.info.pieces,
+        [
+            sha1_smol::Sha1::from("Hello from FAT32").digest().bytes(),
+            sha1_smol::Sha1::from("!").digest().bytes()
+        ]
+    );
+
+    assert!(matches!(
+        builder
+            .build(&mut fs_duple, "missing", &SoftwareSha1::new(), &mut buf)
+            .await,
+        Err(BuildError::NotFound)
+    ));
+}
+
 #[tokio::test]
 async fn test_write_file() {
     let file_name = "test.txt";
@@ -3,12 +3,12 @@ use std::{
     net::{IpAddr, SocketAddr, SocketAddrV4},
 };
 
-use core_logic::TcpConnector;
+use core_logic::{TcpConnector, UdpConnector};
 use embedded_io_async::ErrorType;
-use embedded_nal_async::{AddrType, Dns};
+use embedded_nal_async::{AddrType, ConnectedUdp, Dns};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+    net::{TcpStream, UdpSocket},
 };
 
 // pub const IP_ADDRESS: &Ipv4Addr = &std::net::Ipv4Addr::new(192, 168, 1, 42);
@@ -23,6 +23,10 @@ pub struct WifiHelper;
 #[derive(Debug)]
 pub struct TcpConnectionDuple(TcpStream);
 
+/// Wrapper around tokio's connected `UdpSocket` that implements `ConnectedUdp`.
+#[derive(Debug)]
+pub struct UdpSocketDuple(UdpSocket);
+
 impl TcpConnector for WifiHelper {
     type Error = WifiError;
     type Connection<'a> = TcpConnectionDuple;
@@ -49,6 +53,25 @@ impl TcpConnector for WifiHelper {
     }
 }
 
+impl UdpConnector for WifiHelper {
+    type Error = WifiError;
+    type Socket<'a> = UdpSocketDuple;
+
+    /// Open a UDP socket on an ephemeral port, the buffers are ignored like for TCP.
+    async fn connect_udp<'a>(
+        &'a mut self,
+        remote: SocketAddrV4,
+        _rx_buffer: &'a mut [u8], // tokio manages its own buffers
+        _tx_buffer: &'a mut [u8], // tokio manages its own buffers
+    ) -> Result<Self::Socket<'a>, Self::Error> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .map_err(WifiError::from)?;
+        socket.connect(remote).await.map_err(WifiError::from)?;
+        Ok(UdpSocketDuple(socket))
+    }
+}
+
 impl Dns for WifiHelper {
     type Error = WifiError;
 
@@ -108,6 +131,18 @@ impl embedded_io_async::Write for TcpConnectionDuple {
     }
 }
 
+impl ConnectedUdp for UdpSocketDuple {
+    type Error = WifiError;
+
+    async fn send(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.0.send(data).await.map(|_| ()).map_err(WifiError::from)
+    }
+
+    async fn receive_into(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.recv(buffer).await.map_err(WifiError::from)
+    }
+}
+
 impl ErrorType for TcpConnectionDuple {
     type Error = WifiError;
 }
@@ -6,6 +6,7 @@
     holding buffers for the duration of a data transfer."
 )]
 
+use core_logic::{MetaInfoFile, core::magnet::MagnetLink};
 use defmt::info;
 use embassy_executor::Spawner;
 use embedded_sdmmc::VolumeManager;
@@ -24,16 +25,39 @@ async fn main(spawner: Spawner) -> ! {
     let mut bittorrenter = esp_app::setup::setup(spawner).await;
 
     let mut buf = [0u8; 1024 * 10];
-    let file_length = bittorrenter
-        .fs()
-        .put_torrent_into_buf(&mut buf)
-        .await
-        .unwrap();
+    let file_length = match bittorrenter.fs().put_torrent_into_buf(&mut buf).await {
+        Some(file_length) => file_length,
+        None => {
+            // no torrent yet, build one from the magnet link and keep it for next time
+            let mut link = [0u8; 1024];
+            let link_length = bittorrenter
+                .fs()
+                .put_magnet_into_buf(&mut link)
+                .await
+                .expect("neither a torrent nor a magnet link found");
+            let link = core::str::from_utf8(&link[..link_length]).expect("magnet link isn't utf-8");
+            let magnet = defmt::unwrap!(MagnetLink::parse(link));
+            info!("WE GOT THE MAGNET LINK: {:?}", magnet.info_hash);
+
+            let mut metadata_buf = [0u8; 1024 * 10];
+            let file_length = defmt::unwrap!(
+                bittorrenter
+                    .fetch_torrent(&magnet, &mut metadata_buf, &mut buf)
+                    .await
+            );
+            let torrent = defmt::unwrap!(MetaInfoFile::parse(&buf[..file_length]));
+            defmt::unwrap!(
+                bittorrenter
+                    .fs()
+                    .save_torrent(torrent.info.name, &buf[..file_length])
+                    .await
+            );
+            file_length
+        }
+    };
     info!("WE GOT THE FILE WITH LENGTH: {:?}", file_length);
 
-    let torrent = defmt::unwrap!(core_logic::core::metainfo::MetaInfoFile::parse(
-        &buf[..file_length]
-    ));
+    let torrent = defmt::unwrap!(MetaInfoFile::parse(&buf[..file_length]));
 
     info!("WE GOT THE TORRENT WITH: {:?}", torrent);
 
@@ -43,11 +67,20 @@ async fn main(spawner: Spawner) -> ! {
         Ok(mut downloader) => {
             info!("WE GOT A TRACKER RESPONSE: {:?}", downloader.get_peers());
 
-            match downloader.download().await {
+            // re-announces while downloading and sends `completed` at the end
+            match downloader.download(&mut rx_buf).await {
                 Ok(_) => info!("DOWNLOAD COMPLETED SUCCESSFULLY"),
                 Err(e) => info!("DOWNLOAD FAILED WITH ERROR: {:?}", e),
             }
 
+            // in case the last announce failed, then `stopped` as we're shutting down
+            if let Err(e) = downloader.announce_if_due(&mut rx_buf).await {
+                info!("ANNOUNCE FAILED WITH ERROR: {:?}", e);
+            }
+            if let Err(e) = downloader.stop(&mut rx_buf).await {
+                info!("ANNOUNCE FAILED WITH ERROR: {:?}", e);
+            }
+
             VolumeManager::close_file(
                 downloader.fs.get_volume_mgr(),
                 downloader.fs.get_open_file().unwrap(),
@@ -1,4 +1,7 @@
-use core_logic::BitTorrenter;
+use core_logic::{
+    BitTorrenter, Rng,
+    net::tls::{TlsSettings, Verification},
+};
 use defmt::info;
 use esp_hal::{clock::CpuClock, timer::timg::TimerGroup};
 
@@ -39,5 +42,21 @@ pub async fn setup(spawner: embassy_executor::Spawner) -> BitTorrenter<EspWifi,
 
     info!("Done initializing.");
 
+    // the radio is an entropy source while the wifi is on, so the TRNG is available now
+    let rng = esp_hal::rng::Trng::try_new().expect("The wifi is running");
+    // there are no CA certificates on the device yet
+    let tls = TlsSettings::new(rng, Verification::None);
+
     BitTorrenter::new(wifi, fs)
+        .with_rng(HardwareRng(esp_hal::rng::Rng::new()))
+        .with_tls(tls)
+}
+
+/// The peer id, the tracker key and the transaction ids come from the hardware RNG.
+struct HardwareRng(esp_hal::rng::Rng);
+
+impl Rng for HardwareRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.random()
+    }
 }
@@ -44,3 +44,60 @@ impl embedded_io_async::Error for TcpError {
         }
     }
 }
+
+/// Unified UDP error type for the `UdpConnector` trait.
+///
+/// Embassy-net's `UdpSocket` has separate error types for binding,
+/// sending and receiving.
+#[derive(Debug, defmt::Format)]
+pub enum UdpError {
+    /// Error while binding the socket to a local port
+    Bind(embassy_net::udp::BindError),
+    /// Error while sending a datagram
+    Send(embassy_net::udp::SendError),
+    /// Error while receiving a datagram
+    Recv(embassy_net::udp::RecvError),
+}
+
+impl Display for UdpError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            UdpError::Bind(e) => write!(f, "UDP bind error: {:?}", e),
+            UdpError::Send(e) => write!(f, "UDP send error: {:?}", e),
+            UdpError::Recv(e) => write!(f, "UDP receive error: {:?}", e),
+        }
+    }
+}
+
+impl ::core::error::Error for UdpError {}
+
+impl From<embassy_net::udp::BindError> for UdpError {
+    fn from(err: embassy_net::udp::BindError) -> Self {
+        UdpError::Bind(err)
+    }
+}
+
+impl From<embassy_net::udp::SendError> for UdpError {
+    fn from(err: embassy_net::udp::SendError) -> Self {
+        UdpError::Send(err)
+    }
+}
+
+impl From<embassy_net::udp::RecvError> for UdpError {
+    fn from(err: embassy_net::udp::RecvError) -> Self {
+        UdpError::Recv(err)
+    }
+}
+
+impl embedded_io_async::Error for UdpError {
+    fn kind(&self) -> embedded_io_async::ErrorKind {
+        match self {
+            UdpError::Bind(_) => embedded_io_async::ErrorKind::AddrNotAvailable,
+            UdpError::Send(embassy_net::udp::SendError::PacketTooLarge) => {
+                embedded_io_async::ErrorKind::InvalidInput
+            }
+            UdpError::Send(_) => embedded_io_async::ErrorKind::NotConnected,
+            UdpError::Recv(_) => embedded_io_async::ErrorKind::OutOfMemory,
+        }
+    }
+}
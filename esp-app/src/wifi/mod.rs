@@ -1,8 +1,15 @@
 use core::net::SocketAddrV4;
-use core_logic::TcpConnector;
-use embassy_net::{Stack, tcp::TcpSocket};
+use core_logic::{TcpConnector, UdpConnector};
+use embassy_net::{
+    Stack,
+    tcp::TcpSocket,
+    udp::{PacketMetadata, UdpSocket},
+};
 
-use crate::wifi::{error::TcpError, socket::EspTcpSocket};
+use crate::wifi::{
+    error::{TcpError, UdpError},
+    socket::{EspTcpSocket, EspUdpSocket},
+};
 
 pub mod dns;
 pub mod error;
@@ -10,10 +17,13 @@ mod network;
 pub(crate) mod setup;
 pub mod socket;
 
-/// WiFi network client for ESP32 that provides DNS resolution and TCP connections.
+/// How many datagrams can be queued per direction, a tracker exchange only needs one at a time.
+const UDP_PACKETS: usize = 4;
+
+/// WiFi network client for ESP32 that provides DNS resolution, TCP connections and UDP sockets.
 ///
-/// This struct only holds a reference to the
-/// embassy-net `Stack`. **It does not own TCP socket buffers.** Buffers
+/// This struct holds a reference to the embassy-net `Stack` and the metadata
+/// storage of the UDP socket. **It does not own socket buffers.** Buffers
 /// are provided by the caller (e.g., `BitTorrenter`) when establishing connections.
 ///
 /// # Example
@@ -31,6 +41,10 @@ pub struct EspWifi {
     ///
     /// Handles IP routing, TCP state machines, and the WiFi driver interface.
     stack: Stack<'static>,
+    /// Metadata of the datagrams queued in the UDP socket's receive buffer.
+    udp_rx_meta: [PacketMetadata; UDP_PACKETS],
+    /// Metadata of the datagrams queued in the UDP socket's transmit buffer.
+    udp_tx_meta: [PacketMetadata; UDP_PACKETS],
 }
 
 impl EspWifi {
@@ -38,7 +52,11 @@ impl EspWifi {
     ///
     /// The stack should already be initialized and connected to a network.
     pub fn new(stack: Stack<'static>) -> Self {
-        Self { stack }
+        Self {
+            stack,
+            udp_rx_meta: [PacketMetadata::EMPTY; UDP_PACKETS],
+            udp_tx_meta: [PacketMetadata::EMPTY; UDP_PACKETS],
+        }
     }
 
     /// Get access to the underlying network stack.
@@ -64,3 +82,26 @@ impl TcpConnector for EspWifi {
         Ok(EspTcpSocket::new(socket))
     }
 }
+
+impl UdpConnector for EspWifi {
+    type Error = UdpError;
+    type Socket<'a> = EspUdpSocket<'a>;
+
+    async fn connect_udp<'a>(
+        &'a mut self,
+        remote: SocketAddrV4,
+        rx_buffer: &'a mut [u8],
+        tx_buffer: &'a mut [u8],
+    ) -> Result<Self::Socket<'a>, Self::Error> {
+        let mut socket = UdpSocket::new(
+            self.stack,
+            &mut self.udp_rx_meta,
+            rx_buffer,
+            &mut self.udp_tx_meta,
+            tx_buffer,
+        );
+        // port 0 picks an ephemeral port
+        socket.bind(0)?;
+        Ok(EspUdpSocket::new(socket, remote))
+    }
+}
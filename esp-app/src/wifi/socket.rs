@@ -1,6 +1,8 @@
-use embassy_net::tcp::TcpSocket;
+use core::net::SocketAddrV4;
 
-use crate::wifi::error::TcpError;
+use embassy_net::{IpEndpoint, tcp::TcpSocket, udp::UdpSocket};
+
+use crate::wifi::error::{TcpError, UdpError};
 
 /// A connected TCP socket wrapper using `TcpError` for all operations.
 ///
@@ -34,3 +36,41 @@ impl<'a> embedded_io_async::Write for EspTcpSocket<'a> {
         self.0.flush().await.map_err(TcpError::from)
     }
 }
+
+/// A UDP socket that only talks to one remote, i.e. a connected socket.
+///
+/// Embassy-net's `UdpSocket` is unconnected, so the remote is kept here:
+/// datagrams are sent to it and datagrams from anyone else are dropped.
+pub struct EspUdpSocket<'a> {
+    socket: UdpSocket<'a>,
+    remote: IpEndpoint,
+}
+
+impl<'a> EspUdpSocket<'a> {
+    pub(crate) fn new(socket: UdpSocket<'a>, remote: SocketAddrV4) -> Self {
+        Self {
+            socket,
+            remote: remote.into(),
+        }
+    }
+}
+
+impl<'a> embedded_nal_async::ConnectedUdp for EspUdpSocket<'a> {
+    type Error = UdpError;
+
+    async fn send(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.socket
+            .send_to(data, self.remote)
+            .await
+            .map_err(UdpError::from)
+    }
+
+    async fn receive_into(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        loop {
+            let (len, meta) = self.socket.recv_from(buffer).await?;
+            if meta.endpoint == self.remote {
+                return Ok(len);
+            }
+        }
+    }
+}